@@ -10,19 +10,25 @@ pub struct ParsedArgs {
     pub default_config: bool,
     pub bare_repl: bool,
     pub script_path: Option<PathBuf>,
+    pub session: Option<String>,
     pub unknown_args: Vec<String>,
 }
 
 pub fn parse_args(args: &[String]) -> ParsedArgs {
     let mut parsed = ParsedArgs::default();
 
-    for arg in args {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
         match arg.as_str() {
             "-h" | "--help" => parsed.show_help = true,
             "-v" | "--version" => parsed.show_version = true,
             "--reset-config" => parsed.reset_config = true,
             "--default-config" => parsed.default_config = true,
             "--bare-repl" => parsed.bare_repl = true,
+            "--session" => match args.next() {
+                Some(name) => parsed.session = Some(name.clone()),
+                None => parsed.unknown_args.push(arg.clone()),
+            },
             _ => {
                 // If it's a file path, set script_path
                 if arg.ends_with(".rs") && parsed.script_path.is_none() {
@@ -25,6 +25,21 @@ pub fn run(mut irust: IRust) -> crate::irust::Result<()> {
             return Err("Invalid input format: missing start marker".into());
         };
         let output = irust.parse(to_eval.to_string());
+        // The raw streams, the panic and the returned error of evaluations, before the formatted
+        // output
+        if let Some(result) = irust.take_last_eval() {
+            print!(
+                "IRUST_EVAL_RESULT_START{}IRUST_EVAL_RESULT_END",
+                serde_json::json!({
+                    "status": result.status.code(),
+                    "stdout": result.stdout,
+                    "stderr": result.stderr,
+                    "run_time_ms": result.run_time.map(|time| time.as_secs_f64() * 1000.),
+                    "panic": result.panic,
+                    "returned_error": result.returned_error,
+                })
+            );
+        }
         print!("IRUST_OUTPUT_START");
         match output {
             Ok(output) => {
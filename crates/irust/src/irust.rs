@@ -14,17 +14,20 @@ pub mod options;
 mod parser;
 mod ra;
 mod script;
+mod session;
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers};
+use crossterm::style::Stylize;
 use highlight::theme::Theme;
 use history::History;
 use irust_api::{Command, GlobalVariables};
-use irust_repl::Repl;
+use irust_repl::{Executor, MainResult, Repl, ReplError};
 use options::Options;
 use printer::{buffer::Buffer, printer::Printer};
 use ra::Completer;
 use script::Script;
 
 pub use format::format_err;
+pub use session::named_session_path;
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub struct IRust {
@@ -39,22 +42,71 @@ pub struct IRust {
     history: History,
     completer: Option<Completer>,
     script_mg: Option<Box<dyn Script>>,
+    /// Session file that is saved on exit
+    session: Option<std::path::PathBuf>,
+    /// Result of the last evaluation, only kept in bare repl mode
+    last_eval: Option<irust_repl::EvalResult>,
 
     pub bare_repl: bool,
 }
 
+/// A setting of the config that can't be applied is reported, the repl keeps its default one
+fn report_setting_error(setting: &str, result: std::result::Result<(), ReplError>) {
+    if let Err(e) = result {
+        eprintln!("{}", format!("Failed to set the {setting}: {e}").red());
+    }
+}
+
 impl IRust {
     pub fn new(options: Options) -> Self {
         // Make sure to call Repl::new at the start so it can set `irust-repl` dir, which might be used by others (ScriptManager)
-        let repl = Repl::new(
-            options.toolchain,
-            options.executor,
-            options.main_result,
+        let mut repl = Repl::new(
+            options.toolchain.clone(),
+            // The executor and main result dependencies are added after the crate source is set
+            Executor::Sync,
+            MainResult::Unit,
             options.edition,
             // prelude dir
             (|| Some(crate::utils::irust_dirs::data_dir()?.join("irust")))(),
         )
         .expect("Could not create repl");
+        // Persistent mode is unix only, other platforms keep the default rebuild mode
+        report_setting_error("eval mode", repl.set_eval_mode(options.eval_mode));
+        // Pty run mode is linux only
+        report_setting_error("run mode", repl.set_run_mode(options.run_mode));
+        // Cpu time and memory limits are linux only
+        report_setting_error("limits", repl.set_limits(options.limits));
+        // Evaluations are not sandboxed if the sandbox can't be set up, the error says so
+        report_setting_error("sandbox", repl.set_sandbox(options.sandbox));
+        report_setting_error(
+            "compile profiles",
+            repl.set_compile_profiles(options.compile_profiles.clone()),
+        );
+        if let Err(e) = repl.set_crate_source(options.crate_source.clone()) {
+            report_setting_error("crate source", Err(e));
+            let _ = repl.set_crate_source(Default::default());
+        }
+        if let Err(e) = repl.set_offline(options.offline) {
+            report_setting_error("offline mode", Err(e));
+            let _ = repl.set_offline(false);
+        }
+        report_setting_error(
+            "custom executors",
+            repl.set_custom_executors(options.executors.clone()),
+        );
+        // A failed executor or main result leaves the repl with its dependency half set up
+        if let Err(e) = repl.set_executor(options.executor.clone()) {
+            report_setting_error("executor", Err(e));
+            let _ = repl.set_executor(Executor::Sync);
+        }
+        report_setting_error(
+            "custom main results",
+            repl.set_custom_main_results(options.main_results.clone()),
+        );
+        if let Err(e) = repl.set_main_result(options.main_result.clone()) {
+            report_setting_error("main result", Err(e));
+            let _ = repl.set_main_result(MainResult::Unit);
+        }
 
         let mut global_variables = GlobalVariables::new();
 
@@ -102,10 +154,17 @@ impl IRust {
             history,
             completer,
             script_mg,
+            session: None,
+            last_eval: None,
             bare_repl: false,
         }
     }
 
+    /// Result of the last evaluation, only kept in bare repl mode
+    pub fn take_last_eval(&mut self) -> Option<irust_repl::EvalResult> {
+        self.last_eval.take()
+    }
+
     fn prepare(&mut self) -> Result<()> {
         // title is optional
         let title = if let Some(title) = self.trigger_set_title_hook() {
@@ -1,20 +1,75 @@
 use crate::irust::{IRust, Result};
 use crossterm::style::Color;
+use irust_repl::BuildProgress;
+use std::io::Write;
+
+/// Cargo progress drawn over the current line while a build runs
+pub struct ProgressLine {
+    /// None for the bare repl
+    out: Option<Box<dyn Write>>,
+    shown: bool,
+}
+
+impl ProgressLine {
+    pub fn show(&mut self, progress: &BuildProgress) {
+        let Some(out) = self.out.as_mut() else {
+            return;
+        };
+        // Don't wrap, the line couldn't be cleared
+        let width = crossterm::terminal::size().map_or(80, |(width, _)| width as usize);
+        let status: String = format!(" {progress}").chars().take(width - 1).collect();
+        let _ = crossterm::queue!(
+            out,
+            crossterm::cursor::MoveToColumn(0),
+            crossterm::terminal::Clear(crossterm::terminal::ClearType::CurrentLine),
+            crossterm::style::SetForegroundColor(Color::Cyan),
+            crossterm::style::Print(status),
+            crossterm::style::ResetColor
+        );
+        let _ = out.flush();
+        self.shown = true;
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(out) = self.out.as_mut()
+            && std::mem::take(&mut self.shown)
+        {
+            let _ = crossterm::queue!(
+                out,
+                crossterm::cursor::MoveToColumn(0),
+                crossterm::terminal::Clear(crossterm::terminal::ClearType::CurrentLine)
+            );
+            let _ = out.flush();
+        }
+    }
+}
 
 impl IRust {
-    pub fn wait_add(&mut self, mut add_cmd: std::process::Child, msg: &str) -> Result<()> {
+    pub fn progress_line(&self) -> ProgressLine {
+        ProgressLine {
+            out: if self.bare_repl {
+                None
+            } else {
+                Some(Box::new(std::io::stdout()))
+            },
+            shown: false,
+        }
+    }
+
+    /// Returns cargo-add notes (exp: the dependency features)
+    ///
+    /// `msg` is the action shown while waiting (exp: `Adding`)
+    pub fn wait_add(&mut self, mut add_cmd: std::process::Child, msg: &str) -> Result<String> {
         // no art for bare repl
         if self.bare_repl {
             let res = add_cmd.wait_with_output()?;
             if !res.status.success() {
-                return Err(format!(
-                    "Failed to add dependency: {}",
-                    String::from_utf8_lossy(&res.stderr)
-                )
-                .into());
+                return Err(
+                    format!("{msg} dep failed: {}", String::from_utf8_lossy(&res.stderr)).into(),
+                );
             }
 
-            return Ok(());
+            return Ok(String::new());
         }
 
         self.printer.cursor.save_position();
@@ -22,20 +77,20 @@ impl IRust {
         self.printer.writer.raw.set_fg(Color::Cyan)?;
 
         match self.wait_add_inner(&mut add_cmd, msg) {
-            Ok(()) => {
+            Ok(status) => {
                 self.clean_art()?;
 
                 use std::io::Read;
+                let mut notes = String::new();
                 if let Some(stderr) = add_cmd.stderr.as_mut() {
-                    let mut error = String::new();
-                    stderr.read_to_string(&mut error)?;
-                    if !error.is_empty() {
-                        // show cargo-add stderr
-                        // it have useful info
-                        return Err(error.into());
-                    }
+                    stderr.read_to_string(&mut notes)?;
+                }
+                if !status.success() {
+                    return Err(notes.into());
                 }
-                Ok(())
+                // show cargo-add stderr
+                // it have useful info
+                Ok(notes)
             }
             Err(e) => {
                 self.clean_art()?;
@@ -44,9 +99,13 @@ impl IRust {
         }
     }
 
-    fn wait_add_inner(&mut self, add_cmd: &mut std::process::Child, msg: &str) -> Result<()> {
+    fn wait_add_inner(
+        &mut self,
+        add_cmd: &mut std::process::Child,
+        msg: &str,
+    ) -> Result<std::process::ExitStatus> {
         self.printer.write_at(
-            &format!(" {msg}ing dep [\\]"),
+            &format!(" {msg} dep [\\]"),
             0,
             self.printer.cursor.current_pos().1,
         )?;
@@ -55,49 +114,49 @@ impl IRust {
                 Ok(None) => {
                     self.printer.write_at(
                         "\\",
-                        msg.len() + 10,
+                        msg.len() + 7,
                         self.printer.cursor.current_pos().1,
                     )?;
                     self.printer.write_at(
                         "|",
-                        msg.len() + 10,
+                        msg.len() + 7,
                         self.printer.cursor.current_pos().1,
                     )?;
                     self.printer.write_at(
                         "/",
-                        msg.len() + 10,
+                        msg.len() + 7,
                         self.printer.cursor.current_pos().1,
                     )?;
                     self.printer.write_at(
                         "-",
-                        msg.len() + 10,
+                        msg.len() + 7,
                         self.printer.cursor.current_pos().1,
                     )?;
                     self.printer.write_at(
                         "\\",
-                        msg.len() + 10,
+                        msg.len() + 7,
                         self.printer.cursor.current_pos().1,
                     )?;
                     self.printer.write_at(
                         "|",
-                        msg.len() + 10,
+                        msg.len() + 7,
                         self.printer.cursor.current_pos().1,
                     )?;
                     self.printer.write_at(
                         "/",
-                        msg.len() + 10,
+                        msg.len() + 7,
                         self.printer.cursor.current_pos().1,
                     )?;
                     self.printer.write_at(
                         "-",
-                        msg.len() + 10,
+                        msg.len() + 7,
                         self.printer.cursor.current_pos().1,
                     )?;
                 }
                 Err(e) => {
                     return Err(e.into());
                 }
-                Ok(Some(_)) => return Ok(()),
+                Ok(Some(status)) => return Ok(status),
             }
             std::thread::sleep(std::time::Duration::from_millis(100));
         }
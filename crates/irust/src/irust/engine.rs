@@ -551,11 +551,7 @@ impl IRust {
                                 code: KeyCode::Char('d'),
                                 modifiers: KeyModifiers::CONTROL,
                                 ..
-                            }) => {
-                                if needle.is_empty() {
-                                    break;
-                                }
-                            }
+                            }) if needle.is_empty() => break,
                             _ => (),
                         }
                     }
@@ -682,6 +678,9 @@ impl IRust {
                 if !self.engine.dont_save_options {
                     self.options.save()?;
                 }
+                if let Some(session) = self.session.clone() {
+                    self.save_session(&session)?;
+                }
                 self.printer.write_newline(&self.buffer);
                 self.printer.cursor.show();
                 Ok(())
@@ -1,141 +1,554 @@
 use crate::Options;
 use crossterm::style::Color;
+use irust_repl::{
+    Comparison, Dependency, Diagnostic, DiagnosticLevel, DiagnosticSpan, EvalResult, Feature,
+    Limit, Panic, SpanOrigin,
+};
 use printer::printer::{PrintQueue, PrinterItem};
-use std::sync::OnceLock;
-
-static NO_COLOR: OnceLock<bool> = OnceLock::new();
-/// Have the top precedence
-fn no_color() -> bool {
-    *NO_COLOR.get_or_init(|| std::env::var("NO_COLOR").is_ok())
-}
-
-pub fn format_err<'a>(original_output: &'a str, show_warnings: bool, repl_name: &str) -> String {
-    const BEFORE_2021_END_TAG: &str = ": aborting due to ";
-    // Relies on --color=always
-    const ERROR_TAG: &str = "\u{1b}[0m\u{1b}[1m\u{1b}[38;5;9merror";
-    const WARNING_TAG: &str = "\u{1b}[0m\u{1b}[1m\u{1b}[33mwarning";
-
-    // These are more fragile, should be only used when NO_COLOR is on
-    const ERROR_TAG_NO_COLOR: &str = "error[";
-    const WARNING_TAG_NO_COLOR: &str = "warning: ";
-
-    let go_to_start = |output: &'a str| -> Vec<&'a str> {
-        if show_warnings {
-            output
-                .lines()
-                .skip_while(|line| !line.contains(&format!("{repl_name} v0.1.0")))
-                .skip(1)
-                .collect()
-        } else {
-            output
-                .lines()
-                .skip_while(|line| {
-                    if no_color() {
-                        !line.starts_with(ERROR_TAG_NO_COLOR)
-                    } else {
-                        !line.starts_with(ERROR_TAG)
-                    }
-                })
-                .collect()
-        }
-    };
-    let go_to_end = |output: Box<dyn Iterator<Item = &str>>| -> String {
-        if show_warnings {
-            output
-        } else {
-            Box::new(output.take_while(|line| {
-                if no_color() {
-                    !line.starts_with(WARNING_TAG_NO_COLOR)
-                } else {
-                    !line.starts_with(WARNING_TAG)
-                }
-            }))
+
+/// Render compiler diagnostics the way rustc does, errors are always shown, warnings only if
+/// `show_warnings` is set
+///
+/// If there is no error to show (exp: cargo itself failed or the program exited with an error),
+/// the raw output is shown instead
+pub fn format_err_printqueue(
+    diagnostics: &[Diagnostic],
+    output: &str,
+    show_warnings: bool,
+) -> PrintQueue {
+    let mut queue = PrintQueue::default();
+    for (text, color) in render_err(diagnostics, output, show_warnings) {
+        queue.push(PrinterItem::String(text, color));
+    }
+    queue
+}
+
+/// Same as [format_err_printqueue] but without colors
+pub fn format_err(diagnostics: &[Diagnostic], output: &str, show_warnings: bool) -> String {
+    render_err(diagnostics, output, show_warnings)
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect()
+}
+
+fn render_err(
+    diagnostics: &[Diagnostic],
+    output: &str,
+    show_warnings: bool,
+) -> Vec<(String, Color)> {
+    let shown: Vec<&Diagnostic> = diagnostics
+        .iter()
+        .filter(|diagnostic| {
+            diagnostic.is_error() || (show_warnings && diagnostic.level == DiagnosticLevel::Warning)
+        })
+        .collect();
+    if !shown.iter().any(|diagnostic| diagnostic.is_error()) {
+        let output = output.replace("IRUST_INTERNAL_STDERR_START", "\n");
+        return vec![(output.trim().to_owned(), Color::Red)];
+    }
+
+    let mut rendered = vec![];
+    for (idx, diagnostic) in shown.into_iter().enumerate() {
+        if idx != 0 {
+            rendered.push(("\n\n".into(), Color::Reset));
         }
-        .collect::<Vec<_>>()
-        .join("\n")
-    };
+        render_diagnostic(diagnostic, &mut rendered);
+    }
+    rendered
+}
 
-    let handle_error = |output: &'a str| {
-        go_to_start(output)
-            .into_iter()
-            .take_while(|line| !line.contains(BEFORE_2021_END_TAG))
-    };
-    let handle_error_2021 = |output: &'a str| {
-        go_to_start(output)
-            .into_iter()
-            .rev()
-            .skip_while(|line| !line.is_empty())
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-    };
+fn span_source(span: &DiagnosticSpan) -> String {
+    source_name(span.origin, &span.file_name)
+}
 
-    let output: Box<dyn Iterator<Item = &str>> = if original_output.contains(BEFORE_2021_END_TAG) {
-        Box::new(handle_error(original_output))
-    } else {
-        Box::new(handle_error_2021(original_output))
-    };
+/// `In` for the evaluated input, `In[n]` for earlier inputs, the file name for generated code
+fn source_name(origin: Option<SpanOrigin>, file_name: &str) -> String {
+    match origin {
+        Some(origin) if origin.current => "In".into(),
+        Some(origin) => format!("In[{}]", origin.input),
+        None => file_name.to_owned(),
+    }
+}
 
-    let formatted_error = go_to_end(output);
-    // The formatting logic is ad-hoc, there will always be a chance of failure with a rust update
-    //
-    // So we do a sanity check here, if the formatted_error is empty (which means we failed to
-    // format the output), ask the user to open a bug report with the original_output
-    let irust_version = env!("CARGO_PKG_VERSION");
-    if !formatted_error.is_empty() {
-        formatted_error
-    } else {
-        format!(
-            "IRust {irust_version}: failed to format the error output.\nThis is a bug in IRust.\nFeel free to open a bug-report at https://github.com/sigmaSd/IRust/issues/new with the next text:\n\noriginal_output:\n{original_output}"
-        )
+fn level_color(level: DiagnosticLevel) -> Color {
+    match level {
+        DiagnosticLevel::Error | DiagnosticLevel::InternalCompilerError => Color::Red,
+        DiagnosticLevel::Warning => Color::Yellow,
+        _ => Color::Cyan,
     }
 }
 
-pub fn format_err_printqueue(output: &str, show_warnings: bool, repl_name: &str) -> PrintQueue {
-    PrinterItem::String(format_err(output, show_warnings, repl_name), Color::Red).into()
+fn render_diagnostic(diagnostic: &Diagnostic, rendered: &mut Vec<(String, Color)>) {
+    const GUTTER_COLOR: Color = Color::Blue;
+    let color = level_color(diagnostic.level);
+
+    let header = match &diagnostic.code {
+        Some(code) => format!("{}[{code}]", diagnostic.level),
+        None => diagnostic.level.to_string(),
+    };
+    rendered.push((header, color));
+    rendered.push((format!(": {}", diagnostic.message), Color::Reset));
+
+    // Primary span first
+    let mut spans: Vec<_> = diagnostic.spans.iter().collect();
+    spans.sort_by_key(|span| (!span.is_primary, span.line_start));
+    let width = spans
+        .iter()
+        .map(|span| span.line_start.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let pad = " ".repeat(width);
+
+    let mut previous_location: Option<(String, usize)> = None;
+    for span in &spans {
+        let Some(line) = span.text.first() else {
+            continue;
+        };
+        // Each input (or file) gets a header, like rustc does for different files
+        let source = span_source(span);
+        match &previous_location {
+            None => rendered.push((format!("\n{pad}--> "), GUTTER_COLOR)),
+            Some((previous_source, _)) if *previous_source != source => {
+                rendered.push((format!("\n{pad}::: "), GUTTER_COLOR))
+            }
+            _ => (),
+        }
+        if previous_location
+            .as_ref()
+            .is_none_or(|(previous_source, _)| *previous_source != source)
+        {
+            rendered.push((
+                format!("{source}:{}:{}", span.line_start, span.column_start),
+                Color::Reset,
+            ));
+            rendered.push((format!("\n{pad} |"), GUTTER_COLOR));
+        }
+        // Spans on the same line share the source line
+        let location = (source, span.line_start);
+        if previous_location.as_ref() != Some(&location) {
+            rendered.push((format!("\n{:>width$} | ", span.line_start), GUTTER_COLOR));
+            rendered.push((line.clone(), Color::Reset));
+        }
+        previous_location = Some(location);
+
+        let start = span.column_start.saturating_sub(1);
+        let end = if span.line_end == span.line_start {
+            span.column_end.saturating_sub(1)
+        } else {
+            line.chars().count()
+        }
+        .max(start + 1);
+        let marker = if span.is_primary { "^" } else { "-" };
+        rendered.push((format!("\n{pad} | "), GUTTER_COLOR));
+        rendered.push((
+            format!(
+                "{}{} {}",
+                " ".repeat(start),
+                marker.repeat(end - start),
+                span.label.as_deref().unwrap_or_default()
+            )
+            .trim_end()
+            .to_owned(),
+            if span.is_primary { color } else { GUTTER_COLOR },
+        ));
+    }
+
+    for child in &diagnostic.children {
+        // Suggestions are rendered bellow
+        if child
+            .spans
+            .iter()
+            .any(|span| span.suggested_replacement.is_some())
+        {
+            continue;
+        }
+        rendered.push((format!("\n{pad} = "), GUTTER_COLOR));
+        rendered.push((format!("{}", child.level), level_color(child.level)));
+        rendered.push((format!(": {}", child.message), Color::Reset));
+    }
+    for suggestion in &diagnostic.suggestions {
+        rendered.push(("\nhelp".into(), Color::Cyan));
+        rendered.push((
+            format!(": {}: `{}`", suggestion.message, suggestion.replacement),
+            Color::Reset,
+        ));
+    }
 }
 
 pub fn format_eval_output(
     options: &Options,
-    status: std::process::ExitStatus,
-    output: String,
+    result: EvalResult,
     prompt: String,
     show_warnings: bool,
-    repl_name: &str,
     new_lines_after_output: usize,
 ) -> Option<PrintQueue> {
-    if !status.success() {
-        return Some(format_err_printqueue(&output, show_warnings, repl_name));
+    if let Some(limit) = result.limit_exceeded {
+        // Show what the program printed before it was stopped
+        let mut queue = PrintQueue::default();
+        if !result.stdout.is_empty() {
+            queue.push(PrinterItem::String(prompt, options.out_color));
+            queue.push(PrinterItem::String(result.stdout, options.eval_color));
+            queue.add_new_line(1);
+        }
+        if !result.stderr.is_empty() {
+            queue.push(PrinterItem::String(result.stderr, Color::Red));
+            queue.add_new_line(1);
+        }
+        queue.push(format_limit_exceeded(options, limit));
+        queue.add_new_line(new_lines_after_output);
+        return Some(queue);
     }
-    if output.contains("IRUST_INTERNAL_STDERR_START") {
-        let (stdout, stderr) = output.split_once("IRUST_INTERNAL_STDERR_START").unwrap();
-        let mut eval_output = PrintQueue::default();
-        eval_output.push(PrinterItem::String(prompt, options.out_color));
-        eval_output.push(PrinterItem::String(stdout.into(), options.eval_color));
-        eval_output.add_new_line(1);
-        eval_output.push(PrinterItem::String(format!("Err: {stderr}"), Color::Red));
-        eval_output.add_new_line(new_lines_after_output);
-        return Some(eval_output);
+    if let Some(panic) = &result.panic {
+        return Some(format_panic(options, &result, panic, prompt));
+    }
+    if let Some(error) = &result.returned_error {
+        return Some(format_returned_error(options, &result, error, prompt));
+    }
+    if !result.status.success() {
+        return Some(format_err_printqueue(
+            &result.diagnostics,
+            &result.output,
+            show_warnings,
+        ));
     }
 
     let mut eval_output = PrintQueue::default();
     eval_output.push(PrinterItem::String(prompt, options.out_color));
-    eval_output.push(PrinterItem::String(output, options.eval_color));
+    eval_output.push(PrinterItem::String(result.stdout, options.eval_color));
+    if !result.stderr.is_empty() {
+        eval_output.add_new_line(1);
+        eval_output.push(PrinterItem::String(
+            format!("Err: {}", result.stderr),
+            Color::Red,
+        ));
+    }
     eval_output.add_new_line(new_lines_after_output);
     Some(eval_output)
 }
 
-fn check_is_err(s: &str) -> bool {
-    !s.contains("[unoptimized + debuginfo]")
+/// The program output was already printed while it ran, so only point to where it panicked, or show
+/// its exit status if it failed otherwise
+pub fn format_relayed_eval_output(
+    options: &Options,
+    result: &EvalResult,
+    new_lines_after_output: usize,
+) -> PrintQueue {
+    let mut queue = PrintQueue::default();
+    if let Some(limit) = result.limit_exceeded {
+        queue.add_new_line(1);
+        queue.push(format_limit_exceeded(options, limit));
+    } else if let Some(location) = result
+        .panic
+        .as_ref()
+        .and_then(|panic| panic.location.as_ref())
+    {
+        let source = source_name(location.origin, &location.file_name);
+        queue.push(PrinterItem::String(" --> ".into(), Color::Blue));
+        queue.push(PrinterItem::String(
+            format!("{source}:{}:{}", location.line, location.column),
+            Color::Reset,
+        ));
+    } else if result.returned_error.is_some() {
+        // The error report is part of the program output
+        queue.push(PrinterItem::String("returned an error".into(), Color::Red));
+    } else if !result.status.success() {
+        queue.push(PrinterItem::String(
+            result.status.to_string(),
+            options.err_color,
+        ));
+    } else {
+        // Not empty, so the next prompt is printed after the program output
+        queue.push(PrinterItem::String(String::new(), options.eval_color));
+    }
+    queue.add_new_line(new_lines_after_output);
+    queue
+}
+
+/// A table with a row per compared target: its evaluation time and what it printed, or why it
+/// failed
+///
+/// Outputs that differ from the first target one are highlighted
+pub fn format_comparisons(options: &Options, comparisons: &[Comparison]) -> PrintQueue {
+    let rows: Vec<(String, String, Result<String, String>)> = comparisons
+        .iter()
+        .map(|comparison| {
+            (
+                comparison.target.to_string(),
+                format!("{:.2?}", comparison.time),
+                comparison_outcome(comparison),
+            )
+        })
+        .collect();
+    let target_width = rows
+        .iter()
+        .map(|(target, ..)| target.len())
+        .chain(["target".len()])
+        .max()
+        .unwrap_or_default();
+    let time_width = rows
+        .iter()
+        .map(|(_, time, _)| time.len())
+        .chain(["time".len()])
+        .max()
+        .unwrap_or_default();
+    let indent = " ".repeat(target_width + time_width + 4);
+
+    let mut queue = PrintQueue::default();
+    queue.push(PrinterItem::String(
+        format!("{:target_width$}  {:time_width$}  output", "target", "time"),
+        Color::Blue,
+    ));
+    queue.add_new_line(1);
+    let first = rows.first().map(|(_, _, outcome)| outcome);
+    for (target, time, outcome) in &rows {
+        queue.push(PrinterItem::String(
+            format!("{target:target_width$}  "),
+            Color::Blue,
+        ));
+        queue.push(PrinterItem::String(
+            format!("{time:time_width$}  "),
+            Color::Cyan,
+        ));
+        let (text, color) = match outcome {
+            Ok(output) if Some(outcome) == first => (output, options.eval_color),
+            Ok(output) => (output, Color::Yellow),
+            Err(failure) => (failure, options.err_color),
+        };
+        let text = text
+            .lines()
+            .collect::<Vec<_>>()
+            .join(&format!("\n{indent}"));
+        queue.push(PrinterItem::String(text, color));
+        queue.add_new_line(1);
+    }
+    queue
+}
+
+/// One line per dependency: its name, resolved version, where it comes from and enabled features
+pub fn format_dependencies(dependencies: &[Dependency]) -> PrintQueue {
+    let mut queue = PrintQueue::default();
+    if dependencies.is_empty() {
+        queue.push(PrinterItem::Str("No dependencies", Color::Blue));
+        queue.add_new_line(1);
+        return queue;
+    }
+    let name_width = dependencies
+        .iter()
+        .map(|dependency| dependency.name.len())
+        .max()
+        .unwrap_or_default();
+    for dependency in dependencies {
+        queue.push(PrinterItem::String(
+            format!("{:name_width$}  ", dependency.name),
+            Color::Blue,
+        ));
+        queue.push(PrinterItem::String(
+            dependency
+                .version
+                .clone()
+                .unwrap_or_else(|| "unresolved".to_owned()),
+            Color::Green,
+        ));
+        let source = match &dependency.path {
+            Some(path) => format!(" (path: {path})"),
+            None => format!(" ({})", dependency.requirement),
+        };
+        queue.push(PrinterItem::String(source, Color::DarkGrey));
+        if !dependency.features.is_empty() {
+            queue.push(PrinterItem::String(
+                format!(" [{}]", dependency.features.join(", ")),
+                Color::Cyan,
+            ));
+        }
+        queue.add_new_line(1);
+    }
+    queue
+}
+
+/// The variables in scope with their types, and their values if they were asked for
+pub fn format_vars(
+    bindings: &[String],
+    types: &[Option<String>],
+    values: &[Option<String>],
+) -> PrintQueue {
+    let mut queue = PrintQueue::default();
+    if bindings.is_empty() {
+        queue.push(PrinterItem::Str("No variables", Color::Blue));
+        queue.add_new_line(1);
+        return queue;
+    }
+    let name_width = bindings
+        .iter()
+        .map(|binding| binding.len())
+        .max()
+        .unwrap_or_default();
+    for (idx, binding) in bindings.iter().enumerate() {
+        queue.push(PrinterItem::String(
+            format!("{binding:name_width$}: "),
+            Color::Blue,
+        ));
+        queue.push(PrinterItem::String(
+            types
+                .get(idx)
+                .cloned()
+                .flatten()
+                .unwrap_or_else(|| "unknown".to_owned()),
+            Color::Green,
+        ));
+        if let Some(value) = values.get(idx).cloned().flatten() {
+            queue.push(PrinterItem::String(format!(" = {value}"), Color::Cyan));
+        }
+        queue.add_new_line(1);
+    }
+    queue
+}
+
+/// The features of a dependency and what they enable, the enabled ones are highlighted
+pub fn format_features(features: &[Feature]) -> PrintQueue {
+    let mut queue = PrintQueue::default();
+    if features.is_empty() {
+        queue.push(PrinterItem::Str("No features", Color::Blue));
+        queue.add_new_line(1);
+        return queue;
+    }
+    for feature in features {
+        let color = if feature.enabled {
+            Color::Green
+        } else {
+            Color::Cyan
+        };
+        queue.push(PrinterItem::String(feature.name.clone(), color));
+        if !feature.enables.is_empty() {
+            queue.push(PrinterItem::String(
+                format!(" = [{}]", feature.enables.join(", ")),
+                Color::DarkGrey,
+            ));
+        }
+        queue.add_new_line(1);
+    }
+    queue
+}
+
+/// The output of a compared evaluation, or a one line summary of why it failed
+fn comparison_outcome(comparison: &Comparison) -> Result<String, String> {
+    let result = match &comparison.result {
+        Ok(result) => result,
+        Err(e) => return Err(e.to_string().lines().next().unwrap_or_default().to_owned()),
+    };
+    if let Some(limit) = result.limit_exceeded {
+        Err(format!("Limit exceeded: {limit}"))
+    } else if let Some(panic) = &result.panic {
+        Err(format!("panicked: {}", panic.message))
+    } else if let Some(error) = &result.returned_error {
+        Err(format!(
+            "returned: {}",
+            error.lines().next().unwrap_or_default()
+        ))
+    } else if let Some(error) = result.diagnostics.iter().find(|d| d.is_error()) {
+        Err(match &error.code {
+            Some(code) => format!("error[{code}]: {}", error.message),
+            None => format!("error: {}", error.message),
+        })
+    } else if !result.status.success() {
+        Err(result.status.to_string())
+    } else if result.stderr.is_empty() {
+        Ok(result.stdout.clone())
+    } else {
+        Ok(format!("{}\nErr: {}", result.stdout, result.stderr))
+    }
+}
+
+fn format_limit_exceeded(options: &Options, limit: Limit) -> PrinterItem {
+    PrinterItem::String(format!("Limit exceeded: {limit}"), options.err_color)
+}
+
+/// Show a panic like a compiler error, pointing to the input that panicked
+fn format_panic(
+    options: &Options,
+    result: &EvalResult,
+    panic: &Panic,
+    prompt: String,
+) -> PrintQueue {
+    const GUTTER_COLOR: Color = Color::Blue;
+    let mut queue = PrintQueue::default();
+    // What the program printed before panicking
+    let stderr = result
+        .stderr
+        .find("thread '")
+        .map_or("", |idx| &result.stderr[..idx]);
+    if !result.stdout.is_empty() {
+        queue.push(PrinterItem::String(prompt, options.out_color));
+        queue.push(PrinterItem::String(
+            result.stdout.clone(),
+            options.eval_color,
+        ));
+        queue.add_new_line(1);
+    }
+    if !stderr.trim().is_empty() {
+        queue.push(PrinterItem::String(format!("Err: {stderr}"), Color::Red));
+    }
+
+    queue.push(PrinterItem::String("panicked".into(), Color::Red));
+    queue.push(PrinterItem::String(
+        format!(": {}", panic.message),
+        Color::Reset,
+    ));
+    if let Some(location) = &panic.location {
+        let source = source_name(location.origin, &location.file_name);
+        queue.push(PrinterItem::String("\n --> ".into(), GUTTER_COLOR));
+        queue.push(PrinterItem::String(
+            format!("{source}:{}:{}", location.line, location.column),
+            Color::Reset,
+        ));
+    }
+    if let Some(backtrace) = &panic.backtrace {
+        queue.push(PrinterItem::String(
+            format!("\nstack backtrace:\n{backtrace}"),
+            Color::Reset,
+        ));
+    }
+    queue.add_new_line(1);
+    queue
+}
+
+/// Show an error returned by the repl code (see `MainResult`), after what the program printed
+fn format_returned_error(
+    options: &Options,
+    result: &EvalResult,
+    error: &str,
+    prompt: String,
+) -> PrintQueue {
+    let mut queue = PrintQueue::default();
+    // What the program printed before returning
+    let stderr = result
+        .stderr
+        .rfind("Error: ")
+        .map_or("", |idx| &result.stderr[..idx]);
+    if !result.stdout.is_empty() {
+        queue.push(PrinterItem::String(prompt, options.out_color));
+        queue.push(PrinterItem::String(
+            result.stdout.clone(),
+            options.eval_color,
+        ));
+        queue.add_new_line(1);
+    }
+    if !stderr.trim().is_empty() {
+        queue.push(PrinterItem::String(format!("Err: {stderr}"), Color::Red));
+    }
+    queue.push(PrinterItem::String("returned".into(), Color::Red));
+    queue.push(PrinterItem::String(format!(": {error}"), Color::Reset));
+    queue.add_new_line(1);
+    queue
 }
 
 pub fn format_check_output(
-    output: String,
+    status: std::process::ExitStatus,
+    output: &str,
+    diagnostics: &[Diagnostic],
     show_warnings: bool,
-    repl_name: &str,
 ) -> Option<PrintQueue> {
-    if check_is_err(&output) {
-        Some(format_err_printqueue(&output, show_warnings, repl_name))
+    if !status.success() {
+        Some(format_err_printqueue(diagnostics, output, show_warnings))
     } else {
         None
     }
@@ -1,7 +1,11 @@
 use crate::irust::{IRust, Result};
 use crossterm::style::Color;
-use irust_repl::{CompileMode, DEFAULT_EVALUATOR, Edition, Executor, MainResult, ToolChain};
+use irust_repl::{
+    CompareTarget, CompileMode, CompileProfile, CrateSource, CustomExecutor, CustomMainResult,
+    DEFAULT_EVALUATOR, Edition, EvalMode, Executor, Limits, MainResult, RunMode, ToolChain,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::io::{Read, Write};
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -35,9 +39,11 @@ pub struct Options {
     pub output_prompt: String,
     pub activate_scripting: bool,
     pub executor: Executor,
+    pub executors: BTreeMap<String, CustomExecutor>,
     pub evaluator: Vec<String>,
     pub compile_time: bool,
     pub main_result: MainResult,
+    pub main_results: BTreeMap<String, CustomMainResult>,
     pub show_warnings: bool,
     pub edition: Edition,
     pub debugger: Debugger,
@@ -46,6 +52,14 @@ pub struct Options {
     pub local_server_adress: std::net::SocketAddrV4,
     pub theme: String,
     pub compile_mode: CompileMode,
+    pub compile_profiles: BTreeMap<String, CompileProfile>,
+    pub eval_mode: EvalMode,
+    pub run_mode: RunMode,
+    pub limits: Limits,
+    pub sandbox: bool,
+    pub offline: bool,
+    pub crate_source: CrateSource,
+    pub compare_targets: Vec<CompareTarget>,
     pub new_lines_after_output: usize,
 
     #[serde(skip)]
@@ -95,12 +109,56 @@ impl Default for Options {
             output_prompt: "Out: ".to_string(),
             activate_scripting: false,
             executor: Executor::Sync,
+            executors: BTreeMap::from([
+                (
+                    "smol".to_owned(),
+                    CustomExecutor {
+                        block_on: Some("smol::block_on($$)".to_owned()),
+                        dependency: vec!["smol".to_owned()],
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "tokio_current_thread".to_owned(),
+                    CustomExecutor {
+                        attribute: Some("#[tokio::main(flavor = \"current_thread\")]".to_owned()),
+                        block_on: Some(
+                            "tokio::runtime::Builder::new_current_thread().enable_all().build().expect(\"irust: failed to start tokio\").block_on($$)"
+                                .to_owned(),
+                        ),
+                        dependency: vec![
+                            "tokio".to_owned(),
+                            "--features".to_owned(),
+                            "macros rt".to_owned(),
+                        ],
+                        prelude: None,
+                    },
+                ),
+            ]),
             evaluator: DEFAULT_EVALUATOR
                 .iter()
                 .map(|part| part.to_string())
                 .collect(),
             compile_time: false,
             main_result: MainResult::Unit,
+            main_results: BTreeMap::from([
+                (
+                    "anyhow".to_owned(),
+                    CustomMainResult {
+                        ttype: "anyhow::Result<()>".to_owned(),
+                        success: "Ok(())".to_owned(),
+                        dependency: vec!["anyhow".to_owned()],
+                    },
+                ),
+                (
+                    "eyre".to_owned(),
+                    CustomMainResult {
+                        ttype: "eyre::Result<()>".to_owned(),
+                        success: "Ok(())".to_owned(),
+                        dependency: vec!["eyre".to_owned()],
+                    },
+                ),
+            ]),
             show_warnings: false,
             edition: Edition::E2021,
             debugger: Debugger::LLDB,
@@ -109,6 +167,30 @@ impl Default for Options {
             local_server_adress: "127.0.0.1:9000".parse().expect("correct"),
             theme: "default".into(),
             compile_mode: CompileMode::Debug,
+            // Debug builds without the debug info, they are quicker to compile
+            compile_profiles: BTreeMap::from([(
+                "fast".to_owned(),
+                CompileProfile {
+                    debug: Some(false),
+                    ..Default::default()
+                },
+            )]),
+            eval_mode: EvalMode::Rebuild,
+            run_mode: RunMode::Piped,
+            limits: Limits::default(),
+            sandbox: false,
+            offline: false,
+            crate_source: CrateSource::CratesIo,
+            compare_targets: vec![
+                CompareTarget {
+                    toolchain: Some(ToolChain::Stable),
+                    edition: None,
+                },
+                CompareTarget {
+                    toolchain: Some(ToolChain::Nightly),
+                    edition: None,
+                },
+            ],
             new_lines_after_output: 1,
             config_load_time: None,
         }
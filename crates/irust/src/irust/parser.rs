@@ -12,10 +12,16 @@ use crate::irust::{IRust, Result};
 use crate::utils::{copy_dir, stdout_and_stderr};
 use crate::utils::{find_workpace_root, patch_name_to};
 use crate::{
-    irust::format::{format_check_output, format_eval_output},
+    irust::format::{
+        format_check_output, format_comparisons, format_dependencies, format_eval_output,
+        format_features, format_relayed_eval_output, format_vars,
+    },
     utils::ctrlc_cancel,
 };
-use irust_repl::{CompileMode, EvalConfig, EvalResult, Executor, MainResult, ToolChain};
+use irust_repl::{
+    BuildProgress, CompareTarget, CompileMode, CrateSource, Edition, EvalCallbacks, EvalConfig,
+    EvalMode, EvalResult, Executor, Limits, MainResult, OutputChunk, RunMode, ToolChain,
+};
 use printer::printer::{PrintQueue, PrinterItem};
 
 const SUCCESS: &str = "Ok!";
@@ -58,14 +64,24 @@ impl IRust {
             }
             cmd if cmd.starts_with(":help") => self.help(buffer),
             cmd if cmd.starts_with("::") => self.run_cmd(buffer),
+            cmd if cmd.starts_with(":edition") => self.edition(buffer),
             cmd if cmd.starts_with(":edit") => self.extern_edit(buffer),
             cmd if cmd.starts_with(":add") => self.add_dep(buffer),
+            cmd if cmd.starts_with(":rm") => self.remove_dep(buffer),
+            cmd if cmd.starts_with(":update") => self.update_deps(buffer),
+            cmd if cmd.starts_with(":deps") => self.deps(),
+            cmd if cmd.starts_with(":features") => self.features(buffer),
             cmd if cmd.starts_with(":hard_load_crate") => self.hard_load_crate(buffer),
             cmd if cmd.starts_with(":hard_load") => self.hard_load(buffer),
             cmd if cmd.starts_with(":load") => self.load(buffer),
             cmd if cmd.starts_with(":reload") => self.reload(),
             cmd if cmd.starts_with(":type") => self.show_type(),
+            cmd if cmd.starts_with(":vars") => self.vars(buffer),
             cmd if cmd.starts_with(":del") => self.del(buffer),
+            cmd if cmd.starts_with(":undef") => self.undef(buffer),
+            cmd if cmd.starts_with(":undo") => self.undo(),
+            cmd if cmd.starts_with(":redo") => self.redo(),
+            cmd if cmd.starts_with(":history_repl") => Ok(self.history_repl()),
             cmd if cmd.starts_with(":dbg") => self.dbg(buffer),
             cmd if cmd.starts_with(":color") => self.color(buffer),
             cmd if cmd.starts_with(":cd") => self.cd(buffer),
@@ -82,7 +98,17 @@ impl IRust {
             cmd if cmd.starts_with(":scripts") => self.scripts(buffer),
             cmd if cmd.starts_with(":compile_time") => self.compile_time(buffer),
             cmd if cmd.starts_with(":compile_mode") => self.compile_mode(buffer),
+            cmd if cmd.starts_with(":eval_mode") => self.eval_mode(buffer),
+            cmd if cmd.starts_with(":run_mode") => self.run_mode(buffer),
+            cmd if cmd.starts_with(":limits") => self.limits(buffer),
+            cmd if cmd.starts_with(":sandbox") => self.sandbox(buffer),
+            cmd if cmd.starts_with(":vendor") => self.vendor(buffer),
+            cmd if cmd.starts_with(":compare_targets") => self.compare_targets(buffer),
+            cmd if cmd.starts_with(":compare") => self.compare(buffer),
             cmd if cmd.starts_with(":expand") => self.expand(buffer),
+            cmd if cmd.starts_with(":export") => self.export(buffer),
+            cmd if cmd.starts_with(":save") => self.save(buffer),
+            cmd if cmd.starts_with(":restore") => self.restore(buffer),
             cmd if self.options.shell_interpolate && cmd.contains("$$") => {
                 let buffer = self.shell_interpolate(buffer)?;
                 self.parse_second_order(buffer)
@@ -93,11 +119,13 @@ impl IRust {
 
     fn reset(&mut self) -> Result<PrintQueue> {
         self.repl.reset()?;
+        self.repl.commit(":reset")?;
         success!()
     }
 
     fn pop(&mut self) -> Result<PrintQueue> {
         self.repl.pop();
+        self.repl.commit(":pop")?;
         success!()
     }
 
@@ -111,13 +139,91 @@ impl IRust {
     fn del(&mut self, buffer: String) -> Result<PrintQueue> {
         if let Some(line_num) = buffer.split_whitespace().last() {
             self.repl.del(line_num)?;
+            self.repl.commit(&buffer)?;
+        }
+        success!()
+    }
+
+    fn undef(&mut self, buffer: String) -> Result<PrintQueue> {
+        let name = buffer
+            .strip_prefix(":undef")
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .ok_or("No item name specified")?;
+        self.repl.undef(name)?;
+        self.repl.commit(&buffer)?;
+        self.repl.write_to_extern()?;
+        success!()
+    }
+
+    fn undo(&mut self) -> Result<PrintQueue> {
+        let dependencies = self.dependency_files()?;
+        self.repl.undo()?;
+        self.undo_done(dependencies)
+    }
+
+    fn redo(&mut self) -> Result<PrintQueue> {
+        let dependencies = self.dependency_files()?;
+        self.repl.redo()?;
+        self.undo_done(dependencies)
+    }
+
+    /// Cargo.toml and Cargo.lock, they are restored by an undo or a redo
+    fn dependency_files(&self) -> Result<String> {
+        let paths = &self.repl.cargo.paths;
+        let lockfile =
+            std::fs::read_to_string(paths.irust_dir.join("Cargo.lock")).unwrap_or_default();
+        Ok(std::fs::read_to_string(&paths.cargo_toml_file)? + &lockfile)
+    }
+
+    /// After an undo or a redo, `dependencies` are the previous [Self::dependency_files]
+    fn undo_done(&mut self, dependencies: String) -> Result<PrintQueue> {
+        // The executor, main result and edition are part of the commits
+        self.options.executor = self.repl.executor().clone();
+        self.options.main_result = self.repl.main_result().clone();
+        self.options.edition = self.repl.edition();
+        self.repl.write_to_extern()?;
+        if self.dependency_files()? != dependencies {
+            self.dependencies_changed()?;
         }
         success!()
     }
 
+    /// The committed changes of the repl with their In[n], the undone ones can be redone
+    fn history_repl(&mut self) -> PrintQueue {
+        let mut queue = PrintQueue::default();
+        for (commit, applied) in self.repl.commits() {
+            let mut lines = commit.description.lines();
+            let mut line = match commit.input_number {
+                Some(n) => format!("In[{n}]: {}", lines.next().unwrap_or_default()),
+                None => lines.next().unwrap_or_default().to_owned(),
+            };
+            if lines.next().is_some() {
+                line.push_str(" ...");
+            }
+            if applied {
+                queue.push(PrinterItem::String(line, Color::Cyan));
+            } else {
+                queue.push(PrinterItem::String(line + " (undone)", Color::DarkGrey));
+            }
+            queue.add_new_line(1);
+        }
+        queue
+    }
+
     fn show(&mut self) -> PrintQueue {
         let code: Vec<char> = self.repl.show().chars().collect();
-        highlight(&code.into(), &self.theme)
+        let mut queue = highlight(&code.into(), &self.theme);
+        let definitions = self.repl.definitions();
+        if !definitions.is_empty() {
+            queue.push(PrinterItem::String("Definitions:".into(), Color::Blue));
+            for definition in definitions {
+                queue.add_new_line(1);
+                queue.push(PrinterItem::String(format!("  {definition}"), Color::Cyan));
+            }
+            queue.add_new_line(1);
+        }
+        queue
     }
 
     fn toolchain(&mut self, buffer: String) -> Result<PrintQueue> {
@@ -125,11 +231,63 @@ impl IRust {
 
         if let Some(toolchain) = toolchain {
             let toolchain = ToolChain::from_str(toolchain)?;
-            self.repl.set_toolchain(toolchain);
+            self.repl.set_toolchain(toolchain.clone())?;
             self.options.toolchain = toolchain;
             success!()
         } else {
-            print_queue!(self.options.toolchain.to_string(), Color::Blue)
+            let mut queue = PrintQueue::default();
+            queue.push(PrinterItem::String(
+                self.options.toolchain.to_string(),
+                Color::Blue,
+            ));
+            queue.add_new_line(1);
+            // The installed toolchains, the current one is highlighted
+            let installed = ToolChain::installed()?;
+            let current = self.options.toolchain.find_in(&installed);
+            for toolchain in &installed {
+                let color = if current == Some(toolchain.as_str()) {
+                    Color::Green
+                } else {
+                    Color::Cyan
+                };
+                queue.push(PrinterItem::String(format!("  {toolchain}"), color));
+                queue.add_new_line(1);
+            }
+            Ok(queue)
+        }
+    }
+
+    fn edition(&mut self, buffer: String) -> Result<PrintQueue> {
+        let edition = buffer.split_whitespace().nth(1);
+
+        if let Some(edition) = edition {
+            let edition = Edition::from_str(edition)?;
+            self.before_compiling_hook();
+            let mut progress = self.progress_line();
+            let errors = self.repl.set_edition(
+                edition,
+                Some(ctrlc_cancel),
+                Some(&mut |p: &BuildProgress| progress.show(p)),
+            );
+            progress.clear();
+            self.after_compiling_hook();
+            let errors = errors?;
+            self.options.edition = edition;
+            self.repl.commit(&buffer)?;
+            if errors.is_empty() {
+                return success!();
+            }
+            // The edition is kept, the user can fix or delete the statements that broke
+            let mut queue = PrintQueue::default();
+            queue.push(PrinterItem::String(
+                format!("Some statements don't compile with edition {edition}:"),
+                Color::Yellow,
+            ));
+            queue.add_new_line(1);
+            queue.append(&mut format_err_printqueue(&errors, "", false));
+            Ok(queue)
+        } else {
+            print_queue!(self.options.edition.to_string(), Color::Blue)
         }
     }
 
@@ -170,16 +328,113 @@ impl IRust {
 
         if let Some(main_result) = main_result {
             let main_result = MainResult::from_str(main_result)?;
-            self.repl.set_main_result(main_result);
+            self.repl.set_main_result(main_result.clone())?;
             self.options.main_result = main_result;
+            self.repl.commit(&buffer)?;
             success!()
         } else {
-            print_queue!(self.options.main_result.to_string(), Color::Blue)
+            let mut queue = PrintQueue::default();
+            queue.push(PrinterItem::String(
+                self.repl.main_result().to_string(),
+                Color::Blue,
+            ));
+            queue.add_new_line(1);
+            // The builtin main results and the ones from the config, the current one is highlighted
+            let builtin = [MainResult::Unit, MainResult::Result, MainResult::Option];
+            let custom = self
+                .repl
+                .custom_main_results()
+                .keys()
+                .map(|name| MainResult::Custom(name.clone()));
+            for main_result in builtin.into_iter().chain(custom) {
+                let color = if self.repl.main_result() == &main_result {
+                    Color::Green
+                } else {
+                    Color::Cyan
+                };
+                queue.push(PrinterItem::String(format!("  {main_result}"), color));
+                queue.add_new_line(1);
+            }
+            Ok(queue)
         }
     }
 
+    fn eval_mode(&mut self, buffer: String) -> Result<PrintQueue> {
+        let eval_mode = buffer.split_whitespace().nth(1);
+
+        if let Some(eval_mode) = eval_mode {
+            let eval_mode = EvalMode::from_str(eval_mode)?;
+            self.repl.set_eval_mode(eval_mode)?;
+            self.options.eval_mode = eval_mode;
+            success!()
+        } else {
+            print_queue!(self.repl.eval_mode().to_string(), Color::Blue)
+        }
+    }
+
+    fn run_mode(&mut self, buffer: String) -> Result<PrintQueue> {
+        let run_mode = buffer.split_whitespace().nth(1);
+
+        if let Some(run_mode) = run_mode {
+            let run_mode = RunMode::from_str(run_mode)?;
+            self.repl.set_run_mode(run_mode)?;
+            self.options.run_mode = run_mode;
+            success!()
+        } else {
+            print_queue!(self.repl.run_mode().to_string(), Color::Blue)
+        }
+    }
+
+    fn limits(&mut self, buffer: String) -> Result<PrintQueue> {
+        let limits = buffer.trim_start_matches(":limits").trim();
+
+        if !limits.is_empty() {
+            let limits = Limits::from_str(limits)?;
+            self.repl.set_limits(limits)?;
+            self.options.limits = limits;
+            success!()
+        } else {
+            print_queue!(self.repl.limits().to_string(), Color::Blue)
+        }
+    }
+
+    fn sandbox(&mut self, buffer: String) -> Result<PrintQueue> {
+        let buffer: Vec<&str> = buffer
+            .strip_prefix(":sandbox")
+            .expect("already checked")
+            .split_whitespace()
+            .collect();
+        let sandbox = match buffer.as_slice() {
+            [] => return print_queue!(self.repl.sandbox().to_string(), Color::Blue),
+            [on_off] => match on_off.to_lowercase().as_str() {
+                "on" => true,
+                "off" => false,
+                _ => return Err("Invalid argument (only accepts on/off)".into()),
+            },
+            _ => return Err("Invalid number of arguments".into()),
+        };
+        self.repl.set_sandbox(sandbox)?;
+        self.options.sandbox = sandbox;
+        success!()
+    }
+
+    fn vendor(&mut self, buffer: String) -> Result<PrintQueue> {
+        let buffer = buffer
+            .strip_prefix(":vendor")
+            .expect("already checked")
+            .trim();
+        let crate_source = match buffer {
+            "" => return print_queue!(self.repl.crate_source().to_string(), Color::Blue),
+            "off" | "crates-io" => CrateSource::CratesIo,
+            dir => CrateSource::Vendored(dir.into()),
+        };
+        self.repl.set_crate_source(crate_source)?;
+        self.options.crate_source = self.repl.crate_source().clone();
+        success!()
+    }
+
     fn add_dep(&mut self, buffer: String) -> Result<PrintQueue> {
-        let mut dep: Vec<String> = crate::utils::split_args(buffer);
+        let mut dep: Vec<String> = crate::utils::split_args(buffer.clone());
         dep.remove(0); //drop :add
 
         // Try to canonicalize all arguments that corresponds to an existing path
@@ -211,21 +466,97 @@ impl IRust {
         //     }
         // }
 
-        self.wait_add(self.repl.add_dep(&dep)?, "Add")?;
-        self.wait_add(self.repl.build()?, "Build")?;
+        let notes = self
+            .wait_add(self.repl.add_dep(&dep)?, "Adding")
+            .map_err(|e| self.repl.dependency_error(&e.to_string()))?;
+        self.dependencies_changed()?;
+        self.repl.commit(&buffer)?;
 
-        if self.options.check_statements {
-            self.wait_add(
-                self.repl.cargo.cargo_check(self.options.toolchain)?,
-                "Check",
-            )?;
+        let mut print_queue = PrintQueue::default();
+        if !notes.is_empty() {
+            print_queue.push(PrinterItem::String(notes, Color::Cyan));
+        }
+        print_queue.push(PrinterItem::Str(SUCCESS, Color::Blue));
+        print_queue.add_new_line(1);
+        Ok(print_queue)
+    }
+
+    fn remove_dep(&mut self, buffer: String) -> Result<PrintQueue> {
+        let dep: Vec<String> = crate::utils::split_args(buffer.clone())
+            .into_iter()
+            .skip(1)
+            .collect();
+        if dep.is_empty() {
+            return Err("No dependency specified".into());
         }
+        self.wait_add(self.repl.remove_dep(&dep)?, "Removing")?;
+        self.dependencies_changed()?;
+        self.repl.commit(&buffer)?;
+        success!()
+    }
+
+    fn update_deps(&mut self, buffer: String) -> Result<PrintQueue> {
+        // Without arguments every dependency is updated
+        let dep: Vec<String> = crate::utils::split_args(buffer.clone())
+            .into_iter()
+            .skip(1)
+            .collect();
+        let notes = self.wait_add(self.repl.update_deps(&dep)?, "Updating")?;
+        self.dependencies_changed()?;
+        self.repl.commit(&buffer)?;
+
+        let mut print_queue = PrintQueue::default();
+        if !notes.is_empty() {
+            print_queue.push(PrinterItem::String(notes, Color::Cyan));
+        }
+        print_queue.push(PrinterItem::Str(SUCCESS, Color::Blue));
+        print_queue.add_new_line(1);
+        Ok(print_queue)
+    }
+
+    /// Build the changed dependencies now so the next eval is quick, and let rust-analyzer see
+    /// them
+    fn dependencies_changed(&mut self) -> Result<()> {
+        let mut progress = self.progress_line();
+        let toolchain = self.repl.toolchain();
+        let mut show = |p: &BuildProgress| progress.show(p);
+        let built = self.repl.cargo.cargo_build_output(
+            false,
+            &CompileMode::Debug,
+            &toolchain,
+            Some(ctrlc_cancel),
+            Some(&mut show),
+        );
+        let checked = if built.is_ok() && self.options.check_statements {
+            self.repl
+                .cargo
+                .cargo_check_output(&toolchain, Some(ctrlc_cancel), Some(&mut show))
+                .map(|_| ())
+        } else {
+            Ok(())
+        };
+        progress.clear();
+        built?;
+        checked?;
 
         if let Some(ra) = self.completer.as_mut() {
             ra.rust_analyzer.reload_workspace()?;
         }
+        Ok(())
+    }
 
-        success!()
+    fn deps(&mut self) -> Result<PrintQueue> {
+        Ok(format_dependencies(&self.repl.dependencies()?))
+    }
+
+    fn features(&mut self, buffer: String) -> Result<PrintQueue> {
+        let [name] = buffer.split_whitespace().skip(1).collect::<Vec<_>>()[..] else {
+            return Err("Incorrect usage, expected `:features <crate>`".into());
+        };
+        let features = self.repl.features(name)?;
+        // cargo metadata resolves the dependencies if they weren't built yet
+        self.repl.commit(&buffer)?;
+        Ok(format_features(&features))
     }
 
     fn color(&mut self, buffer: String) -> Result<PrintQueue> {
@@ -342,16 +673,22 @@ impl IRust {
         self.repl.reset()?;
 
         // read code
-        let code = std::fs::read_to_string(path)?;
+        let code = std::fs::read_to_string(&path)?;
 
         // build the code
-        let EvalResult { output, status } = self.repl.eval_build(code.clone())?;
+        let EvalResult {
+            output,
+            status,
+            diagnostics,
+            ..
+        } = self.repl.eval_build(code.clone())?;
 
         if !status.success() {
+            self.repl.commit(format!(":load {}", path.display()))?;
             Ok(format_err_printqueue(
+                &diagnostics,
                 &output,
                 self.options.show_warnings,
-                &self.repl.cargo.name,
             ))
         } else {
             self.repl.insert(code);
@@ -360,52 +697,86 @@ impl IRust {
     }
 
     fn show_type(&mut self) -> Result<PrintQueue> {
-        // TODO
-        // We should probably use the `Any` trait instead of the current method
-        // Current method might break with compiler updates
-        // On the other hand `Any` is more limited
-
-        const TYPE_FOUND_MSG: &str = "expected `()`, found ";
-        const EMPTY_TYPE_MSG: &str = "dev [unoptimized + debuginfo]";
-
-        let variable = self
+        let expression = self
             .buffer
             .to_string()
             .trim_start_matches(":type")
+            .trim()
             .to_string();
-        let mut raw_out = String::new();
+        if expression.is_empty() {
+            return Err("No expression specified".into());
+        }
 
-        let toolchain = self.options.toolchain;
-        let get_type = format!("let _:() = {variable};");
+        // rust-analyzer is asked first, it doesn't need a build
+        let ra_type = match self.completer.as_mut() {
+            Some(ra) => ra
+                .expression_type(&mut self.repl, &expression)
+                .ok()
+                .flatten(),
+            None => None,
+        };
+        let var_type = match ra_type {
+            Some(ty) => Some(ty),
+            None => self
+                .repl
+                .expression_types(&[expression], Some(ctrlc_cancel))?
+                .pop()
+                .flatten(),
+        };
 
-        let cargo = self.repl.cargo.clone();
-        self.repl.eval_in_tmp_repl(get_type, |_| -> Result<()> {
-            let (_status, out) = cargo.cargo_build_output(false, false, toolchain)?;
-            raw_out = out;
-            Ok(())
-        })?;
+        print_queue!(
+            var_type.unwrap_or_else(|| "Unknown".to_owned()),
+            self.options.ok_color
+        )
+    }
 
-        let var_type = if raw_out.contains(TYPE_FOUND_MSG) {
-            raw_out
-                .lines()
-                // there is a case where there could be 2 found msg
-                // the second one is more detailed
-                .rev()
-                .find(|l| l.contains("found"))
-                // safe
-                .unwrap()
-                .rsplit("found ")
-                .next()
-                // safe
-                .unwrap()
-                .to_string()
-        } else if raw_out.contains(EMPTY_TYPE_MSG) {
-            "()".into()
-        } else {
-            "Unknown".into()
+    fn vars(&mut self, buffer: String) -> Result<PrintQueue> {
+        let values = match buffer.split_whitespace().skip(1).collect::<Vec<_>>()[..] {
+            [] => false,
+            ["--values"] => true,
+            _ => return Err("Incorrect usage, expected `:vars [--values]`".into()),
+        };
+        let bindings = self.repl.bindings();
+
+        // rust-analyzer is asked first, the compiler finds the types it doesn't know
+        let ra_types = match self.completer.as_mut() {
+            Some(ra) if !bindings.is_empty() => {
+                ra.variable_types(&mut self.repl).unwrap_or_default()
+            }
+            _ => vec![],
         };
+        let mut types: Vec<Option<String>> = bindings
+            .iter()
+            .map(|binding| {
+                ra_types
+                    .iter()
+                    .find(|(name, _)| name == binding)
+                    .map(|(_, ty)| ty.clone())
+                    .filter(|ty| !ty.contains("{unknown}"))
+            })
+            .collect();
+        let unknown: Vec<String> = bindings
+            .iter()
+            .zip(&types)
+            .filter(|(_, ty)| ty.is_none())
+            .map(|(binding, _)| binding.clone())
+            .collect();
+        if !unknown.is_empty() {
+            let mut found = self
+                .repl
+                .expression_types(&unknown, Some(ctrlc_cancel))?
+                .into_iter();
+            for ty in types.iter_mut().filter(|ty| ty.is_none()) {
+                *ty = found.next().flatten();
+            }
+        }
 
-        print_queue!(var_type, self.options.ok_color)
+        let values = if values && !bindings.is_empty() {
+            self.repl.binding_values(&bindings, Some(ctrlc_cancel))?
+        } else {
+            vec![]
+        };
+        Ok(format_vars(&bindings, &types, &values))
     }
 
     fn run_cmd(&mut self, buffer: String) -> Result<PrintQueue> {
@@ -463,12 +834,20 @@ impl IRust {
 
             if self.options.check_statements {
                 self.before_compiling_hook();
-                let check_result = self.repl.eval_check(buffer.clone());
+                let mut progress = self.progress_line();
+                let check_result = self.repl.eval_check(
+                    buffer.clone(),
+                    Some(ctrlc_cancel),
+                    Some(&mut |p: &BuildProgress| progress.show(p)),
+                );
+                progress.clear();
                 self.after_compiling_hook();
+                let check_result = check_result?;
                 if let Some(mut e) = format_check_output(
-                    check_result?.output,
+                    check_result.status,
+                    &check_result.output,
+                    &check_result.diagnostics,
                     self.options.show_warnings,
-                    &self.repl.cargo.name,
                 ) {
                     print_queue.append(&mut e);
                     insert_flag = false;
@@ -485,30 +864,96 @@ impl IRust {
         } else {
             let mut outputs = PrintQueue::default();
 
+            let pty =
+                self.repl.run_mode() == RunMode::Pty && self.repl.eval_mode() == EvalMode::Rebuild;
+            #[cfg(target_os = "linux")]
+            let interactive_function = if pty {
+                crate::utils::pty_ctrlc_cancel
+            } else {
+                ctrlc_cancel
+            };
+            #[cfg(not(target_os = "linux"))]
+            let interactive_function = ctrlc_cancel;
+
             self.before_compiling_hook();
+            // Print the program output as it arrives, after the output prompt
+            let output_prompt = self.get_output_prompt();
+            let (out_color, eval_color) = (self.options.out_color, self.options.eval_color);
+            let mut streamed = false;
+            // Shown until the program starts writing
+            let progress = std::cell::RefCell::new(self.progress_line());
+            let mut show_progress = |p: &BuildProgress| progress.borrow_mut().show(p);
+            let raw = &mut self.printer.writer.raw;
+            let mut print_chunk = |chunk: OutputChunk| {
+                let (output, color) = match chunk {
+                    OutputChunk::Stdout(output) => (output, eval_color),
+                    OutputChunk::Stderr(output) => (output, Color::Red),
+                };
+                if !streamed {
+                    progress.borrow_mut().clear();
+                    let _ = raw.write_with_color(&output_prompt, out_color);
+                    streamed = true;
+                }
+                let _ = raw.set_fg(color);
+                if pty {
+                    let _ = raw.write_all(output);
+                } else {
+                    // The terminal is in raw mode, the pty translates new lines by itself
+                    for (idx, line) in output.split(|byte| *byte == b'\n').enumerate() {
+                        if idx != 0 {
+                            let _ = raw.write_all(b"\r\n");
+                        }
+                        let _ = raw.write_all(line);
+                    }
+                }
+                let _ = raw.flush();
+            };
+
             let result = self.repl.eval_with_configuration(EvalConfig {
                 input: buffer,
-                interactive_function: Some(ctrlc_cancel),
+                interactive_function: Some(interactive_function),
+                // The bare repl sends the whole output at the end
+                output_function: if self.bare_repl {
+                    None
+                } else {
+                    Some(&mut print_chunk)
+                },
+                progress_function: Some(&mut show_progress),
                 color: true,
                 evaluator: &self.options.evaluator,
-                compile_mode: self.options.compile_mode,
+                compile_mode: self.options.compile_mode.clone(),
             });
+            progress.borrow_mut().clear();
             self.after_compiling_hook();
-            let EvalResult { output, status } = result?;
+            if pty {
+                // In case the program was killed before restoring the terminal
+                let _ = crossterm::execute!(
+                    std::io::stdout(),
+                    crossterm::terminal::LeaveAlternateScreen,
+                    crossterm::event::DisableMouseCapture
+                );
+            }
+            let result = result?;
 
             // Save output if it was a success
-            if status.success() {
-                self.global_variables.set_last_output(output.clone());
+            if result.status.success() {
+                self.global_variables.set_last_output(result.output.clone());
+            }
+            if self.bare_repl {
+                self.last_eval = Some(result.clone());
             }
 
-            let output_prompt = self.get_output_prompt();
-            if let Some(mut eval_output) = format_eval_output(
+            if streamed {
+                outputs.append(&mut format_relayed_eval_output(
+                    &self.options,
+                    &result,
+                    self.options.new_lines_after_output,
+                ));
+            } else if let Some(mut eval_output) = format_eval_output(
                 &self.options,
-                status,
-                output,
+                result,
                 output_prompt,
                 self.options.show_warnings,
-                &self.repl.cargo.name,
                 self.options.new_lines_after_output,
             ) {
                 outputs.append(&mut eval_output);
@@ -534,11 +979,19 @@ impl IRust {
     }
 
     pub fn sync(&mut self) -> Result<PrintQueue> {
+        self.sync_from_extern(":sync")
+    }
+
+    /// Load the repl code written by an external editor, `command` is committed with it
+    fn sync_from_extern(&mut self, command: &str) -> Result<PrintQueue> {
         match self.repl.update_from_extern_main_file() {
-            Ok(_) => success!(),
+            Ok(_) => {
+                self.repl.commit(command)?;
+                success!()
+            }
             Err(e) => {
                 self.repl.reset()?;
-                Err(e)
+                Err(e.into())
             }
         }
     }
@@ -584,7 +1037,7 @@ impl IRust {
             .spawn()?
             .wait()?;
 
-        self.sync()
+        self.sync_from_extern(&buffer)
     }
 
     fn irust(&mut self) -> Result<PrintQueue> {
@@ -626,17 +1079,18 @@ impl IRust {
     }
 
     fn time(&mut self, buffer: String) -> Result<PrintQueue> {
-        self.inner_time(buffer, ":time", false)
+        let compile_mode = self.options.compile_mode.clone();
+        self.inner_time(buffer, ":time", compile_mode)
     }
     fn time_release(&mut self, buffer: String) -> Result<PrintQueue> {
-        self.inner_time(buffer, ":time_release", true)
+        self.inner_time(buffer, ":time_release", CompileMode::Release)
     }
 
     fn inner_time(
         &mut self,
         buffer: String,
         pattern: &'static str,
-        release: bool,
+        compile_mode: CompileMode,
     ) -> Result<PrintQueue> {
         let fnn = buffer
             .split_once(pattern)
@@ -656,27 +1110,33 @@ impl IRust {
         ",
         );
 
-        let toolchain = self.options.toolchain;
-        let mut raw_out = String::new();
-        let mut status = None;
-
+        let toolchain = self.options.toolchain.clone();
+        let limits = self.repl.limits();
+        let main_result = self.repl.main_result_definition().clone();
         let cargo = self.repl.cargo.clone();
-        self.repl.eval_in_tmp_repl(time, |_| -> Result<()> {
-            let (s, out) = cargo.cargo_run(true, release, toolchain, Some(ctrlc_cancel))?;
-            raw_out = out;
-            status = Some(s);
-            Ok(())
-        })?;
+        let result = self
+            .repl
+            .eval_in_tmp_repl(time, |_| -> Result<EvalResult> {
+                Ok(cargo.cargo_run(
+                    true,
+                    &compile_mode,
+                    &toolchain,
+                    RunMode::Piped,
+                    limits,
+                    &main_result,
+                    EvalCallbacks {
+                        interactive_function: Some(ctrlc_cancel),
+                        ..Default::default()
+                    },
+                )?)
+            })?;
 
         let output_prompt = self.get_output_prompt();
-        // safe unwrap
         Ok(format_eval_output(
             &self.options,
-            status.unwrap(),
-            raw_out,
+            result,
             output_prompt,
             self.options.show_warnings,
-            &self.repl.cargo.name,
             self.options.new_lines_after_output,
         )
         .ok_or("failed to bench function")?)
@@ -685,12 +1145,14 @@ impl IRust {
     fn bench(&mut self) -> Result<PrintQueue> {
         //make sure we have the latest changes in main.rs
         self.repl.write()?;
-        let out = self
-            .repl
-            .cargo
-            .cargo_bench(self.options.toolchain)?
-            .trim()
-            .to_owned();
+        let mut progress = self.progress_line();
+        let out = self.repl.cargo.cargo_bench(
+            &self.options.toolchain,
+            Some(ctrlc_cancel),
+            Some(&mut |p: &BuildProgress| progress.show(p)),
+        );
+        progress.clear();
+        let out = out?.trim().to_owned();
 
         print_queue!(out, self.options.eval_color)
     }
@@ -701,23 +1163,107 @@ impl IRust {
             return Err("No function specified".into());
         }
 
-        let asm = self
-            .repl
-            .with_lib(|| self.repl.cargo.cargo_asm(fnn, self.options.toolchain))??;
+        // with_lib only takes a Fn
+        let progress = std::cell::RefCell::new(self.progress_line());
+        let asm = self.repl.with_lib(|| {
+            self.repl.cargo.cargo_asm(
+                fnn,
+                &self.options.toolchain,
+                Some(ctrlc_cancel),
+                Some(&mut |p: &BuildProgress| progress.borrow_mut().show(p)),
+            )
+        });
+        progress.borrow_mut().clear();
+        let asm = asm??;
 
         print_queue!(asm, self.options.eval_color)
     }
 
+    fn compare(&mut self, buffer: String) -> Result<PrintQueue> {
+        let input = buffer
+            .strip_prefix(":compare")
+            .expect("already checked")
+            .trim();
+        if input.is_empty() {
+            return Err("No expression specified".into());
+        }
+        if self.options.compare_targets.is_empty() {
+            return Err("No compare targets, set them with `:compare_targets`".into());
+        }
+
+        let mut progress = self.progress_line();
+        let comparisons = self.repl.compare(
+            &self.options.compare_targets,
+            EvalConfig {
+                input,
+                interactive_function: Some(ctrlc_cancel),
+                output_function: None,
+                progress_function: Some(&mut |p: &BuildProgress| progress.show(p)),
+                color: false,
+                evaluator: &self.options.evaluator,
+                compile_mode: self.options.compile_mode.clone(),
+            },
+        );
+        progress.clear();
+
+        Ok(format_comparisons(&self.options, &comparisons))
+    }
+
+    fn compare_targets(&mut self, buffer: String) -> Result<PrintQueue> {
+        let targets = buffer
+            .strip_prefix(":compare_targets")
+            .expect("already checked")
+            .split_whitespace()
+            .map(CompareTarget::from_str)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        if !targets.is_empty() {
+            self.options.compare_targets = targets;
+            success!()
+        } else {
+            let targets: Vec<String> = self
+                .options
+                .compare_targets
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            print_queue!(targets.join(" "), Color::Blue)
+        }
+    }
+
     fn executor(&mut self, buffer: String) -> Result<PrintQueue> {
         let executor = buffer.split_whitespace().nth(1);
         if let Some(executor) = executor {
             let executor = Executor::from_str(executor.trim())?;
-            self.repl.set_executor(executor)?;
+            self.repl.set_executor(executor.clone())?;
             // save executor
             self.options.executor = executor;
+            self.repl.commit(&buffer)?;
             success!()
         } else {
-            print_queue!(self.options.executor.to_string(), Color::Blue)
+            let mut queue = PrintQueue::default();
+            queue.push(PrinterItem::String(
+                self.repl.executor().to_string(),
+                Color::Blue,
+            ));
+            queue.add_new_line(1);
+            // The builtin executors and the ones from the config, the current one is highlighted
+            let builtin = [Executor::Sync, Executor::Tokio, Executor::AsyncStd];
+            let custom = self
+                .repl
+                .custom_executors()
+                .keys()
+                .map(|name| Executor::Custom(name.clone()));
+            for executor in builtin.into_iter().chain(custom) {
+                let color = if self.repl.executor() == &executor {
+                    Color::Green
+                } else {
+                    Color::Cyan
+                };
+                queue.push(PrinterItem::String(format!("  {executor}"), color));
+                queue.add_new_line(1);
+            }
+            Ok(queue)
         }
     }
 
@@ -884,10 +1430,35 @@ impl IRust {
             .trim();
 
         if mode.is_empty() {
-            return print_queue!(self.options.compile_mode.to_string(), Color::Blue);
+            let mut queue = PrintQueue::default();
+            queue.push(PrinterItem::String(
+                self.options.compile_mode.to_string(),
+                Color::Blue,
+            ));
+            queue.add_new_line(1);
+            // The profiles from the config, the current one is highlighted
+            for name in self.repl.compile_profiles().keys() {
+                let color = if self.options.compile_mode == CompileMode::Profile(name.clone()) {
+                    Color::Green
+                } else {
+                    Color::Cyan
+                };
+                queue.push(PrinterItem::String(format!("  {name}"), color));
+                queue.add_new_line(1);
+            }
+            return Ok(queue);
         }
 
-        self.options.compile_mode = CompileMode::from_str(mode)?;
+        let compile_mode = CompileMode::from_str(mode)?;
+        if let CompileMode::Profile(name) = &compile_mode
+            && !self.repl.compile_profiles().contains_key(name)
+        {
+            return Err(format!(
+                "Unknown compile profile: `{name}`, see `compile_profiles` in the config"
+            )
+            .into());
+        }
+        self.options.compile_mode = compile_mode;
         success!()
     }
 
@@ -919,7 +1490,13 @@ impl IRust {
 
         let cargo = self.repl.cargo.clone();
         self.repl.eval_in_tmp_repl(expression, |_| -> Result<()> {
-            let (status, _out) = cargo.cargo_build_output(true, false, self.options.toolchain)?;
+            let (status, _out, _) = cargo.cargo_build_output(
+                true,
+                &CompileMode::Debug,
+                &self.options.toolchain,
+                Some(ctrlc_cancel),
+                None,
+            )?;
             if !status.success() {
                 return Err("Failed to execute expression".into());
             }
@@ -1034,17 +1611,54 @@ impl IRust {
             let r = self.repl.with_lib(|| {
                 self.repl
                     .cargo
-                    .cargo_expand(Some(fnn), self.options.toolchain)
+                    .cargo_expand(Some(fnn), &self.options.toolchain)
             })??;
             print_queue!(r, Color::White)
         } else {
             print_queue!(
-                self.repl.cargo.cargo_expand(None, self.options.toolchain)?,
+                self.repl
+                    .cargo
+                    .cargo_expand(None, &self.options.toolchain)?,
                 Color::White
             )
         }
     }
 
+    fn save(&mut self, buffer: String) -> Result<PrintQueue> {
+        let path = buffer
+            .strip_prefix(":save")
+            .expect("already checked")
+            .trim();
+        if path.is_empty() {
+            return Err("No path specified".into());
+        }
+        self.save_session(std::path::Path::new(path))?;
+        success!()
+    }
+
+    fn restore(&mut self, buffer: String) -> Result<PrintQueue> {
+        let path = buffer
+            .strip_prefix(":restore")
+            .expect("already checked")
+            .trim();
+        if path.is_empty() {
+            return Err("No path specified".into());
+        }
+        self.restore_session(std::path::Path::new(path))?;
+        success!()
+    }
+
+    fn export(&mut self, buffer: String) -> Result<PrintQueue> {
+        let mut args: Vec<&str> = buffer.split_whitespace().skip(1).collect();
+        let lib = args.contains(&"--lib");
+        args.retain(|arg| *arg != "--lib");
+        let [dir] = args[..] else {
+            return Err("Incorrect usage, expected `:export <dir> [--lib]`".into());
+        };
+        self.repl.export(std::path::Path::new(dir), lib)?;
+        success!()
+    }
+
     fn exit(&mut self) -> Result<PrintQueue> {
         self.exit_flag = true;
         Ok(PrintQueue::default())
@@ -23,7 +23,7 @@ pub struct Completer {
     // suggestions: (Name, definition)
     suggestions: Vec<(String, String)>,
     suggestion_idx: usize,
-    cmds: [String; 31],
+    cmds: [String; 51],
     update_lock: bool,
     pub active_suggestion: Option<String>,
 }
@@ -43,14 +43,24 @@ impl Completer {
             "quit".to_string(),
             "edit".to_string(),
             "add".to_string(),
+            "rm".to_string(),
+            "update".to_string(),
+            "deps".to_string(),
+            "features".to_string(),
             "load".to_string(),
             "reload".to_string(),
             "type".to_string(),
             "del".to_string(),
+            "undef".to_string(),
+            "undo".to_string(),
+            "redo".to_string(),
+            "history_repl".to_string(),
+            "vars".to_string(),
             "dbg".to_string(),
             "cd".to_string(),
             "color".to_string(),
             "toolchain".to_string(),
+            "edition".to_string(),
             "theme".to_string(),
             "main_result".to_string(),
             "check_statements".to_string(),
@@ -64,6 +74,16 @@ impl Completer {
             "scripts".to_string(),
             "compile_time".to_string(),
             "compile_mode".to_string(),
+            "eval_mode".to_string(),
+            "run_mode".to_string(),
+            "limits".to_string(),
+            "sandbox".to_string(),
+            "vendor".to_string(),
+            "compare".to_string(),
+            "compare_targets".to_string(),
+            "save".to_string(),
+            "restore".to_string(),
+            "export".to_string(),
             "clear".to_string(),
         ];
 
@@ -181,7 +201,7 @@ impl Completer {
             // Auto complete rust code
             let ra = self;
 
-            ra.cursor.0 = repl.lines_count() + StringTools::new_lines_count(&buffer);
+            ra.cursor.0 = repl.insert_position(&buffer) + 1 + StringTools::new_lines_count(&buffer);
 
             ra.cursor.1 = 0;
             for c in buffer.chars() {
@@ -379,6 +399,44 @@ impl Completer {
         Ok(())
     }
 
+    /// The types of the variables in scope at the repl cursor, as inferred by rust-analyzer
+    pub fn variable_types(&mut self, repl: &mut Repl) -> Result<Vec<(String, String)>> {
+        // An empty line at the cursor to complete from
+        let line = repl.insert_position(" ");
+        let ra = self;
+        repl.eval_in_tmp_repl(" ".to_owned(), move |repl| -> Result<_> {
+            ra.rust_analyzer
+                .document_did_change(&repl.cargo.paths.main_file, repl.body())?;
+            ra.rust_analyzer
+                .document_variables(&repl.cargo.paths.main_file, (line, 1))
+        })
+    }
+
+    /// The type of `expression` evaluated at the repl cursor, as inferred by rust-analyzer
+    ///
+    /// Like the hover, the type isn't fully qualified (exp: `HashMap<u8, String>`)
+    pub fn expression_type(&mut self, repl: &mut Repl, expression: &str) -> Result<Option<String>> {
+        // The hover of a variable shows its type: `let irust_type: Vec<i32>`
+        const BINDING: &str = "let irust_type";
+
+        let input = format!("{BINDING} = {expression};");
+        let line = repl.insert_position(&input);
+        let ra = self;
+        let hover = repl.eval_in_tmp_repl(input, move |repl| -> Result<_> {
+            ra.rust_analyzer
+                .document_did_change(&repl.cargo.paths.main_file, repl.body())?;
+            ra.rust_analyzer
+                .document_hover(&repl.cargo.paths.main_file, (line, "let ".len()))
+        })?;
+        Ok(hover.and_then(|hover| {
+            hover
+                .lines()
+                .find_map(|line| line.trim().strip_prefix(BINDING)?.strip_prefix(": "))
+                .filter(|ty| !ty.contains("{unknown}"))
+                .map(ToOwned::to_owned)
+        }))
+    }
+
     pub fn lock_ra_update(&mut self) -> Result<()> {
         self.update_lock = true;
         Ok(())
@@ -5,7 +5,6 @@ use std::io::{BufRead, Read};
 use std::process::{Child, ChildStdin, ChildStdout};
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
-use std::time::Duration;
 use std::{
     io::BufReader,
     path::Path,
@@ -134,12 +133,82 @@ impl RustAnalyzer {
     pub fn document_completion(
         &mut self,
         uri: &Path,
-        (line, character): (usize, usize),
+        position: (usize, usize),
     ) -> Result<Vec<String>> {
+        Ok(self
+            .completion_items(uri, position)?
+            .iter()
+            .filter_map(|item| item.get("filterText"))
+            .map(|item| item.to_string())
+            // remove quotes
+            .map(|item| item[1..item.len() - 1].to_owned())
+            .collect())
+    }
+
+    /// The local variables in scope at the position with their types, from the completions
+    pub fn document_variables(
+        &mut self,
+        uri: &Path,
+        position: (usize, usize),
+    ) -> Result<Vec<(String, String)>> {
+        // https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#completionItemKind
+        const VARIABLE_KIND: u64 = 6;
+
+        Ok(self
+            .completion_items(uri, position)?
+            .iter()
+            .filter(|item| item.get("kind").and_then(Value::as_u64) == Some(VARIABLE_KIND))
+            .filter_map(|item| {
+                Some((
+                    item.get("label")?.as_str()?.to_owned(),
+                    item.get("detail")?.as_str()?.to_owned(),
+                ))
+            })
+            .collect())
+    }
+
+    /// The hover text at the position, it's markdown
+    pub fn document_hover(
+        &mut self,
+        uri: &Path,
+        (line, character): (usize, usize),
+    ) -> Result<Option<String>> {
+        let id = ID.fetch_add(1, Ordering::SeqCst);
+        let hover_request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": {
+                    "uri": format!("file://{}",uri.display()),
+                },
+                "position": {
+                    "line": line,
+                    "character": character
+                },
+            },
+        });
+        send_request(&mut self.stdin, &hover_request)?;
+
+        let hover_response = self.response_to(id)?;
+        Ok(hover_response
+            .get("result")
+            .and_then(|result| result.get("contents"))
+            .and_then(|contents| contents.get("value"))
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned))
+    }
+
+    fn completion_items(
+        &mut self,
+        uri: &Path,
+        (line, character): (usize, usize),
+    ) -> Result<Vec<Value>> {
         // Send a "textDocument/completion" request to the language server
+        let id = ID.fetch_add(1, Ordering::SeqCst);
         let completion_request = json!({
             "jsonrpc": "2.0",
-            "id":  ID.fetch_add(1, Ordering::SeqCst),
+            "id": id,
             "method": "textDocument/completion",
             "params": {
                 "textDocument": {
@@ -153,30 +222,38 @@ impl RustAnalyzer {
         });
         send_request(&mut self.stdin, &completion_request)?;
 
-        let completion_response = loop {
-            let completion_response = read_response(&mut self.stdout)?;
-            if completion_response.get("result").is_some() {
-                break completion_response;
-            }
-            // NOTE: we block until we get a completion
-            std::thread::sleep(Duration::from_millis(100));
-        };
+        // NOTE: we block until we get a completion
+        let completion_response = self.response_to(id)?;
         if let Some(result) = completion_response.get("result")
             && let Some(items) = result.get("items")
         {
             return Ok(items
                 .as_array()
                 .ok_or("ra items is not an array")?
-                .iter()
-                .filter_map(|item| item.get("filterText"))
-                .map(|item| item.to_string())
-                // remove quotes
-                .map(|item| item[1..item.len() - 1].to_owned())
-                .collect());
+                .to_owned());
         }
 
         Ok(vec![])
     }
+
+    /// The response to the request `id`, the other messages (notifications, responses to older
+    /// requests) are skipped
+    fn response_to(&mut self, id: usize) -> Result<Value> {
+        loop {
+            let response = read_response(&mut self.stdout)?;
+            if response.get("id").and_then(Value::as_u64) != Some(id as u64) {
+                continue;
+            }
+            if let Some(error) = response.get("error") {
+                let message = error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown error");
+                return Err(format!("rust-analyzer error: {message}").into());
+            }
+            return Ok(response);
+        }
+    }
 }
 
 fn send_request(stdin: &mut std::process::ChildStdin, request: &Value) -> Result<()> {
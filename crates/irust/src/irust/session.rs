@@ -0,0 +1,88 @@
+use super::{IRust, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+/// Bump this when the session file format changes in an incompatible way
+const SESSION_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct SessionFile {
+    version: u32,
+    evaluator: Vec<String>,
+    repl: irust_repl::Session,
+}
+
+/// Path of a named session, named sessions live in the irust data directory
+///
+/// The name must be a plain file name, so the session can't be read or written elsewhere
+pub fn named_session_path(name: &str) -> Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    let plain = matches!(components.next(), Some(Component::Normal(c)) if c == name)
+        && components.next().is_none()
+        && !name.contains(['/', '\\']);
+    if !plain {
+        return Err(format!("Invalid session name `{name}`, it must be a plain file name").into());
+    }
+    let sessions_dir = crate::utils::irust_dirs::data_dir()
+        .ok_or("Could not find the data directory to store sessions")?
+        .join("irust")
+        .join("sessions");
+    // Ignore directory exists error
+    let _ = std::fs::create_dir_all(&sessions_dir);
+    Ok(sessions_dir.join(format!("{name}.toml")))
+}
+
+impl IRust {
+    /// Save the session on exit
+    pub fn set_session(&mut self, path: PathBuf) {
+        self.session = Some(path);
+    }
+
+    pub fn save_session(&self, path: &Path) -> Result<()> {
+        let session = SessionFile {
+            version: SESSION_VERSION,
+            evaluator: self.options.evaluator.clone(),
+            repl: self.repl.session()?,
+        };
+        std::fs::write(path, toml::to_string(&session)?)?;
+        Ok(())
+    }
+
+    pub fn restore_session(&mut self, path: &Path) -> Result<()> {
+        let session = std::fs::read_to_string(path)?;
+        let version = toml::from_str::<toml::Table>(&session)?
+            .get("version")
+            .and_then(toml::Value::as_integer)
+            .ok_or("Session file is missing its version")?;
+        if version != SESSION_VERSION as i64 {
+            return Err(format!(
+                "Unsupported session version {version}, expected {SESSION_VERSION}"
+            )
+            .into());
+        }
+        let session: SessionFile = toml::from_str(&session)?;
+        if session.evaluator.len() != 2 {
+            return Err("Session evaluator requires two parts".into());
+        }
+
+        // The options are only changed once the repl accepted the session, so they stay in sync
+        self.repl.restore_session(session.repl)?;
+        self.options.toolchain = self.repl.toolchain();
+        self.options.executor = self.repl.executor().clone();
+        self.options.main_result = self.repl.main_result().clone();
+        self.options.edition = self.repl.edition();
+        self.options.evaluator = session.evaluator;
+        Ok(())
+    }
+}
+
+#[test]
+fn session_names() {
+    for name in ["", ".", "..", "../../foo", "/tmp/foo", "a/b", "a\\b", "./a"] {
+        assert!(named_session_path(name).is_err(), "{name}");
+    }
+    if crate::utils::irust_dirs::data_dir().is_some() {
+        let path = named_session_path("work").unwrap();
+        assert!(path.ends_with("irust/sessions/work.toml"));
+    }
+}
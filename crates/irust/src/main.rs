@@ -25,7 +25,7 @@ version: {}
 config file is in {}
 
 USAGE:
-    irust [FLAGS] [path_to_rust_file]
+    irust [FLAGS] [OPTIONS] [path_to_rust_file]
 
 FLAGS:
     -h, --help            Show this help message and exit
@@ -34,6 +34,9 @@ FLAGS:
     --default-config      Use the default configuration for this run (it will not be saved)
     --bare-repl           Start IRust in bare REPL mode
 
+OPTIONS:
+    --session <name>      Restore the named session if it exists, and save it on exit
+
 POSITIONAL ARGUMENTS:
     path_to_rust_file     Start IRust with the file loaded in the REPL
 ",
@@ -66,6 +69,8 @@ POSITIONAL ARGUMENTS:
                 let result = repl.eval_with_configuration(EvalConfig {
                     input,
                     interactive_function: None,
+                    output_function: None,
+                    progress_function: None,
                     color: true,
                     evaluator: &*DEFAULT_EVALUATOR,
                     compile_mode: CompileMode::Debug,
@@ -78,7 +83,7 @@ POSITIONAL ARGUMENTS:
                     } else {
                         println!(
                             "{}",
-                            irust::format_err(&result.output, false, &repl.cargo.name)
+                            irust::format_err(&result.diagnostics, &result.output, false)
                         );
                     }
                     exit(0)
@@ -115,6 +120,25 @@ POSITIONAL ARGUMENTS:
         IRust::new(options)
     };
 
+    // Resume the named session, it is created on exit if it doesn't exist yet
+    if let Some(name) = parsed.session.as_deref() {
+        match irust::named_session_path(name) {
+            Ok(path) => {
+                if path.exists()
+                    && let Err(e) = irust.restore_session(&path)
+                {
+                    eprintln!("{}", format!("Failed to restore session: {e}").red());
+                    exit(1);
+                }
+                irust.set_session(path);
+            }
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                exit(1);
+            }
+        }
+    }
+
     // If a script path was provided try to load it
     if let Some(script) = parsed.script_path.clone() {
         // Ignore if it fails
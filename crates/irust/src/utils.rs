@@ -158,45 +158,29 @@ impl StringTools {
         for character in s.chars() {
             // safe unwraps ahead
             match character {
-                '(' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'(').unwrap() += 1;
-                    }
+                '(' if !quote && !double_quote => {
+                    *braces.get_mut(&'(').unwrap() += 1;
                 }
-                ')' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'(').unwrap() -= 1;
-                    }
+                ')' if !quote && !double_quote => {
+                    *braces.get_mut(&'(').unwrap() -= 1;
                 }
-                '[' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'[').unwrap() += 1;
-                    }
+                '[' if !quote && !double_quote => {
+                    *braces.get_mut(&'[').unwrap() += 1;
                 }
-                ']' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'[').unwrap() -= 1;
-                    }
+                ']' if !quote && !double_quote => {
+                    *braces.get_mut(&'[').unwrap() -= 1;
                 }
-                '{' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'{').unwrap() += 1;
-                    }
+                '{' if !quote && !double_quote => {
+                    *braces.get_mut(&'{').unwrap() += 1;
                 }
-                '}' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'{').unwrap() -= 1;
-                    }
+                '}' if !quote && !double_quote => {
+                    *braces.get_mut(&'{').unwrap() -= 1;
                 }
-                '"' => {
-                    if previous_char != '\\' {
-                        double_quote = !double_quote;
-                    }
+                '"' if previous_char != '\\' => {
+                    double_quote = !double_quote;
                 }
-                '\'' => {
-                    if previous_char != '\\' {
-                        quote = !quote;
-                    }
+                '\'' if previous_char != '\\' => {
+                    quote = !quote;
                 }
                 _ => (),
             }
@@ -262,49 +246,120 @@ fn _balanced_quotes(s: &str) -> bool {
     s.match_indices(['"', '\'']).count().is_multiple_of(2)
 }
 
-pub fn ctrlc_cancel(process: &mut std::process::Child) -> Result<()> {
-    use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
+pub fn ctrlc_cancel(
+    process: &mut std::process::Child,
+) -> std::result::Result<(), irust_repl::ReplError> {
+    relay_input(process, b"\n")
+}
+
+/// [ctrlc_cancel] for a program running in a pseudo-terminal, enter is sent as `\r` like a real
+/// terminal in raw mode would
+#[cfg(target_os = "linux")]
+pub fn pty_ctrlc_cancel(
+    process: &mut std::process::Child,
+) -> std::result::Result<(), irust_repl::ReplError> {
+    relay_input(process, b"\r")
+}
+
+/// Kill the process on ctrl-c, forward other keys to its stdin
+fn relay_input(
+    process: &mut std::process::Child,
+    enter: &[u8],
+) -> std::result::Result<(), irust_repl::ReplError> {
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+    use std::io::Write;
     // Running a command as Command::new().output takes at minimum 1ms
     // So Polling should take a similar order of magnitude
     if let Ok(event) = crossterm::event::poll(std::time::Duration::from_millis(1))
         && event
         && let Ok(event) = crossterm::event::read()
     {
-        match event {
+        let input = match event {
             Event::Key(KeyEvent {
                 kind: KeyEventKind::Release,
                 ..
-            }) => (),
+            }) => return Ok(()),
             Event::Key(KeyEvent {
                 code: KeyCode::Char('c'),
-                modifiers: crossterm::event::KeyModifiers::CONTROL,
+                modifiers: KeyModifiers::CONTROL,
                 ..
             }) => {
                 process.kill()?;
-                return Err("Cancelled!".into());
-            }
-            Event::Key(KeyEvent {
-                code: KeyCode::Char(a),
-                ..
-            }) => {
-                use std::io::Write;
-                // Ignore write errors (process might have ended)
-                let _ = process.stdin.as_mut().unwrap().write_all(&[a as u8]);
+                return Err(irust_repl::ReplError::Cancelled);
             }
-            Event::Key(KeyEvent {
-                code: KeyCode::Enter,
-                ..
-            }) => {
-                use std::io::Write;
-                // Ignore write errors (process might have ended)
-                let _ = process.stdin.as_mut().unwrap().write_all(b"\n");
+            Event::Key(key) => key_to_bytes(key, enter),
+            Event::Paste(text) => Some(text.into_bytes()),
+            #[cfg(target_os = "linux")]
+            Event::Resize(width, height) => {
+                if let Some(stdin) = &process.stdin {
+                    resize_pty(stdin, width, height);
+                }
+                None
             }
-            _ => (),
+            _ => None,
+        };
+        if let Some(input) = input
+            && let Some(stdin) = process.stdin.as_mut()
+        {
+            // Ignore write errors (process might have ended)
+            let _ = stdin.write_all(&input);
         }
     }
     Ok(())
 }
 
+/// The bytes a terminal sends for a key press
+fn key_to_bytes(key: crossterm::event::KeyEvent, enter: &[u8]) -> Option<Vec<u8>> {
+    use crossterm::event::{KeyCode, KeyModifiers};
+    let mut bytes = match key.code {
+        KeyCode::Char(c)
+            if key.modifiers.contains(KeyModifiers::CONTROL) && c.is_ascii_alphabetic() =>
+        {
+            vec![c.to_ascii_lowercase() as u8 & 0x1f]
+        }
+        KeyCode::Char(c) => c.to_string().into_bytes(),
+        KeyCode::Enter => enter.to_vec(),
+        KeyCode::Tab => b"\t".to_vec(),
+        KeyCode::BackTab => b"\x1b[Z".to_vec(),
+        KeyCode::Backspace => b"\x7f".to_vec(),
+        KeyCode::Esc => b"\x1b".to_vec(),
+        KeyCode::Up => b"\x1b[A".to_vec(),
+        KeyCode::Down => b"\x1b[B".to_vec(),
+        KeyCode::Right => b"\x1b[C".to_vec(),
+        KeyCode::Left => b"\x1b[D".to_vec(),
+        KeyCode::Home => b"\x1b[H".to_vec(),
+        KeyCode::End => b"\x1b[F".to_vec(),
+        KeyCode::Insert => b"\x1b[2~".to_vec(),
+        KeyCode::Delete => b"\x1b[3~".to_vec(),
+        KeyCode::PageUp => b"\x1b[5~".to_vec(),
+        KeyCode::PageDown => b"\x1b[6~".to_vec(),
+        KeyCode::F(n @ 1..=4) => vec![0x1b, b'O', b'P' + n - 1],
+        KeyCode::F(5) => b"\x1b[15~".to_vec(),
+        KeyCode::F(n @ 6..=10) => format!("\x1b[{}~", n + 11).into_bytes(),
+        KeyCode::F(n @ 11..=12) => format!("\x1b[{}~", n + 12).into_bytes(),
+        _ => return None,
+    };
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        bytes.insert(0, 0x1b);
+    }
+    Some(bytes)
+}
+
+/// Tell the program its terminal was resized
+#[cfg(target_os = "linux")]
+fn resize_pty(pty: &std::process::ChildStdin, width: u16, height: u16) {
+    use std::os::fd::AsRawFd;
+    let size = libc::winsize {
+        ws_row: height,
+        ws_col: width,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // Ignore errors, stdin is a pipe in the piped run mode
+    // SAFETY: size is a valid winsize
+    unsafe { libc::ioctl(pty.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+}
+
 pub fn find_workpace_root(metadata: String) -> Option<String> {
     let start = metadata.find("workspace_root")? + 17;
     let end = metadata[start..].find('"')?;
@@ -30,10 +30,10 @@ pub fn log_to_file(message: &str) -> Result<()> {
 macro_rules! log {
     ($($arg:tt)*) => {{
         use std::fmt::Write as FmtWrite;
-        if crate::log::ACTIVE_LOGGER.load(std::sync::atomic::Ordering::Relaxed) {
+        if $crate::log::ACTIVE_LOGGER.load(std::sync::atomic::Ordering::Relaxed) {
             let mut message = String::new();
             write!(&mut message, $($arg)*).unwrap();
-            crate::log::log_to_file(&message).unwrap();
+            $crate::log::log_to_file(&message).unwrap();
         }
     }};
 }
@@ -1,10 +1,9 @@
-use irust_repl::{DEFAULT_EVALUATOR, EvalConfig, EvalResult, Repl};
+use irust_repl::{
+    DEFAULT_EVALUATOR, Diagnostic, DiagnosticLevel, EvalConfig, EvalResult, Repl, ReplError,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Deserializer;
-use std::{
-    io::{self, Read},
-    sync::OnceLock,
-};
+use std::io::{self, Read};
 
 mod log;
 use log::init_log;
@@ -96,11 +95,16 @@ fn execute(repl: &mut Repl, code: String) -> Result<()> {
             .trim();
     }
     if code.ends_with(';') || is_a_statement(code) {
-        let EvalResult { output, status } = repl.eval_check(code.to_owned())?;
+        let EvalResult {
+            output,
+            status,
+            diagnostics,
+            ..
+        } = repl.eval_check(code.to_owned(), None, None)?;
         if !status.success() {
             let output = serde_json::to_string(&Action::Eval {
                 // NOTE: make show warnings configurable
-                value: format_err(&output, false, &repl.cargo.name),
+                value: format_err(&diagnostics, &output, false),
                 mime_type: MimeType::PlainText,
             })?;
             println!("{output}");
@@ -136,34 +140,46 @@ fn execute(repl: &mut Repl, code: String) -> Result<()> {
         }
         log!("Dependencies added");
         // start building the dependencies as soon as possible
-        repl.cargo.cargo_build(repl.toolchain())?;
+        repl.cargo.cargo_build(&repl.toolchain())?;
 
         let output = serde_json::to_string(&Action::AddDependencyEnd)?;
         println!("{output}");
         return Ok(());
     } else {
         // eval here
-        let EvalResult {
-            output: value,
-            status,
-        } = repl.eval_with_configuration(EvalConfig {
-            input: code,
-            interactive_function: None,
-            color: true,
-            evaluator: &*DEFAULT_EVALUATOR,
-            compile_mode: irust_repl::CompileMode::Debug,
-        })?;
+        let result = repl
+            .eval_with_configuration(EvalConfig {
+                input: code,
+                interactive_function: None,
+                output_function: None,
+                progress_function: None,
+                color: true,
+                evaluator: &*DEFAULT_EVALUATOR,
+                compile_mode: irust_repl::CompileMode::Debug,
+            })?
+            .into_result();
 
         // It errored, format the error and send it
-        if !status.success() {
+        let send_error = |error: String| -> Result<()> {
             let output = serde_json::to_string(&Action::Eval {
-                // NOTE: make show warnings configurable
-                value: format_err(&value, false, &repl.cargo.name),
+                value: error,
                 mime_type: MimeType::PlainText,
             })?;
             println!("{output}");
-            return Ok(());
-        }
+            Ok(())
+        };
+        let value = match result {
+            Ok(EvalResult { output, .. }) => output,
+            // NOTE: make show warnings configurable
+            Err(ReplError::Compile {
+                diagnostics,
+                output,
+            }) => return send_error(format_err(&diagnostics, &output, false)),
+            Err(ReplError::Run { output, .. }) => {
+                return send_error(format_err(&[], &output, false));
+            }
+            Err(e) => return Err(e.into()),
+        };
 
         // EVCXR
         if value.starts_with("EVCXR_BEGIN_CONTENT") {
@@ -222,88 +238,17 @@ pub fn is_a_statement(buffer_trimmed: &str) -> bool {
     }
 }
 
-static NO_COLOR: OnceLock<bool> = OnceLock::new();
-/// Have the top precedence
-fn no_color() -> bool {
-    *NO_COLOR.get_or_init(|| std::env::var("NO_COLOR").is_ok())
-}
-pub fn format_err<'a>(original_output: &'a str, show_warnings: bool, repl_name: &str) -> String {
-    const BEFORE_2021_END_TAG: &str = ": aborting due to ";
-    // Relies on --color=always
-    const ERROR_TAG: &str = "\u{1b}[0m\u{1b}[1m\u{1b}[38;5;9merror";
-    const WARNING_TAG: &str = "\u{1b}[0m\u{1b}[1m\u{1b}[33mwarning";
-
-    // These are more fragile, should be only used when NO_COLOR is on
-    const ERROR_TAG_NO_COLOR: &str = "error[";
-    const WARNING_TAG_NO_COLOR: &str = "warning: ";
-
-    let go_to_start = |output: &'a str| -> Vec<&'a str> {
-        if show_warnings {
-            output
-                .lines()
-                .skip_while(|line| !line.contains(&format!("{repl_name} v0.1.0")))
-                .skip(1)
-                .collect()
-        } else {
-            output
-                .lines()
-                .skip_while(|line| {
-                    if no_color() {
-                        !line.starts_with(ERROR_TAG_NO_COLOR)
-                    } else {
-                        !line.starts_with(ERROR_TAG)
-                    }
-                })
-                .collect()
-        }
-    };
-    let go_to_end = |output: Box<dyn Iterator<Item = &str>>| -> String {
-        if show_warnings {
-            output
-        } else {
-            Box::new(output.take_while(|line| {
-                if no_color() {
-                    !line.starts_with(WARNING_TAG_NO_COLOR)
-                } else {
-                    !line.starts_with(WARNING_TAG)
-                }
-            }))
-        }
-        .collect::<Vec<_>>()
-        .join("\n")
-    };
-
-    let handle_error = |output: &'a str| {
-        go_to_start(output)
-            .into_iter()
-            .take_while(|line| !line.contains(BEFORE_2021_END_TAG))
-    };
-    let handle_error_2021 = |output: &'a str| {
-        go_to_start(output)
-            .into_iter()
-            .rev()
-            .skip_while(|line| !line.is_empty())
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-    };
-
-    let output: Box<dyn Iterator<Item = &str>> = if original_output.contains(BEFORE_2021_END_TAG) {
-        Box::new(handle_error(original_output))
-    } else {
-        Box::new(handle_error_2021(original_output))
-    };
-
-    let formatted_error = go_to_end(output);
-    // The formatting logic is ad-hoc, there will always be a chance of failure with a rust update
-    //
-    // So we do a sanity check here, if the formatted_error is empty (which means we failed to
-    // format the output), ask the user to open a bug report with the original_output
-    if !formatted_error.is_empty() {
+/// Use the compiler own rendering of the diagnostics, falls back to the raw output if there
+/// is no error diagnostic (exp: cargo failed)
+pub fn format_err(diagnostics: &[Diagnostic], output: &str, show_warnings: bool) -> String {
+    let formatted_error = diagnostics
+        .iter()
+        .filter(|d| d.is_error() || (show_warnings && d.level == DiagnosticLevel::Warning))
+        .filter_map(|d| d.rendered.as_deref())
+        .collect::<String>();
+    if diagnostics.iter().any(|d| d.is_error()) && !formatted_error.is_empty() {
         formatted_error
     } else {
-        format!(
-            "IRust: failed to format the error output.\nThis is a bug in IRust.\nFeel free to open a bug-report at https://github.com/sigmaSd/IRust/issues/new with the next text:\n\noriginal_output:\n{original_output}"
-        )
+        output.replace("IRUST_INTERNAL_STDERR_START", "")
     }
 }
@@ -1,15 +1,21 @@
 use super::Edition;
 use crate::Result;
 use crate::{
-    ToolChain,
+    BuildProgress, CompileMode, CompileProfile, CrateSource, CustomMainResult, Diagnostic,
+    EvalCallbacks, EvalResult, Limits, ReplError, RunMode, ToolChain,
+    compile_profile::BuildProfile,
+    limits::LimitWatch,
+    progress::{self, ProgressParser},
     utils::{ProcessUtils, stdout_and_stderr},
 };
+use std::collections::BTreeMap;
 use std::io;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::process::{Command, ExitStatus};
 use std::sync::OnceLock;
+use std::time::Instant;
 use std::{fs, process};
 
 static NO_COLOR: OnceLock<bool> = OnceLock::new();
@@ -18,22 +24,32 @@ fn no_color() -> bool {
     *NO_COLOR.get_or_init(|| std::env::var("NO_COLOR").is_ok())
 }
 
-const WRITE_LIB_LIMIT: &str = concat!(
-    "\nAlso your code in this repl session needs to only consist of top level statements",
-    "\nSo if you have a `let a = 4;` it will not work",
-    "\nUse :reset to reset the repl in that case"
-);
-
 #[derive(Debug, Clone)]
 pub struct Cargo {
     pub name: String,
     pub paths: CargoPaths,
+    /// Run the compiled programs in a sandbox without network and with a read-only filesystem
+    /// (linux only, see [crate::Repl::set_sandbox])
+    pub sandbox: bool,
+    /// The compile profiles that [CompileMode::Profile] refers to (see
+    /// [crate::Repl::set_compile_profiles])
+    pub profiles: BTreeMap<String, CompileProfile>,
+    /// Never use the network (see [crate::Repl::set_offline])
+    pub offline: bool,
+    pub crate_source: CrateSource,
 }
 impl Default for Cargo {
     fn default() -> Self {
         let name = "irust_host_repl_".to_string() + &uuid::Uuid::new_v4().simple().to_string();
         let paths = CargoPaths::new(&name);
-        Self { name, paths }
+        Self {
+            name,
+            paths,
+            sandbox: false,
+            profiles: BTreeMap::new(),
+            offline: false,
+            crate_source: CrateSource::CratesIo,
+        }
     }
 }
 
@@ -50,6 +66,15 @@ pub struct CargoPaths {
     pub lib_file: PathBuf,
     pub exe_path: PathBuf,
     pub release_exe_path: PathBuf,
+    pub persistent_dir: PathBuf,
+    pub host_dir: PathBuf,
+    pub snippet_dir: PathBuf,
+    pub snippet_lib_path: PathBuf,
+    pub release_snippet_lib_path: PathBuf,
+    /// The only writable directory of sandboxed programs
+    pub sandbox_dir: PathBuf,
+    /// Cargo config of the repl project (crate source and offline mode)
+    pub cargo_config_file: PathBuf,
 }
 
 impl CargoPaths {
@@ -94,6 +119,20 @@ impl CargoPaths {
         } else {
             irust_target_dir.join(format!("release/{}", &name))
         };
+        // Packages used by the persistent eval mode, they live inside the repl project
+        // and share its target directory
+        let persistent_dir = irust_dir.join("persistent");
+        let host_dir = persistent_dir.join("host");
+        let snippet_dir = persistent_dir.join("snippet");
+        let snippet_lib = format!(
+            "{}{name}_snippet{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        );
+        let snippet_lib_path = irust_target_dir.join("debug").join(&snippet_lib);
+        let release_snippet_lib_path = irust_target_dir.join("release").join(&snippet_lib);
+        let sandbox_dir = irust_dir.join("sandbox");
+        let cargo_config_file = irust_dir.join(".cargo").join("config.toml");
 
         Self {
             tmp_dir,
@@ -106,24 +145,65 @@ impl CargoPaths {
             lib_file,
             exe_path,
             release_exe_path,
+            persistent_dir,
+            host_dir,
+            snippet_dir,
+            snippet_lib_path,
+            release_snippet_lib_path,
+            sandbox_dir,
+            cargo_config_file,
             common_root,
         }
     }
 }
 impl Cargo {
+    /// Another repl project with the same settings
+    pub fn fresh(&self) -> Self {
+        Self {
+            sandbox: self.sandbox,
+            profiles: self.profiles.clone(),
+            offline: self.offline,
+            crate_source: self.crate_source.clone(),
+            ..Self::default()
+        }
+    }
+
     pub fn cargo_new(&self, edition: Edition) -> std::result::Result<(), io::Error> {
         // Ignore directory exists error
         let _ = std::fs::create_dir_all(&self.paths.irust_src_dir);
         self.clean_cargo_toml(edition)?;
         self.clean_files()?;
+        self.write_cargo_config()?;
 
         Ok(())
     }
 
+    /// Cargo picks up the config of the repl project when it runs inside of it, the commands
+    /// that run elsewhere pass it explicitly (see [Cargo::cargo_add])
+    pub fn write_cargo_config(&self) -> io::Result<()> {
+        let mut config = self.crate_source.cargo_config().unwrap_or_default();
+        if self.offline {
+            config += "\n[net]\noffline = true\n";
+        }
+        if config.is_empty() {
+            return match fs::remove_file(&self.paths.cargo_config_file) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        fs::create_dir_all(self.paths.irust_dir.join(".cargo"))?;
+        fs::write(
+            &self.paths.cargo_config_file,
+            format!("# Written by IRust, see the offline and crate_source options\n{config}"),
+        )
+    }
+
+    /// Create the library if it doesn't exist, otherwise only make sure it uses `edition`
     pub fn cargo_new_lib_simple(
         &self,
         path: &Path,
         name: &'static str,
+        edition: Edition,
     ) -> std::result::Result<(), io::Error> {
         let lib_path = path.join(name);
         let _ = std::fs::create_dir_all(lib_path.join("src"));
@@ -138,66 +218,125 @@ impl Cargo {
 [package]
 name = \"{name}\"
 version = \"0.1.0\"
-edition = \"2021\""
+edition = \"{edition}\""
         );
         create_if_not_exist(lib_path.join("src/lib.rs"), "")?;
         create_if_not_exist(lib_path.join("Cargo.toml"), &cargo_toml)?;
+        set_manifest_edition(&lib_path.join("Cargo.toml"), edition)?;
 
         Ok(())
     }
 
+    /// `main_result` is what the repl code returns, see [EvalResult::returned_error]
+    #[allow(clippy::too_many_arguments)]
     pub fn cargo_run(
         &self,
         color: bool,
-        release: bool,
-        toolchain: ToolChain,
-        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
-    ) -> Result<(ExitStatus, String)> {
-        let (status, output) = self.cargo_build_output(color, release, toolchain)?;
+        compile_mode: &CompileMode,
+        toolchain: &ToolChain,
+        run_mode: RunMode,
+        limits: Limits,
+        main_result: &CustomMainResult,
+        callbacks: EvalCallbacks,
+    ) -> Result<EvalResult> {
+        let EvalCallbacks {
+            interactive_function,
+            output_function,
+            progress_function,
+        } = callbacks;
+        let (status, output, diagnostics) = self.cargo_build_output(
+            color,
+            compile_mode,
+            toolchain,
+            interactive_function,
+            progress_function,
+        )?;
 
         if !status.success() {
-            Ok((status, output))
+            Ok((status, output, diagnostics).into())
         } else {
             // Run the exexcutable directly instead of cargo run
             // This allows to run it without modifying the current working directory
             // example: std::process::Commmand::new("pwd") will output the expected path instead of `/tmp/irust_host_repl`
-            if !release {
-                Ok((
-                    status,
-                    stdout_and_stderr(
-                        std::process::Command::new(&self.paths.exe_path)
-                            .stdin(Stdio::piped())
-                            .stdout(Stdio::piped())
-                            .stderr(Stdio::piped())
-                            .spawn()?
-                            .interactive_output(interactive_function)?,
-                    ),
-                ))
-            } else {
-                Ok((
-                    status,
-                    stdout_and_stderr(
-                        std::process::Command::new(&self.paths.release_exe_path)
-                            .stdin(Stdio::piped())
-                            .stdout(Stdio::piped())
-                            .stderr(Stdio::piped())
-                            .spawn()?
-                            .interactive_output(interactive_function)?,
-                    ),
-                ))
+            let exe_path = self.build_profile(compile_mode)?.out_dir.join(
+                self.paths
+                    .exe_path
+                    .file_name()
+                    .expect("the executable path has a file name"),
+            );
+            let mut command = std::process::Command::new(exe_path);
+            limits.apply(&mut command);
+            #[cfg(target_os = "linux")]
+            if self.sandbox {
+                crate::sandbox::apply(&mut command, &self.paths)?;
+            }
+            let start = Instant::now();
+            let mut limit_watch = LimitWatch::new(limits);
+            let (output, panic_in_stdout) = match run_mode {
+                #[cfg(target_os = "linux")]
+                RunMode::Pty => (
+                    crate::pty::run(
+                        command,
+                        interactive_function,
+                        output_function,
+                        &mut limit_watch,
+                    )?,
+                    // The panic message is in the same stream as the program output
+                    true,
+                ),
+                // Repl::set_run_mode only accepts pty on linux
+                _ => {
+                    let output = command
+                        .stdin(Stdio::piped())
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::piped())
+                        .spawn()?
+                        .interactive_output(
+                            interactive_function,
+                            output_function,
+                            Some(&mut limit_watch),
+                        )?;
+                    (output, false)
+                }
+            };
+            #[cfg(target_os = "linux")]
+            if self.sandbox && crate::sandbox::violation(output.status) {
+                return Err(crate::sandbox::violation_error(
+                    &output.stdout,
+                    &output.stderr,
+                ));
+            }
+            let limit_exceeded =
+                limit_watch.exceeded(output.status, &output.stdout, &output.stderr);
+            // The run status, so a panic is reported as a failure
+            let mut result =
+                EvalResult::from_run(output, start.elapsed(), diagnostics, main_result);
+            if panic_in_stdout && !result.status.success() {
+                result.panic = crate::Panic::from_stderr(&result.stdout);
+                result.returned_error = crate::main_result::returned_error(
+                    main_result,
+                    result.status,
+                    result.panic.is_some(),
+                    &result.stdout,
+                );
             }
+            result.limit_exceeded = limit_exceeded;
+            Ok(result)
         }
     }
 
     pub fn cargo_add(&self, dep: &[String]) -> io::Result<std::process::Child> {
-        Command::new("cargo")
-            .arg("add")
-            .args(dep)
-            .args([
-                "--manifest-path",
-                &self.paths.cargo_toml_file.display().to_string(),
-            ])
-            .stdout(std::process::Stdio::null())
+        let mut cmd = Command::new("cargo");
+        cmd.arg("add").args(dep).args([
+            "--manifest-path",
+            &self.paths.cargo_toml_file.display().to_string(),
+        ]);
+        // It runs in the current directory so relative paths work, but the repl project config
+        // still applies
+        if self.paths.cargo_config_file.exists() {
+            cmd.arg("--config").arg(&self.paths.cargo_config_file);
+        }
+        cmd.stdout(std::process::Stdio::null())
             .stderr(std::process::Stdio::piped())
             .spawn()
     }
@@ -207,74 +346,169 @@ edition = \"2021\""
             .append(true)
             .open(&self.paths.cargo_toml_file)?;
 
-        let path = if !cfg!(windows) {
-            path.display().to_string()
-        } else {
-            path.display().to_string().replace('\\', "\\\\")
-        };
-
         writeln!(
             f,
             "
 [dependencies]
-{name} = {{ path = \"{path}\" }}
-"
+{}
+",
+            path_dependency(&path, name)
         )
     }
 
+    /// Replace the manifest with a clean one followed by `tables` (exp: `[dependencies]`)
+    pub fn restore_manifest(&self, edition: Edition, tables: &str) -> io::Result<()> {
+        self.clean_cargo_toml(edition)?;
+        let mut f = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.paths.cargo_toml_file)?;
+        writeln!(f, "\n\n{tables}")
+    }
+
     pub fn cargo_add_sync(&self, dep: &[String]) -> Result<()> {
-        let process = Command::new("cargo")
+        let output = Command::new("cargo")
             .current_dir(&self.paths.irust_dir)
             .arg("add")
             .args(dep)
             .stdout(std::process::Stdio::null())
             .stderr(std::process::Stdio::piped())
             .spawn()?
-            .wait()?;
-        if process.success() {
+            .wait_with_output()?;
+        if output.status.success() {
             Ok(())
         } else {
-            Err(format!("Failed to add dependency: {:?}", &dep).into())
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(ReplError::Dependency(
+                self.unavailable_crate(&stderr)
+                    .unwrap_or_else(|| format!("Failed to add dependency: {:?}", &dep)),
+            ))
         }
     }
 
+    /// A clearer error than cargo's when it failed because a crate can't be had without the
+    /// network (offline mode or crates.io replaced)
+    pub(crate) fn unavailable_crate(&self, cargo_stderr: &str) -> Option<String> {
+        let location = match &self.crate_source {
+            CrateSource::CratesIo if self.offline => {
+                "offline, only the crates already downloaded can be used".to_owned()
+            }
+            CrateSource::CratesIo => return None,
+            CrateSource::Vendored(dir) => format!("in the vendored crates of {}", dir.display()),
+            CrateSource::LocalRegistry(dir) => {
+                format!("in the local registry {}", dir.display())
+            }
+            CrateSource::Mirror(url) => format!("in the mirror {url}"),
+        };
+        let name = ["the crate `", "no matching package named `"]
+            .iter()
+            .find_map(|prefix| {
+                let start = cargo_stderr.find(prefix)? + prefix.len();
+                let len = cargo_stderr[start..].find('`')?;
+                Some(&cargo_stderr[start..start + len])
+            })?;
+        Some(format!("`{name}` is not available {location}"))
+    }
+
     pub fn cargo_rm_sync(&self, dep: &str) -> Result<()> {
         // Ignore error if dependency doesn't exist
-        Command::new("cargo-rm")
+        self.cargo_remove(&[dep.to_owned()])?.wait()?;
+        Ok(())
+    }
+
+    pub fn cargo_remove(&self, dep: &[String]) -> io::Result<std::process::Child> {
+        Command::new("cargo")
             .current_dir(&self.paths.irust_dir)
-            .arg("rm")
-            .arg(dep)
+            .arg("remove")
+            .args(dep)
             .stdout(std::process::Stdio::null())
             .stderr(std::process::Stdio::piped())
-            .spawn()?
-            .wait()?;
-        Ok(())
+            .spawn()
+    }
+
+    /// Update the given dependencies in the lockfile, or all of them if none is given
+    pub fn cargo_update(
+        &self,
+        toolchain: &ToolChain,
+        dep: &[String],
+    ) -> io::Result<std::process::Child> {
+        let mut cmd = Command::new("cargo");
+        self.cargo_common(&mut cmd, "update", toolchain, &self.debug_profile());
+        for dep in dep {
+            cmd.args(["--package", dep]);
+        }
+        cmd.stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+    }
+
+    /// `cargo metadata` json output, the versions and features come from the lockfile
+    pub(crate) fn cargo_metadata(&self, toolchain: &ToolChain) -> Result<String> {
+        let mut cmd = Command::new("cargo");
+        let output = self
+            .cargo_common(&mut cmd, "metadata", toolchain, &self.debug_profile())
+            .args(["--format-version", "1"])
+            .output()?;
+        if !output.status.success() {
+            return Err(ReplError::Dependency(format!(
+                "cargo metadata failed:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// How cargo is invoked for `compile_mode`, a profile that doesn't exist is an error
+    pub(crate) fn build_profile(&self, compile_mode: &CompileMode) -> Result<BuildProfile> {
+        match compile_mode {
+            CompileMode::Debug => Ok(self.debug_profile()),
+            CompileMode::Release => Ok(BuildProfile::builtin(true, &self.paths.irust_target_dir)),
+            CompileMode::Profile(name) => Ok(self
+                .profiles
+                .get(name)
+                .ok_or_else(|| ReplError::UnknownSetting {
+                    setting: "compile profile",
+                    name: name.clone(),
+                })?
+                .build_profile(name, &self.paths.irust_target_dir)),
+        }
+    }
+
+    fn debug_profile(&self) -> BuildProfile {
+        BuildProfile::builtin(false, &self.paths.irust_target_dir)
     }
 
     // The difference in env flags makes cargo recompiles again!!!
     // => make  sure all build env flags are the same
     // Or even better dont use any
+    // The flags of a profile are always the same, and the profiles that change them have their own
+    // target directory
     fn cargo_common<'a>(
         &self,
         cargo: &'a mut process::Command,
         cmd: &str,
-        toolchain: ToolChain,
+        toolchain: &ToolChain,
+        profile: &BuildProfile,
     ) -> &'a mut Command {
-        match toolchain {
+        let cargo = match toolchain {
             ToolChain::Default => cargo,
             _ => cargo.arg(toolchain.as_arg()),
         }
         .arg(cmd)
-        .env("CARGO_TARGET_DIR", &self.paths.irust_target_dir)
-        .current_dir(&self.paths.irust_dir)
+        .args(&profile.args)
+        .env("CARGO_TARGET_DIR", &profile.target_dir)
+        .current_dir(&self.paths.irust_dir);
+        if let Some(rustflags) = &profile.rustflags {
+            cargo.env("RUSTFLAGS", rustflags);
+        }
+        cargo
     }
 
     pub fn cargo_check(
         &self,
-        toolchain: ToolChain,
+        toolchain: &ToolChain,
     ) -> std::result::Result<std::process::Child, io::Error> {
         let mut cmd = Command::new("cargo");
-        self.cargo_common(&mut cmd, "check", toolchain)
+        self.cargo_common(&mut cmd, "check", toolchain, &self.debug_profile())
             .stdout(std::process::Stdio::null())
             .stderr(std::process::Stdio::null())
             .spawn()
@@ -282,25 +516,29 @@ edition = \"2021\""
 
     pub fn cargo_check_output(
         &self,
-        toolchain: ToolChain,
-    ) -> std::result::Result<(ExitStatus, String), io::Error> {
-        let color = if no_color() { "never" } else { "always" };
+        toolchain: &ToolChain,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+        progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<(ExitStatus, String, Vec<Diagnostic>)> {
+        let color = !no_color();
         let mut cmd = Command::new("cargo");
-        let output = self
-            .cargo_common(&mut cmd, "check", toolchain)
-            .args(["--color", color])
-            .output()?;
-
-        let status = output.status;
-        Ok((status, stdout_and_stderr(output)))
+        let output = cargo_output(
+            self.cargo_common(&mut cmd, "check", toolchain, &self.debug_profile())
+                .args(["--color", if color { "always" } else { "never" }])
+                .args(["--message-format", message_format(color)]),
+            interactive_function,
+            progress_function,
+        )?;
+
+        Ok(compiler_output(output))
     }
 
     pub fn cargo_build(
         &self,
-        toolchain: ToolChain,
+        toolchain: &ToolChain,
     ) -> std::result::Result<std::process::Child, io::Error> {
         let mut cmd = Command::new("cargo");
-        self.cargo_common(&mut cmd, "build", toolchain)
+        self.cargo_common(&mut cmd, "build", toolchain, &self.debug_profile())
             .stdout(std::process::Stdio::null())
             .stderr(std::process::Stdio::null())
             .spawn()
@@ -309,41 +547,86 @@ edition = \"2021\""
     pub fn cargo_build_output(
         &self,
         color: bool,
-        release: bool,
-        toolchain: ToolChain,
-    ) -> std::result::Result<(ExitStatus, String), io::Error> {
-        let color = if no_color() {
-            "never"
-        } else if color {
-            "always"
-        } else {
-            "never"
-        };
-        let mut cmd = Command::new("cargo");
+        compile_mode: &CompileMode,
+        toolchain: &ToolChain,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+        progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<(ExitStatus, String, Vec<Diagnostic>)> {
+        self.cargo_build_output_inner(
+            None,
+            color,
+            compile_mode,
+            toolchain,
+            interactive_function,
+            progress_function,
+        )
+    }
 
-        let output = if !release {
-            self.cargo_common(&mut cmd, "build", toolchain)
-                .args(["--color", color])
-                .output()?
-        } else {
-            self.cargo_common(&mut cmd, "build", toolchain)
-                .arg("--release")
-                .args(["--color", color])
-                .output()?
-        };
-        let status = output.status;
+    /// Same as `cargo_build_output` but for a package that lives inside the repl project
+    /// (exp: the persistent eval mode host and snippet packages)
+    pub fn cargo_build_package_output(
+        &self,
+        package_dir: &Path,
+        color: bool,
+        compile_mode: &CompileMode,
+        toolchain: &ToolChain,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+        progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<(ExitStatus, String, Vec<Diagnostic>)> {
+        self.cargo_build_output_inner(
+            Some(package_dir),
+            color,
+            compile_mode,
+            toolchain,
+            interactive_function,
+            progress_function,
+        )
+    }
 
-        Ok((status, stdout_and_stderr(output)))
+    fn cargo_build_output_inner(
+        &self,
+        package_dir: Option<&Path>,
+        color: bool,
+        compile_mode: &CompileMode,
+        toolchain: &ToolChain,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+        progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<(ExitStatus, String, Vec<Diagnostic>)> {
+        let color = !no_color() && color;
+        let profile = self.build_profile(compile_mode)?;
+        let mut cmd = Command::new("cargo");
+        let cmd = self.cargo_common(&mut cmd, "build", toolchain, &profile);
+        if let Some(package_dir) = package_dir {
+            cmd.arg("--manifest-path")
+                .arg(package_dir.join("Cargo.toml"));
+        }
+        let output = cargo_output(
+            cmd.args(["--color", if color { "always" } else { "never" }])
+                .args(["--message-format", message_format(color)]),
+            interactive_function,
+            progress_function,
+        )?;
+
+        Ok(compiler_output(output))
     }
 
-    pub fn cargo_bench(&self, toolchain: ToolChain) -> std::result::Result<String, io::Error> {
-        let color = if no_color() { "never" } else { "always" };
+    pub fn cargo_bench(
+        &self,
+        toolchain: &ToolChain,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+        progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<String> {
+        let color = !no_color();
         let mut cmd = Command::new("cargo");
-        Ok(stdout_and_stderr(
-            self.cargo_common(&mut cmd, "bench", toolchain)
-                .args(["--color", color])
-                .output()?,
-        ))
+        let output = cargo_output(
+            self.cargo_common(&mut cmd, "bench", toolchain, &self.debug_profile())
+                .args(["--color", if color { "always" } else { "never" }])
+                .args(["--message-format", message_format(color)]),
+            interactive_function,
+            progress_function,
+        )?;
+        // The benchmarks results are the non json lines
+        Ok(compiler_output(output).1)
     }
 
     pub fn cargo_fmt(&self, c: &str) -> std::io::Result<String> {
@@ -367,45 +650,58 @@ edition = \"2021\""
         Ok(fmt_c)
     }
 
-    pub fn cargo_asm(&self, fnn: &str, toolchain: ToolChain) -> Result<String> {
+    /// cargo-asm doesn't forward cargo json messages, so the progress only comes from cargo bar
+    pub fn cargo_asm(
+        &self,
+        fnn: &str,
+        toolchain: &ToolChain,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+        progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<String> {
         // 0 doesn't activate FORCE_COLOR (tested)
         let force_color = if no_color() { "0" } else { "1" };
         let mut cmd = Command::new("cargo");
-        let output = self
-            .cargo_common(&mut cmd, "asm", toolchain)
-            .arg("--lib")
-            .arg(format!("{}::{fnn}", &self.name))
-            .arg("--rust")
-            .env("FORCE_COLOR", force_color)
-            .output()?;
+        let output = cargo_output(
+            self.cargo_common(&mut cmd, "asm", toolchain, &self.debug_profile())
+                .arg("--lib")
+                .arg(format!("{}::{fnn}", &self.name))
+                .arg("--rust")
+                .env("FORCE_COLOR", force_color),
+            interactive_function,
+            progress_function,
+        )?;
         if !output.status.success() {
-            return Err(
-            (stdout_and_stderr(output)
-            + "\nMaybe you should make the function `pub`, see https://github.com/pacak/cargo-show-asm#my-function-isnt-there" +
-                      WRITE_LIB_LIMIT).into());
+            return Err(ReplError::Compile {
+                diagnostics: vec![],
+                output: stdout_and_stderr(output)
+                    + "\nMaybe you should make the function `pub`, see https://github.com/pacak/cargo-show-asm#my-function-isnt-there",
+            });
         }
         Ok(stdout_and_stderr(output))
     }
 
-    pub fn cargo_expand(&self, fnn: Option<&str>, toolchain: ToolChain) -> Result<String> {
+    pub fn cargo_expand(&self, fnn: Option<&str>, toolchain: &ToolChain) -> Result<String> {
         let color = if no_color() { "never" } else { "always" };
         let mut cmd = Command::new("cargo");
         let output = if let Some(fnn) = fnn {
-            self.cargo_common(&mut cmd, "expand", toolchain)
+            self.cargo_common(&mut cmd, "expand", toolchain, &self.debug_profile())
                 // For cargo expand, color needs to be specified here
                 .args(["--color", color])
                 .arg("--lib")
                 .arg(fnn)
                 .output()?
         } else {
-            self.cargo_common(&mut cmd, "expand", toolchain)
+            self.cargo_common(&mut cmd, "expand", toolchain, &self.debug_profile())
                 // For cargo expand, color needs to be specified here
                 .args(["--color", color])
                 .args(["--bin", &self.name])
                 .output()?
         };
         if !output.status.success() {
-            return Err((stdout_and_stderr(output) + WRITE_LIB_LIMIT).into());
+            return Err(ReplError::Compile {
+                diagnostics: vec![],
+                output: stdout_and_stderr(output),
+            });
         }
         Ok(stdout_and_stderr(output).trim().to_owned())
     }
@@ -447,6 +743,11 @@ edition = \"{edition}\"",
         Ok(())
     }
 
+    /// Switch the repl project to another edition, its dependencies are kept
+    pub fn set_edition(&self, edition: Edition) -> io::Result<()> {
+        set_manifest_edition(&self.paths.cargo_toml_file, edition)
+    }
+
     fn clean_files(&self) -> io::Result<()> {
         const MAIN_SRC: &str = "fn main() {\n\n}";
         let mut main = fs::File::create(&self.paths.main_file)?;
@@ -462,3 +763,110 @@ edition = \"{edition}\"",
         std::fs::remove_dir_all(&self.paths.irust_dir)
     }
 }
+
+/// `name = { path = "path" }`
+pub(crate) fn path_dependency(path: &Path, name: &str) -> String {
+    let path = if !cfg!(windows) {
+        path.display().to_string()
+    } else {
+        path.display().to_string().replace('\\', "\\\\")
+    };
+    format!("{name} = {{ path = \"{path}\" }}")
+}
+
+/// Run a cargo command like `Command::output`, but spawned so the interactive function can cancel
+/// it (exp: on ctrl-c), and with its progress given to the progress function
+fn cargo_output(
+    cmd: &mut Command,
+    interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+    progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+) -> Result<process::Output> {
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let Some(progress_function) = progress_function else {
+        return cmd
+            .spawn()?
+            .interactive_output(interactive_function, None, None);
+    };
+
+    let mut parser = ProgressParser::new(progress_function);
+    let mut output = cmd
+        // Cargo only draws its bar in a terminal otherwise
+        .env("CARGO_TERM_PROGRESS_WHEN", "always")
+        .env("CARGO_TERM_PROGRESS_WIDTH", "80")
+        .spawn()?
+        .interactive_output(
+            interactive_function,
+            Some(&mut |chunk| parser.push(chunk)),
+            None,
+        )?;
+    output.stderr = progress::strip_bars(&output.stderr);
+    Ok(output)
+}
+
+fn message_format(color: bool) -> &'static str {
+    if color {
+        "json-diagnostic-rendered-ansi"
+    } else {
+        "json"
+    }
+}
+
+/// Separate the compiler diagnostics from the rest of cargo json output
+///
+/// The returned text output still contains the rendered diagnostics, after cargo own messages
+fn compiler_output(output: process::Output) -> (ExitStatus, String, Vec<Diagnostic>) {
+    let mut diagnostics = vec![];
+    let mut stderr = output.stderr;
+    let mut stdout = vec![];
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(diagnostic) = Diagnostic::from_cargo_message(line) {
+            if let Some(rendered) = &diagnostic.rendered {
+                stderr.extend_from_slice(rendered.as_bytes());
+            }
+            diagnostics.push(diagnostic);
+        } else if !line.starts_with('{') {
+            // build scripts and proc macros can still print to stdout
+            stdout.extend_from_slice(line.as_bytes());
+            stdout.push(b'\n');
+        }
+    }
+    let status = output.status;
+    let output = stdout_and_stderr(process::Output {
+        status,
+        stdout,
+        stderr,
+    });
+    (status, output, diagnostics)
+}
+
+/// Set the `edition` of the `[package]` table of a manifest, it is only written if it changes
+fn set_manifest_edition(manifest: &Path, edition: Edition) -> io::Result<()> {
+    let contents = fs::read_to_string(manifest)?;
+    let edition_line = format!("edition = \"{edition}\"");
+    let mut lines: Vec<String> = contents.lines().map(ToOwned::to_owned).collect();
+    let package = lines
+        .iter()
+        .position(|line| line.trim() == "[package]")
+        .ok_or_else(|| {
+            io::Error::other(format!("{} has no [package] table", manifest.display()))
+        })?;
+    let package_end = lines[package + 1..]
+        .iter()
+        .position(|line| line.trim().starts_with('['))
+        .map_or(lines.len(), |end| package + 1 + end);
+    match lines[package + 1..package_end].iter().position(|line| {
+        line.split_once('=')
+            .is_some_and(|(key, _)| key.trim() == "edition")
+    }) {
+        Some(line) => lines[package + 1 + line] = edition_line,
+        None => lines.insert(package + 1, edition_line),
+    }
+
+    let new_contents = lines.join("\n");
+    if new_contents != contents {
+        fs::write(manifest, new_contents)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use crate::{Edition, EvalCallbacks, EvalConfig, EvalResult, Repl, ReplError, Result, ToolChain};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use std::{fmt::Display, str::FromStr};
+
+/// A toolchain and an edition to evaluate an input with, the unset ones are the repl own
+///
+/// It is written `toolchain@edition`, either part can be omitted (exp: `nightly`, `1.79.0@2021`
+/// or `@2018`)
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompareTarget {
+    pub toolchain: Option<ToolChain>,
+    pub edition: Option<Edition>,
+}
+
+/// The evaluation of an input with one of the targets of [Repl::compare]
+#[derive(Debug)]
+pub struct Comparison {
+    /// The target with the unset parts filled in
+    pub target: CompareTarget,
+    /// An error means the evaluation couldn't happen (exp: the toolchain isn't installed), a failed
+    /// build or run is a failed [EvalResult]
+    pub result: Result<EvalResult>,
+    /// Time of the whole evaluation, build included
+    pub time: Duration,
+}
+
+impl Repl {
+    /// Evaluate `input` after the repl code with each target, in isolated copies of the repl
+    /// project
+    ///
+    /// The callbacks of `eval_config` are used by every evaluation in turn
+    pub fn compare(
+        &self,
+        targets: &[CompareTarget],
+        eval_config: EvalConfig<impl ToString>,
+    ) -> Vec<Comparison> {
+        let EvalConfig {
+            input,
+            interactive_function,
+            output_function,
+            progress_function,
+            color,
+            evaluator,
+            compile_mode,
+        } = eval_config;
+        let input = input.to_string();
+        let mut callbacks = EvalCallbacks {
+            interactive_function,
+            output_function,
+            progress_function,
+        };
+
+        // Once an evaluation is cancelled (exp: on ctrl-c), the remaining ones are skipped
+        let mut cancelled = false;
+        targets
+            .iter()
+            .map(|target| {
+                // The repl own toolchain and edition fill the unset parts
+                let toolchain = target
+                    .toolchain
+                    .clone()
+                    .unwrap_or_else(|| self.toolchain.clone());
+                let edition = target.edition.unwrap_or(self.edition);
+                let target = CompareTarget {
+                    toolchain: Some(toolchain.clone()),
+                    edition: Some(edition),
+                };
+                let start = Instant::now();
+                let result = if cancelled {
+                    Err(ReplError::Cancelled)
+                } else {
+                    self.isolated_copy(toolchain, edition).and_then(|mut repl| {
+                        let EvalCallbacks {
+                            interactive_function,
+                            output_function,
+                            progress_function,
+                        } = callbacks.reborrow();
+                        repl.eval_with_configuration(EvalConfig {
+                            input: &input,
+                            interactive_function,
+                            output_function,
+                            progress_function,
+                            color,
+                            evaluator,
+                            compile_mode: compile_mode.clone(),
+                        })
+                    })
+                };
+                cancelled = matches!(result, Err(ReplError::Cancelled));
+                Comparison {
+                    target,
+                    result,
+                    time: start.elapsed(),
+                }
+            })
+            .collect()
+    }
+
+    /// A new repl with the same code, dependencies and limits, using the target toolchain and
+    /// edition
+    ///
+    /// It doesn't start background builds, the compare builds it right away
+    fn isolated_copy(&self, toolchain: ToolChain, edition: Edition) -> Result<Repl> {
+        toolchain.check_installed()?;
+
+        let mut session = self.session()?;
+        session.toolchain = toolchain.clone();
+        session.edition = edition;
+        let mut repl = Repl::with_cargo_unbuilt(
+            self.cargo.fresh(),
+            self.custom.clone(),
+            toolchain,
+            self.executor.clone(),
+            self.main_result.clone(),
+            edition,
+            self.prelude.clone(),
+        )?;
+        repl.restore_session_unbuilt(session)?;
+        repl.next_input = self.next_input;
+        repl.limits = self.limits;
+        Ok(repl)
+    }
+}
+
+impl FromStr for CompareTarget {
+    type Err = Box<dyn std::error::Error>;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (toolchain, edition) = s.split_once('@').unwrap_or((s, ""));
+        let target = CompareTarget {
+            toolchain: (!toolchain.is_empty())
+                .then(|| toolchain.parse())
+                .transpose()?,
+            edition: (!edition.is_empty()).then(|| edition.parse()).transpose()?,
+        };
+        if target == CompareTarget::default() {
+            return Err(
+                "A compare target needs a toolchain or an edition (exp: `nightly@2021`)".into(),
+            );
+        }
+        Ok(target)
+    }
+}
+
+impl Display for CompareTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(toolchain) = &self.toolchain {
+            write!(f, "{toolchain}")?;
+        }
+        if let Some(edition) = self.edition {
+            write!(f, "@{edition}")?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for CompareTarget {
+    type Error = Box<dyn std::error::Error>;
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<CompareTarget> for String {
+    fn from(target: CompareTarget) -> Self {
+        target.to_string()
+    }
+}
+
+#[test]
+fn parse_compare_target() {
+    let target: CompareTarget = "1.79.0@2021".parse().unwrap();
+    assert_eq!(
+        target.toolchain,
+        Some(ToolChain::Named("1.79.0".to_owned()))
+    );
+    assert_eq!(target.to_string(), "1.79.0@2021");
+    assert_eq!(
+        "@2018".parse::<CompareTarget>().unwrap().to_string(),
+        "@2018"
+    );
+    assert_eq!(
+        "nightly".parse::<CompareTarget>().unwrap(),
+        CompareTarget {
+            toolchain: Some(ToolChain::Nightly),
+            edition: None
+        }
+    );
+    assert!("@".parse::<CompareTarget>().is_err());
+    assert!("stable@2030".parse::<CompareTarget>().is_err());
+}
@@ -2,11 +2,14 @@
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
 pub enum CompileMode {
     Debug,
     Release,
+    /// A [crate::CompileProfile] of the repl, by name
+    Profile(String),
 }
 impl CompileMode {
     pub fn is_release(&self) -> bool {
@@ -20,7 +23,10 @@ impl FromStr for CompileMode {
         match s.to_lowercase().as_ref() {
             "debug" => Ok(CompileMode::Debug),
             "release" => Ok(CompileMode::Release),
-            _ => Err("Unknown compile mode".into()),
+            _ if s.is_empty() || s.contains(char::is_whitespace) => {
+                Err("Unknown compile mode".into())
+            }
+            _ => Ok(CompileMode::Profile(s.to_owned())),
         }
     }
 }
@@ -30,6 +36,20 @@ impl std::fmt::Display for CompileMode {
         match self {
             CompileMode::Debug => write!(f, "Debug"),
             CompileMode::Release => write!(f, "Release"),
+            CompileMode::Profile(name) => write!(f, "{name}"),
         }
     }
 }
+
+impl TryFrom<String> for CompileMode {
+    type Error = Box<dyn std::error::Error>;
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<CompileMode> for String {
+    fn from(compile_mode: CompileMode) -> Self {
+        compile_mode.to_string()
+    }
+}
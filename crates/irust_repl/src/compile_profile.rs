@@ -0,0 +1,222 @@
+use crate::{CompileMode, ReplError, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A user defined compile mode, built as a cargo profile that inherits from the debug (or release)
+/// one
+///
+/// The unset fields keep the value of the inherited profile
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileProfile {
+    /// Inherit from the release profile instead of the debug one
+    pub release: bool,
+    /// `0`, `1`, `2`, `3`, `s` or `z`
+    pub opt_level: Option<String>,
+    /// Disabling the debug info makes the builds faster
+    pub debug: Option<bool>,
+    pub codegen_units: Option<u32>,
+    pub incremental: Option<bool>,
+    /// `mold`, `lld` or `auto` (the first of them that is installed), they are only used if they
+    /// are installed, any other value is the path of a linker
+    pub linker: Option<String>,
+    /// exp: `native`
+    pub target_cpu: Option<String>,
+    /// Added to `RUSTFLAGS`
+    pub rustflags: Vec<String>,
+}
+
+/// How cargo is invoked for a compile mode
+///
+/// Every command of a mode must be invoked the same way, or cargo rebuilds what the other commands
+/// left in the target directory
+#[derive(Debug, Clone)]
+pub(crate) struct BuildProfile {
+    pub args: Vec<String>,
+    /// The whole `RUSTFLAGS`, None keeps the environment one
+    pub rustflags: Option<String>,
+    pub target_dir: PathBuf,
+    /// Where the artifacts end up, inside the target directory
+    pub out_dir: PathBuf,
+}
+
+impl CompileProfile {
+    /// Profile names are used in paths and cargo arguments, the builtin names are reserved
+    pub(crate) fn check(name: &str, profile: &CompileProfile) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ReplError::InvalidSetting {
+                setting: "compile profile",
+                reason: format!("Invalid compile profile name: `{name}`"),
+            });
+        }
+        if name.parse::<CompileMode>().ok() != Some(CompileMode::Profile(name.to_owned())) {
+            return Err(ReplError::InvalidSetting {
+                setting: "compile profile",
+                reason: format!("`{name}` is a builtin compile mode"),
+            });
+        }
+        if let Some(opt_level) = &profile.opt_level
+            && !["0", "1", "2", "3", "s", "z"].contains(&opt_level.as_str())
+        {
+            return Err(ReplError::InvalidSetting {
+                setting: "compile profile",
+                reason: format!(
+                    "Invalid opt_level `{opt_level}` for profile `{name}` (expected 0, 1, 2, 3, s or z)"
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn build_profile(&self, name: &str, common_target_dir: &Path) -> BuildProfile {
+        let cargo_name = format!("irust-{name}");
+        let mut settings = vec![(
+            "inherits",
+            format!("\"{}\"", if self.release { "release" } else { "dev" }),
+        )];
+        if let Some(opt_level) = &self.opt_level {
+            // Numbers are integers in toml, letters are strings
+            settings.push(match opt_level.parse::<u8>() {
+                Ok(level) => ("opt-level", level.to_string()),
+                Err(_) => ("opt-level", format!("\"{opt_level}\"")),
+            });
+        }
+        if let Some(debug) = self.debug {
+            settings.push(("debug", debug.to_string()));
+        }
+        if let Some(codegen_units) = self.codegen_units {
+            settings.push(("codegen-units", codegen_units.to_string()));
+        }
+        if let Some(incremental) = self.incremental {
+            settings.push(("incremental", incremental.to_string()));
+        }
+        let mut args = vec![];
+        for (key, value) in settings {
+            args.push("--config".to_owned());
+            args.push(format!("profile.{cargo_name}.{key}={value}"));
+        }
+        args.push("--profile".to_owned());
+        args.push(cargo_name.clone());
+
+        let flags = self.rustflags_additions();
+        // Changing RUSTFLAGS rebuilds everything, so these profiles get their own target directory
+        let (rustflags, target_dir) = if flags.is_empty() {
+            (None, common_target_dir.to_path_buf())
+        } else {
+            let env_flags = std::env::var("RUSTFLAGS").unwrap_or_default();
+            (
+                Some(
+                    env_flags
+                        .split_whitespace()
+                        .map(ToOwned::to_owned)
+                        .chain(flags)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                common_target_dir.join("irust-profiles").join(name),
+            )
+        };
+        BuildProfile {
+            args,
+            rustflags,
+            out_dir: target_dir.join(cargo_name),
+            target_dir,
+        }
+    }
+
+    fn rustflags_additions(&self) -> Vec<String> {
+        let mut flags = vec![];
+        if let Some(linker) = &self.linker {
+            let candidates: &[&str] = match linker.as_str() {
+                "mold" => &["mold"],
+                "lld" => &["lld"],
+                "auto" => &["mold", "lld"],
+                linker => {
+                    flags.push(format!("-Clinker={linker}"));
+                    &[]
+                }
+            };
+            if let Some(linker) = candidates.iter().find(|linker| linker_installed(linker)) {
+                flags.push(format!("-Clink-arg=-fuse-ld={linker}"));
+            }
+        }
+        if let Some(target_cpu) = &self.target_cpu {
+            flags.push(format!("-Ctarget-cpu={target_cpu}"));
+        }
+        flags.extend(self.rustflags.iter().cloned());
+        flags
+    }
+}
+
+impl BuildProfile {
+    pub(crate) fn builtin(release: bool, target_dir: &Path) -> Self {
+        let (args, dir) = if release {
+            (vec!["--release".to_owned()], "release")
+        } else {
+            (vec![], "debug")
+        };
+        BuildProfile {
+            args,
+            rustflags: None,
+            target_dir: target_dir.to_path_buf(),
+            out_dir: target_dir.join(dir),
+        }
+    }
+}
+
+/// `-fuse-ld=lld` looks for `ld.lld`
+fn linker_installed(linker: &str) -> bool {
+    let program = match linker {
+        "lld" => "ld.lld",
+        linker => linker,
+    };
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+#[test]
+fn compile_profile_arguments() {
+    let profile = CompileProfile {
+        opt_level: Some("s".to_owned()),
+        debug: Some(false),
+        ..Default::default()
+    };
+    let build = profile.build_profile("small", Path::new("/target"));
+    assert_eq!(
+        build.args,
+        [
+            "--config",
+            "profile.irust-small.inherits=\"dev\"",
+            "--config",
+            "profile.irust-small.opt-level=\"s\"",
+            "--config",
+            "profile.irust-small.debug=false",
+            "--profile",
+            "irust-small"
+        ]
+    );
+    assert_eq!(build.target_dir, Path::new("/target"));
+    assert_eq!(build.out_dir, Path::new("/target/irust-small"));
+
+    let profile = CompileProfile {
+        release: true,
+        target_cpu: Some("native".to_owned()),
+        ..Default::default()
+    };
+    let build = profile.build_profile("native", Path::new("/target"));
+    assert!(build.rustflags.unwrap().ends_with("-Ctarget-cpu=native"));
+    assert_eq!(
+        build.out_dir,
+        Path::new("/target/irust-profiles/native/irust-native")
+    );
+
+    assert!(CompileProfile::check("fast", &profile).is_ok());
+    assert!(CompileProfile::check("release", &profile).is_err());
+    assert!(CompileProfile::check("a b", &profile).is_err());
+}
@@ -0,0 +1,115 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+/// Where the crates of the repl come from, anything other than crates.io replaces it
+///
+/// It is written `crates-io`, `vendor:<dir>`, `local-registry:<dir>` or `mirror:<index url>`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CrateSource {
+    #[default]
+    CratesIo,
+    /// A directory of vendored crates, as made by `cargo vendor`
+    Vendored(PathBuf),
+    /// A cargo local registry (exp: made by `cargo local-registry`)
+    LocalRegistry(PathBuf),
+    /// A crates.io mirror, by its index url (exp: `sparse+https://mirror.example/index/`)
+    Mirror(String),
+}
+
+impl CrateSource {
+    /// The source replacement of crates.io, as a cargo config
+    pub(crate) fn cargo_config(&self) -> Option<String> {
+        let (key, value) = match self {
+            CrateSource::CratesIo => return None,
+            CrateSource::Vendored(dir) => ("directory", dir.display().to_string()),
+            CrateSource::LocalRegistry(dir) => ("local-registry", dir.display().to_string()),
+            CrateSource::Mirror(url) => ("registry", url.clone()),
+        };
+        Some(format!(
+            "\
+[source.crates-io]
+replace-with = \"irust\"
+
+[source.irust]
+{key} = {value:?}
+"
+        ))
+    }
+}
+
+impl FromStr for CrateSource {
+    type Err = Box<dyn std::error::Error>;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("crates-io") {
+            return Ok(CrateSource::CratesIo);
+        }
+        let (kind, location) = s.split_once(':').ok_or_else(|| {
+            format!("Invalid crate source: `{s}` (expected crates-io, vendor:<dir>, local-registry:<dir> or mirror:<url>)")
+        })?;
+        if location.is_empty() {
+            return Err(format!("Invalid crate source: `{s}` has no location").into());
+        }
+        match kind.to_lowercase().as_str() {
+            "vendor" => Ok(CrateSource::Vendored(location.into())),
+            "local-registry" => Ok(CrateSource::LocalRegistry(location.into())),
+            "mirror" => Ok(CrateSource::Mirror(location.to_owned())),
+            _ => Err(format!("Unknown crate source kind: `{kind}`").into()),
+        }
+    }
+}
+
+impl Display for CrateSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrateSource::CratesIo => write!(f, "crates-io"),
+            CrateSource::Vendored(dir) => write!(f, "vendor:{}", dir.display()),
+            CrateSource::LocalRegistry(dir) => write!(f, "local-registry:{}", dir.display()),
+            CrateSource::Mirror(url) => write!(f, "mirror:{url}"),
+        }
+    }
+}
+
+impl TryFrom<String> for CrateSource {
+    type Error = Box<dyn std::error::Error>;
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<CrateSource> for String {
+    fn from(source: CrateSource) -> Self {
+        source.to_string()
+    }
+}
+
+#[test]
+fn parse_crate_source() {
+    assert_eq!(
+        "vendor:/opt/vendor".parse::<CrateSource>().unwrap(),
+        CrateSource::Vendored("/opt/vendor".into())
+    );
+    // Only the first colon separates the kind
+    let mirror: CrateSource = "mirror:sparse+https://mirror.example/index/"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        mirror.to_string(),
+        "mirror:sparse+https://mirror.example/index/"
+    );
+    assert_eq!(
+        "Crates-IO".parse::<CrateSource>().unwrap(),
+        CrateSource::CratesIo
+    );
+    assert!("vendor:".parse::<CrateSource>().is_err());
+    assert!("git:https://x".parse::<CrateSource>().is_err());
+    assert!(CrateSource::CratesIo.cargo_config().is_none());
+    assert!(
+        CrateSource::LocalRegistry("/r".into())
+            .cargo_config()
+            .unwrap()
+            .contains("local-registry = \"/r\"")
+    );
+}
@@ -0,0 +1,115 @@
+use crate::{Executor, ReplError, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An executor defined by the user, used with [Executor::Custom]
+///
+/// The builtin executors are defined the same way, see [Executor::definition]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustomExecutor {
+    /// Put on an async main (exp: `#[tokio::main(flavor = "current_thread")]`), without it main
+    /// runs its code with `block_on`
+    pub attribute: Option<String>,
+    /// Runs a future to completion, `$$` is replaced by the future (exp: `smol::block_on($$)`)
+    ///
+    /// It's needed by async executors, the persistent eval mode always uses it
+    pub block_on: Option<String>,
+    /// `cargo add` arguments, the first one is the crate name (exp: `["smol"]`)
+    pub dependency: Vec<String>,
+    /// Items added at the crate root (exp: `use smol::prelude::*;`)
+    pub prelude: Option<String>,
+}
+
+impl CustomExecutor {
+    /// Executor names are used as `:executor` arguments, the builtin names are reserved
+    pub(crate) fn check(name: &str, executor: &CustomExecutor) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ReplError::InvalidSetting {
+                setting: "executor",
+                reason: format!("Invalid executor name: `{name}`"),
+            });
+        }
+        if name.parse::<Executor>().ok() != Some(Executor::Custom(name.to_owned())) {
+            return Err(ReplError::InvalidSetting {
+                setting: "executor",
+                reason: format!("`{name}` is a builtin executor"),
+            });
+        }
+        match &executor.block_on {
+            Some(block_on) if !block_on.contains("$$") => Err(ReplError::InvalidSetting {
+                setting: "executor",
+                reason: format!(
+                    "The block_on of executor `{name}` doesn't contain `$$` (the future)"
+                ),
+            }),
+            None if executor.attribute.is_some() => Err(ReplError::InvalidSetting {
+                setting: "executor",
+                reason: format!(
+                    "Executor `{name}` has an attribute but no block_on, the persistent eval mode needs it"
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The main function signature, without its return type
+    pub(crate) fn main(&self) -> String {
+        match &self.attribute {
+            Some(attribute) => format!("{attribute}async fn main()"),
+            None => "fn main()".into(),
+        }
+    }
+
+    /// The code that goes before and after an async block to run it, empty for sync executors
+    pub(crate) fn block_on(&self) -> (String, String) {
+        match &self.block_on {
+            Some(block_on) => {
+                let (before, after) = block_on.split_once("$$").expect("checked");
+                (format!("{before}async {{"), format!("}}{after}"))
+            }
+            None => (String::new(), String::new()),
+        }
+    }
+
+    /// [CustomExecutor::block_on] for the body of main, an attribute already makes it async
+    pub(crate) fn main_wrapper(&self) -> (String, String) {
+        if self.attribute.is_some() {
+            (String::new(), String::new())
+        } else {
+            self.block_on()
+        }
+    }
+}
+
+#[test]
+fn custom_executor_code() {
+    let smol = CustomExecutor {
+        block_on: Some("smol::block_on($$)".to_owned()),
+        dependency: vec!["smol".to_owned()],
+        ..Default::default()
+    };
+    assert!(CustomExecutor::check("smol", &smol).is_ok());
+    assert_eq!(smol.main(), "fn main()");
+    assert_eq!(
+        smol.main_wrapper(),
+        ("smol::block_on(async {".to_owned(), "})".to_owned())
+    );
+
+    let attribute = CustomExecutor {
+        attribute: Some("#[tokio::main(flavor = \"current_thread\")]".to_owned()),
+        ..Default::default()
+    };
+    assert!(CustomExecutor::check("current_thread", &attribute).is_err());
+    assert!(CustomExecutor::check("tokio", &smol).is_err());
+    let missing_future = CustomExecutor {
+        block_on: Some("smol::block_on()".to_owned()),
+        ..Default::default()
+    };
+    assert!(CustomExecutor::check("smol", &missing_future).is_err());
+}
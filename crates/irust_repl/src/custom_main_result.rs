@@ -0,0 +1,127 @@
+use crate::{MainResult, ReplError, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A return type of the repl code defined by the user, used with [MainResult::Custom]
+///
+/// It's a `Result` whose error implements `Debug` (its report is printed when it's returned), or an
+/// `Option`. The builtin ones are defined the same way, see [MainResult::definition]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustomMainResult {
+    /// exp: `anyhow::Result<()>`
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub ttype: String,
+    /// What the repl code returns when it succeeds (exp: `Ok(())`)
+    pub success: String,
+    /// `cargo add` arguments, the first one is the crate name (exp: `["anyhow"]`)
+    pub dependency: Vec<String>,
+}
+
+const OPTION_PATHS: [&str; 3] = ["Option<", "std::option::Option<", "core::option::Option<"];
+
+impl CustomMainResult {
+    /// Main result names are used as `:main_result` arguments, the builtin names are reserved
+    pub(crate) fn check(name: &str, main_result: &CustomMainResult) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ReplError::InvalidSetting {
+                setting: "main result",
+                reason: format!("Invalid main result name: `{name}`"),
+            });
+        }
+        if name.parse::<MainResult>().ok() != Some(MainResult::Custom(name.to_owned())) {
+            return Err(ReplError::InvalidSetting {
+                setting: "main result",
+                reason: format!("`{name}` is a builtin main result"),
+            });
+        }
+        if main_result.ttype.trim().is_empty() || main_result.success.trim().is_empty() {
+            return Err(ReplError::InvalidSetting {
+                setting: "main result",
+                reason: format!("Main result `{name}` needs a type and a success value"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Anything but `()` can report an error, see [crate::EvalResult::returned_error]
+    pub(crate) fn returns_errors(&self) -> bool {
+        self.ttype.trim() != "()"
+    }
+
+    /// Main can't return an option, it's turned into an exit code
+    fn is_option(&self) -> bool {
+        let ttype = self.ttype.trim();
+        OPTION_PATHS.iter().any(|path| ttype.starts_with(path))
+    }
+
+    /// The return type of main
+    pub(crate) fn main_type(&self) -> &str {
+        if self.is_option() {
+            "std::process::ExitCode"
+        } else {
+            &self.ttype
+        }
+    }
+
+    /// The code around the repl code in main so it can return [CustomMainResult::ttype], an async
+    /// main can't use closures since the repl code awaits
+    pub(crate) fn main_wrapper(&self, async_main: bool) -> (String, String) {
+        if !self.is_option() {
+            return (String::new(), String::new());
+        }
+        let ttype = &self.ttype;
+        let exit_code = "irust_result.map_or_else(|| { eprintln!(\"Error: None\"); std::process::ExitCode::FAILURE }, |_| std::process::ExitCode::SUCCESS)";
+        if async_main {
+            (
+                format!("let irust_result: {ttype} = async {{"),
+                format!("}}.await; {exit_code}"),
+            )
+        } else {
+            (
+                format!("let irust_result: {ttype} = (|| -> {ttype} {{"),
+                format!("}})(); {exit_code}"),
+            )
+        }
+    }
+
+    /// Turns `irust_result`, what the repl code returned, into an exit code and prints its error
+    /// like main does
+    pub(crate) fn report(&self) -> &'static str {
+        if !self.returns_errors() {
+            "{ let () = irust_result; 0 }"
+        } else if self.is_option() {
+            "match irust_result { Some(_) => 0, None => { eprintln!(\"Error: None\"); 1 } }"
+        } else {
+            "match irust_result { Ok(_) => 0, Err(e) => { eprintln!(\"Error: {e:?}\"); 1 } }"
+        }
+    }
+}
+
+#[test]
+fn custom_main_result_code() {
+    let anyhow = CustomMainResult {
+        ttype: "anyhow::Result<()>".to_owned(),
+        success: "Ok(())".to_owned(),
+        dependency: vec!["anyhow".to_owned()],
+    };
+    assert!(CustomMainResult::check("anyhow", &anyhow).is_ok());
+    assert!(CustomMainResult::check("option", &anyhow).is_err());
+    assert!(CustomMainResult::check("empty", &CustomMainResult::default()).is_err());
+    assert_eq!(anyhow.main_type(), "anyhow::Result<()>");
+    assert_eq!(anyhow.main_wrapper(false), (String::new(), String::new()));
+
+    let option = CustomMainResult {
+        ttype: "std::option::Option<()>".to_owned(),
+        success: "Some(())".to_owned(),
+        dependency: vec![],
+    };
+    assert_eq!(option.main_type(), "std::process::ExitCode");
+    assert!(option.main_wrapper(true).0.ends_with("= async {"));
+    assert!(option.report().contains("None =>"));
+}
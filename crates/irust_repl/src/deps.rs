@@ -0,0 +1,158 @@
+//! The repl dependencies, as resolved by `cargo metadata` (manifest and lockfile)
+use crate::{PRELUDE_NAME, Repl, ReplError, Result};
+use serde_json::Value;
+
+/// A dependency of the repl
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    /// The version requirement from the manifest (exp: `^1.0`), `*` for path dependencies
+    pub requirement: String,
+    /// The version from the lockfile, None if it isn't resolved yet
+    pub version: Option<String>,
+    /// Enabled features, the default ones included
+    pub features: Vec<String>,
+    /// Set for path dependencies
+    pub path: Option<String>,
+}
+
+/// A feature of a dependency, see [Repl::features]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub name: String,
+    /// The features and optional dependencies it enables
+    pub enables: Vec<String>,
+    pub enabled: bool,
+}
+
+/// The parts of `cargo metadata` output about the repl package
+struct Metadata {
+    json: Value,
+}
+
+impl Metadata {
+    fn root(&self) -> Option<&Value> {
+        let root = self.json.get("resolve")?.get("root")?.as_str()?;
+        self.package(root)
+    }
+
+    fn package(&self, id: &str) -> Option<&Value> {
+        self.json
+            .get("packages")?
+            .as_array()?
+            .iter()
+            .find(|package| package.get("id").and_then(Value::as_str) == Some(id))
+    }
+
+    fn node(&self, id: &str) -> Option<&Value> {
+        self.json
+            .get("resolve")?
+            .get("nodes")?
+            .as_array()?
+            .iter()
+            .find(|node| node.get("id").and_then(Value::as_str) == Some(id))
+    }
+
+    /// The resolved package of a dependency of the repl
+    fn resolved(&self, name: &str) -> Option<&Value> {
+        let root = self.root()?.get("id")?.as_str()?;
+        self.node(root)?
+            .get("deps")?
+            .as_array()?
+            .iter()
+            .filter_map(|dep| self.package(dep.get("pkg")?.as_str()?))
+            .find(|package| package.get("name").and_then(Value::as_str) == Some(name))
+    }
+
+    fn enabled_features(&self, package: &Value) -> Vec<String> {
+        package
+            .get("id")
+            .and_then(Value::as_str)
+            .and_then(|id| self.node(id))
+            .and_then(|node| node.get("features"))
+            .map(strings)
+            .unwrap_or_default()
+    }
+
+    fn dependencies(&self) -> Vec<Dependency> {
+        let Some(root) = self.root() else {
+            return vec![];
+        };
+        root.get("dependencies")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|dep| {
+                let name = dep.get("name")?.as_str()?.to_owned();
+                // The prelude is a detail of the repl
+                if name == PRELUDE_NAME {
+                    return None;
+                }
+                let resolved = self.resolved(&name);
+                Some(Dependency {
+                    requirement: dep.get("req")?.as_str()?.to_owned(),
+                    version: resolved
+                        .and_then(|package| package.get("version"))
+                        .and_then(Value::as_str)
+                        .map(ToOwned::to_owned),
+                    features: resolved
+                        .map(|package| self.enabled_features(package))
+                        .unwrap_or_default(),
+                    path: dep
+                        .get("path")
+                        .and_then(Value::as_str)
+                        .map(ToOwned::to_owned),
+                    name,
+                })
+            })
+            .collect()
+    }
+
+    fn features(&self, name: &str) -> Option<Vec<Feature>> {
+        let package = self.resolved(name)?;
+        let enabled = self.enabled_features(package);
+        Some(
+            package
+                .get("features")?
+                .as_object()?
+                .iter()
+                .map(|(feature, enables)| Feature {
+                    enabled: enabled.contains(feature),
+                    enables: strings(enables),
+                    name: feature.clone(),
+                })
+                .collect(),
+        )
+    }
+}
+
+fn strings(json: &Value) -> Vec<String> {
+    json.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+impl Repl {
+    /// The dependencies of the repl (the prelude crate excluded)
+    pub fn dependencies(&self) -> Result<Vec<Dependency>> {
+        Ok(self.metadata()?.dependencies())
+    }
+
+    /// The features of a dependency of the repl
+    pub fn features(&self, name: &str) -> Result<Vec<Feature>> {
+        self.metadata()?
+            .features(name)
+            .ok_or_else(|| ReplError::UnknownDependency(name.to_owned()))
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let output = self.cargo.cargo_metadata(&self.toolchain)?;
+        let json = serde_json::from_str(&output).map_err(|_| {
+            ReplError::Dependency("Failed to parse cargo metadata output".to_owned())
+        })?;
+        Ok(Metadata { json })
+    }
+}
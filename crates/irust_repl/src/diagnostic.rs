@@ -0,0 +1,243 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+
+/// A compiler message, as reported by cargo `--message-format json`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: DiagnosticLevel,
+    /// Error code, exp: E0308
+    pub code: Option<String>,
+    pub message: String,
+    pub spans: Vec<DiagnosticSpan>,
+    pub children: Vec<Diagnostic>,
+    /// Replacements proposed by the compiler (collected from the children)
+    pub suggestions: Vec<Suggestion>,
+    /// The compiler own text rendering of this diagnostic
+    pub rendered: Option<String>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiagnosticLevel {
+    Error,
+    Warning,
+    Note,
+    Help,
+    FailureNote,
+    InternalCompilerError,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticSpan {
+    pub file_name: String,
+    /// 1-based
+    pub line_start: usize,
+    pub line_end: usize,
+    /// 1-based, counted in chars
+    pub column_start: usize,
+    pub column_end: usize,
+    pub is_primary: bool,
+    pub label: Option<String>,
+    /// The source lines covered by the span
+    pub text: Vec<String>,
+    pub suggested_replacement: Option<String>,
+    /// Set if the span points to code typed by the user, lines and columns are then relative to
+    /// that input instead of the generated file
+    pub origin: Option<SpanOrigin>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanOrigin {
+    /// The input number (In[n]) the code came from
+    pub input: usize,
+    /// The span is in the input that is being evaluated
+    pub current: bool,
+}
+
+/// Where a line of the generated file comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LineOrigin {
+    pub input: usize,
+    /// 0-based line inside the input
+    pub line: usize,
+    /// Generated code that precedes the input on its first line (exp: the evaluator)
+    pub column_offset: usize,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    pub replacement: String,
+    pub span: DiagnosticSpan,
+}
+
+impl Diagnostic {
+    /// Parse a line of cargo json output, returns None if it's not a compiler message
+    pub(crate) fn from_cargo_message(line: &str) -> Option<Self> {
+        let message = serde_json::from_str::<Value>(line).ok()?;
+        if message.get("reason")?.as_str()? != "compiler-message" {
+            return None;
+        }
+        Self::from_json(message.get("message")?)
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        let children: Vec<Diagnostic> = json
+            .get("children")?
+            .as_array()?
+            .iter()
+            .filter_map(Self::from_json)
+            .collect();
+        let suggestions = children
+            .iter()
+            .flat_map(|child| {
+                child.spans.iter().filter_map(|span| {
+                    Some(Suggestion {
+                        message: child.message.clone(),
+                        replacement: span.suggested_replacement.clone()?,
+                        span: span.clone(),
+                    })
+                })
+            })
+            .collect();
+
+        Some(Self {
+            level: DiagnosticLevel::from_rustc(json.get("level")?.as_str()?),
+            code: json
+                .get("code")
+                .and_then(|code| code.get("code"))
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned),
+            message: json.get("message")?.as_str()?.to_owned(),
+            spans: json
+                .get("spans")?
+                .as_array()?
+                .iter()
+                .filter_map(DiagnosticSpan::from_json)
+                .collect(),
+            children,
+            suggestions,
+            rendered: json
+                .get("rendered")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned),
+        })
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self.level,
+            DiagnosticLevel::Error | DiagnosticLevel::InternalCompilerError
+        )
+    }
+
+    pub fn primary_span(&self) -> Option<&DiagnosticSpan> {
+        self.spans.iter().find(|span| span.is_primary)
+    }
+}
+
+impl DiagnosticSpan {
+    fn from_json(json: &Value) -> Option<Self> {
+        Some(Self {
+            file_name: json.get("file_name")?.as_str()?.to_owned(),
+            line_start: json.get("line_start")?.as_u64()? as usize,
+            line_end: json.get("line_end")?.as_u64()? as usize,
+            column_start: json.get("column_start")?.as_u64()? as usize,
+            column_end: json.get("column_end")?.as_u64()? as usize,
+            is_primary: json.get("is_primary")?.as_bool()?,
+            label: json
+                .get("label")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned),
+            text: json
+                .get("text")?
+                .as_array()?
+                .iter()
+                .filter_map(|line| Some(line.get("text")?.as_str()?.to_owned()))
+                .collect(),
+            suggested_replacement: json
+                .get("suggested_replacement")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned),
+            origin: None,
+        })
+    }
+
+    fn relocate(&mut self, origin: &impl Fn(usize) -> Option<LineOrigin>, current_input: usize) {
+        let Some(start) = self.line_start.checked_sub(1).and_then(origin) else {
+            return;
+        };
+        let end = self.line_end.checked_sub(1).and_then(origin);
+
+        self.column_start = self.column_start.saturating_sub(start.column_offset).max(1);
+        if let Some(first_line) = self.text.first_mut() {
+            *first_line = first_line.chars().skip(start.column_offset).collect();
+        }
+        match end {
+            Some(end) if end.input == start.input => {
+                self.column_end = self.column_end.saturating_sub(end.column_offset).max(1);
+                self.line_end = end.line + 1;
+            }
+            _ => self.line_end = start.line + 1 + (self.line_end - self.line_start),
+        }
+        self.line_start = start.line + 1;
+        self.origin = Some(SpanOrigin {
+            input: start.input,
+            current: start.input == current_input,
+        });
+    }
+}
+
+/// Make the spans that point to user code in `file_name` relative to the input they came from
+///
+/// `origin` maps a 0-based line of the file to its origin
+pub(crate) fn relocate(
+    diagnostics: &mut [Diagnostic],
+    file_name: &str,
+    origin: &impl Fn(usize) -> Option<LineOrigin>,
+    current_input: usize,
+) {
+    for diagnostic in diagnostics {
+        for span in diagnostic
+            .spans
+            .iter_mut()
+            .chain(diagnostic.suggestions.iter_mut().map(|s| &mut s.span))
+            .filter(|span| span.file_name == file_name)
+        {
+            span.relocate(origin, current_input);
+        }
+        relocate(&mut diagnostic.children, file_name, origin, current_input);
+    }
+}
+
+impl DiagnosticLevel {
+    fn from_rustc(level: &str) -> Self {
+        match level {
+            "error" => DiagnosticLevel::Error,
+            "warning" => DiagnosticLevel::Warning,
+            "help" => DiagnosticLevel::Help,
+            "failure-note" => DiagnosticLevel::FailureNote,
+            "error: internal compiler error" => DiagnosticLevel::InternalCompilerError,
+            _ => DiagnosticLevel::Note,
+        }
+    }
+}
+
+impl Display for DiagnosticLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticLevel::Error => write!(f, "error"),
+            DiagnosticLevel::Warning => write!(f, "warning"),
+            DiagnosticLevel::Note => write!(f, "note"),
+            DiagnosticLevel::Help => write!(f, "help"),
+            DiagnosticLevel::FailureNote => write!(f, "failure-note"),
+            DiagnosticLevel::InternalCompilerError => write!(f, "error: internal compiler error"),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::Diagnostic;
+use std::{fmt::Display, io, path::PathBuf, process::ExitStatus};
+
+#[derive(Debug)]
+pub enum ReplError {
+    /// The code failed to compile
+    Compile {
+        diagnostics: Vec<Diagnostic>,
+        /// The compiler output
+        output: String,
+    },
+    /// The program exited with a non zero status (exp: it panicked)
+    Run {
+        status: ExitStatus,
+        output: String,
+    },
+    /// The evaluation was cancelled by the interactive function (exp: ctrl-c)
+    Cancelled,
+    /// The program went over one of the repl limits (exp: it took too long)
+    LimitExceeded {
+        limit: crate::Limit,
+        /// What the program wrote before it was stopped
+        output: String,
+    },
+    /// The sandbox couldn't be set up, or the program broke its rules (exp: it used a forbidden
+    /// system call)
+    Sandbox(String),
+    /// A cargo command that manages dependencies failed (exp: `cargo add`)
+    Dependency(String),
+    /// The main file was corrupted (exp: by an external editor)
+    CorruptedMainFile,
+    /// The loaded session doesn't describe a valid repl
+    CorruptedSession,
+    /// A setting value was rejected (exp: a custom executor without `$$` in its block_on)
+    InvalidSetting {
+        /// exp: `executor`, `compile profile`
+        setting: &'static str,
+        reason: String,
+    },
+    /// A custom executor, main result or compile profile that isn't defined
+    UnknownSetting {
+        setting: &'static str,
+        name: String,
+    },
+    /// The feature isn't available on this system (exp: the pty run mode outside linux)
+    Unsupported {
+        feature: &'static str,
+        /// Where it's available
+        supported_on: &'static str,
+    },
+    /// Rustup failed, or the toolchain isn't installed
+    Toolchain(String),
+    NothingToUndo,
+    NothingToRedo,
+    /// No item has this name, see [crate::Repl::undef]
+    NotDefined(String),
+    /// The crate isn't a dependency of the repl
+    UnknownDependency(String),
+    /// The line number doesn't point to a line of the repl code
+    InvalidLineNumber(String),
+    /// A directory can't be used (exp: the export directory already has a cargo package)
+    InvalidDirectory {
+        dir: PathBuf,
+        /// exp: `is not a directory`
+        reason: &'static str,
+    },
+    Io(io::Error),
+}
+
+impl Display for ReplError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplError::Compile { output, .. } => write!(f, "{output}"),
+            ReplError::Run { status, output } => write!(f, "{status}\n{output}"),
+            ReplError::Cancelled => write!(f, "Cancelled!"),
+            ReplError::LimitExceeded { limit, output } => {
+                write!(f, "Limit exceeded: {limit}\n{output}")
+            }
+            ReplError::Sandbox(e) => write!(f, "{e}"),
+            ReplError::Dependency(e) => write!(f, "{e}"),
+            ReplError::CorruptedMainFile => write!(f, "main.rs file corrupted, resetting irust.."),
+            ReplError::CorruptedSession => write!(f, "Session is corrupted"),
+            ReplError::InvalidSetting { reason, .. } => write!(f, "{reason}"),
+            ReplError::UnknownSetting { setting, name } => write!(f, "Unknown {setting}: `{name}`"),
+            ReplError::Unsupported {
+                feature,
+                supported_on,
+            } => write!(f, "{feature} is only supported on {supported_on}"),
+            ReplError::Toolchain(e) => write!(f, "{e}"),
+            ReplError::NothingToUndo => write!(f, "Nothing to undo"),
+            ReplError::NothingToRedo => write!(f, "Nothing to redo"),
+            ReplError::NotDefined(name) => write!(f, "`{name}` is not defined"),
+            ReplError::UnknownDependency(name) => {
+                write!(f, "`{name}` is not a dependency of the repl")
+            }
+            ReplError::InvalidLineNumber(line) => write!(f, "Incorrect line number: {line}"),
+            ReplError::InvalidDirectory { dir, reason } => write!(f, "{} {reason}", dir.display()),
+            ReplError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReplError {
+    fn from(e: io::Error) -> Self {
+        ReplError::Io(e)
+    }
+}
@@ -0,0 +1,34 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvalMode {
+    /// Rebuild and rerun the whole repl body on each eval
+    #[default]
+    Rebuild,
+    /// Keep a long lived host process and load each new snippet into it as a dylib,
+    /// so statements are executed only once and bindings keep their runtime values
+    Persistent,
+}
+
+impl FromStr for EvalMode {
+    type Err = Box<dyn std::error::Error>;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rebuild" => Ok(EvalMode::Rebuild),
+            "persistent" => Ok(EvalMode::Persistent),
+            _ => Err("Unknown eval mode".into()),
+        }
+    }
+}
+
+impl Display for EvalMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalMode::Rebuild => write!(f, "rebuild"),
+            EvalMode::Persistent => write!(f, "persistent"),
+        }
+    }
+}
@@ -1,50 +1,71 @@
+use crate::{CustomExecutor, ReplError, Result};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr};
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum Executor {
     #[default]
     Sync,
     Tokio,
     AsyncStd,
+    /// A [CustomExecutor] of the repl, by name
+    Custom(String),
 }
 
 impl Executor {
-    pub(crate) fn main(&self) -> String {
-        match self {
-            Executor::Sync => "fn main()".into(),
-            Executor::Tokio => "#[tokio::main]async fn main()".into(),
-            Executor::AsyncStd => "#[async_std::main]async fn main()".into(),
-        }
-    }
-    /// Invokation that can be used with cargo-add
-    /// The first argument is the crate name, it should be used with cargo-rm
-    pub(crate) fn dependecy(&self) -> Option<Vec<String>> {
-        match self {
-            Executor::Sync => None,
-            Executor::Tokio => Some(vec![
-                "tokio".into(),
-                "--features".into(),
-                "macros rt-multi-thread".into(),
-            ]),
-            Executor::AsyncStd => Some(vec![
-                "async_std".into(),
-                "--features".into(),
-                "attributes".into(),
-            ]),
-        }
+    /// How the executor runs the repl code, custom executors are looked up in `custom`
+    pub(crate) fn definition(
+        &self,
+        custom: &BTreeMap<String, CustomExecutor>,
+    ) -> Result<CustomExecutor> {
+        Ok(match self {
+            Executor::Sync => CustomExecutor::default(),
+            Executor::Tokio => CustomExecutor {
+                attribute: Some("#[tokio::main]".into()),
+                block_on: Some(
+                    "tokio::runtime::Builder::new_multi_thread().enable_all().build().expect(\"irust: failed to start tokio\").block_on($$)".into(),
+                ),
+                dependency: vec![
+                    "tokio".into(),
+                    "--features".into(),
+                    "macros rt-multi-thread".into(),
+                ],
+                prelude: None,
+            },
+            Executor::AsyncStd => CustomExecutor {
+                attribute: Some("#[async_std::main]".into()),
+                block_on: Some("async_std::task::block_on($$)".into()),
+                dependency: vec![
+                    "async_std".into(),
+                    "--features".into(),
+                    "attributes".into(),
+                ],
+                prelude: None,
+            },
+            Executor::Custom(name) => custom
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ReplError::UnknownSetting {
+                    setting: "executor",
+                    name: name.clone(),
+                })?,
+        })
     }
 }
+
 impl FromStr for Executor {
     type Err = Box<dyn std::error::Error>;
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match s {
+        // The builtin executors used to be written as `Sync`, `Tokio` and `AsyncStd`
+        match s.to_lowercase().as_str() {
             "sync" => Ok(Executor::Sync),
             "tokio" => Ok(Executor::Tokio),
-            "async_std" => Ok(Executor::AsyncStd),
-            _ => Err("Unknown executor".into()),
+            "async_std" | "asyncstd" => Ok(Executor::AsyncStd),
+            _ if s.is_empty() || s.contains(char::is_whitespace) => Err("Unknown executor".into()),
+            _ => Ok(Executor::Custom(s.to_owned())),
         }
     }
 }
@@ -55,6 +76,20 @@ impl std::fmt::Display for Executor {
             Executor::Sync => write!(f, "sync"),
             Executor::Tokio => write!(f, "tokio"),
             Executor::AsyncStd => write!(f, "async_std"),
+            Executor::Custom(name) => write!(f, "{name}"),
         }
     }
 }
+
+impl TryFrom<String> for Executor {
+    type Error = Box<dyn std::error::Error>;
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Executor> for String {
+    fn from(executor: Executor) -> Self {
+        executor.to_string()
+    }
+}
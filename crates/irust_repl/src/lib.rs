@@ -3,47 +3,214 @@ pub mod cargo_cmds;
 use cargo_cmds::*;
 mod executor;
 pub use executor::Executor;
+mod custom_executor;
+pub use custom_executor::CustomExecutor;
 mod toolchain;
 pub use toolchain::ToolChain;
 mod main_result;
 pub use main_result::MainResult;
+mod custom_main_result;
+pub use custom_main_result::CustomMainResult;
 mod edition;
 pub use edition::Edition;
 mod compile_mode;
 pub use compile_mode::CompileMode;
+mod compile_profile;
+pub use compile_profile::CompileProfile;
+mod eval_mode;
+pub use eval_mode::EvalMode;
+mod run_mode;
+pub use run_mode::RunMode;
+mod limits;
+pub use limits::{Limit, Limits};
+mod compare;
+pub use compare::{CompareTarget, Comparison};
+mod deps;
+pub use deps::{Dependency, Feature};
+mod crate_source;
+pub use crate_source::CrateSource;
+#[cfg(unix)]
+mod persistent;
+
+mod diagnostic;
+use diagnostic::LineOrigin;
+pub use diagnostic::{Diagnostic, DiagnosticLevel, DiagnosticSpan, SpanOrigin, Suggestion};
+mod error;
+pub use error::ReplError;
+mod panic;
+pub use panic::{Panic, PanicLocation};
+mod progress;
+pub use progress::BuildProgress;
+#[cfg(target_os = "linux")]
+mod pty;
+#[cfg(target_os = "linux")]
+mod sandbox;
+mod session;
+pub use session::Session;
+mod snapshot;
+pub use snapshot::Commit;
+use snapshot::{DependencyFiles, MAX_COMMITS, Settings, Snapshot};
+mod statements;
+use statements::Definition;
+mod vars;
 
 mod utils;
 
 use std::{
+    collections::BTreeMap,
     io::{self, Write},
-    path::PathBuf,
+    ops::Range,
+    path::{Path, PathBuf},
     process::{Child, ExitStatus},
+    time::Duration,
 };
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+type Result<T> = std::result::Result<T, ReplError>;
 
 pub static DEFAULT_EVALUATOR: LazyLock<[String; 2]> =
     LazyLock::new(|| ["println!(\"{:?}\", {\n".into(), "\n});".into()]);
 
 pub struct EvalConfig<'a, S: ToString> {
     pub input: S,
+    /// Called repeatedly while cargo builds the input (without a stdin) and while the program
+    /// runs, it can cancel them by killing the process and returning an error (exp: on ctrl-c)
     pub interactive_function: Option<fn(&mut Child) -> Result<()>>,
+    /// Called with the program output as it arrives, the chunks add up to
+    /// [EvalResult::stdout] and [EvalResult::stderr]
+    pub output_function: Option<&'a mut dyn FnMut(OutputChunk)>,
+    /// Called each time cargo makes progress while building the input
+    pub progress_function: Option<&'a mut dyn FnMut(&BuildProgress)>,
     pub color: bool,
     pub evaluator: &'a [String],
     pub compile_mode: CompileMode,
 }
 
-#[derive(Debug)]
+/// The functions that follow an evaluation, see [EvalConfig]
+#[derive(Default)]
+pub struct EvalCallbacks<'a> {
+    /// See [EvalConfig::interactive_function]
+    pub interactive_function: Option<fn(&mut Child) -> Result<()>>,
+    pub output_function: Option<&'a mut dyn FnMut(OutputChunk)>,
+    pub progress_function: Option<&'a mut dyn FnMut(&BuildProgress)>,
+}
+
+impl EvalCallbacks<'_> {
+    /// Lend the callbacks to a call, so they can be used again after it
+    pub(crate) fn reborrow(&mut self) -> EvalCallbacks<'_> {
+        EvalCallbacks {
+            interactive_function: self.interactive_function,
+            output_function: self
+                .output_function
+                .as_mut()
+                .map(|f| &mut **f as &mut dyn FnMut(OutputChunk)),
+            progress_function: self
+                .progress_function
+                .as_mut()
+                .map(|f| &mut **f as &mut dyn FnMut(&BuildProgress)),
+        }
+    }
+}
+
+/// A piece of the program output, see [EvalConfig::output_function]
+#[derive(Debug, Clone, Copy)]
+pub enum OutputChunk<'a> {
+    Stdout(&'a [u8]),
+    /// Never used by the pty run mode, the terminal merges stderr into stdout
+    Stderr(&'a [u8]),
+}
+
+#[derive(Debug, Clone)]
 pub struct EvalResult {
+    /// The program stdout and stderr (joined by IRUST_INTERNAL_STDERR_START), or the compiler
+    /// output if it didn't run
     pub output: String,
     pub status: ExitStatus,
+    /// Compiler errors and warnings of the build
+    pub diagnostics: Vec<Diagnostic>,
+    /// The program stdout, empty if it didn't run
+    pub stdout: String,
+    /// The program stderr, empty if it didn't run
+    pub stderr: String,
+    /// Wall-clock time of the program run, None if it didn't run
+    pub run_time: Option<Duration>,
+    /// Set if the program panicked
+    pub panic: Option<Panic>,
+    /// Set if the repl code returned an error (see [MainResult]), it's the error report (its
+    /// `Debug` output)
+    pub returned_error: Option<String>,
+    /// Set if the program was stopped for going over one of the repl [Limits]
+    pub limit_exceeded: Option<Limit>,
 }
 
-impl From<(ExitStatus, String)> for EvalResult {
-    fn from(result: (ExitStatus, String)) -> Self {
+impl From<(ExitStatus, String, Vec<Diagnostic>)> for EvalResult {
+    fn from(result: (ExitStatus, String, Vec<Diagnostic>)) -> Self {
         Self {
             output: result.1,
             status: result.0,
+            diagnostics: result.2,
+            stdout: String::new(),
+            stderr: String::new(),
+            run_time: None,
+            panic: None,
+            returned_error: None,
+            limit_exceeded: None,
+        }
+    }
+}
+
+impl EvalResult {
+    /// `main_result` is what the program returned, it tells if it can report an error
+    pub(crate) fn from_run(
+        output: std::process::Output,
+        run_time: Duration,
+        diagnostics: Vec<Diagnostic>,
+        main_result: &CustomMainResult,
+    ) -> Self {
+        let (stdout, stderr) = utils::split_stdout_and_stderr(&output);
+        // The program can print a panic message and still succeed (exp: a panicked thread)
+        let panic = if output.status.success() {
+            None
+        } else {
+            Panic::from_stderr(&stderr)
+        };
+        Self {
+            output: utils::join_stdout_and_stderr(stdout.clone(), &stderr),
+            status: output.status,
+            diagnostics,
+            returned_error: main_result::returned_error(
+                main_result,
+                output.status,
+                panic.is_some(),
+                &stderr,
+            ),
+            panic,
+            stdout,
+            stderr,
+            run_time: Some(run_time),
+            limit_exceeded: None,
+        }
+    }
+
+    /// Turn a failed evaluation into [ReplError::Compile] if the compiler reported errors, into
+    /// [ReplError::LimitExceeded] if it went over a limit, or into [ReplError::Run] otherwise
+    pub fn into_result(self) -> Result<Self> {
+        if let Some(limit) = self.limit_exceeded {
+            Err(ReplError::LimitExceeded {
+                limit,
+                output: self.output,
+            })
+        } else if self.status.success() {
+            Ok(self)
+        } else if self.diagnostics.iter().any(Diagnostic::is_error) {
+            Err(ReplError::Compile {
+                diagnostics: self.diagnostics,
+                output: self.output,
+            })
+        } else {
+            Err(ReplError::Run {
+                status: self.status,
+                output: self.output,
+            })
         }
     }
 }
@@ -51,12 +218,32 @@ impl From<(ExitStatus, String)> for EvalResult {
 #[derive(Debug, Clone)]
 pub struct Repl {
     body: Vec<String>,
+    /// Which user input each body line comes from (None for generated lines)
+    origins: Vec<Option<LineOrigin>>,
+    /// The number (In[n]) of the next input
+    next_input: usize,
     cursor: usize,
+    /// The states of the repl after each commit, the first one is the state it started with
+    snapshots: Vec<Snapshot>,
+    /// The current state in `snapshots`, the next ones were undone and can be redone
+    snapshots_idx: usize,
+    /// The dependencies were rewritten by a reset, the next insert records them
+    uncommitted_dependencies: bool,
     toolchain: ToolChain,
     executor: Executor,
+    /// How the current executor runs the repl code
+    executor_definition: CustomExecutor,
     main_result: MainResult,
+    /// What the repl code currently returns
+    main_result_definition: CustomMainResult,
+    custom: CustomDefinitions,
     edition: Edition,
     prelude: Option<PathBuf>,
+    eval_mode: EvalMode,
+    run_mode: RunMode,
+    limits: Limits,
+    #[cfg(unix)]
+    persistent: persistent::Persistent,
     pub cargo: Cargo,
 }
 impl Default for Repl {
@@ -71,6 +258,15 @@ impl Default for Repl {
         .expect("Paniced while trying to create repl")
     }
 }
+
+/// The executors and main results defined by the user, see [Repl::set_custom_executors] and
+/// [Repl::set_custom_main_results]
+#[derive(Debug, Clone, Default)]
+struct CustomDefinitions {
+    executors: BTreeMap<String, CustomExecutor>,
+    main_results: BTreeMap<String, CustomMainResult>,
+}
+
 impl Drop for Repl {
     fn drop(&mut self) {
         let _ = self.cargo.delete_project();
@@ -87,30 +283,80 @@ impl Repl {
         edition: Edition,
         prelude_parent_path: Option<PathBuf>,
     ) -> Result<Self> {
-        let cargo = Cargo::default();
+        Self::with_cargo(
+            Cargo::default(),
+            CustomDefinitions::default(),
+            toolchain,
+            executor,
+            main_result,
+            edition,
+            prelude_parent_path,
+        )
+    }
+
+    /// Like [Repl::new], with the repl project settings (crate source, sandbox...) of `cargo` and
+    /// the executors and main results that can be used
+    fn with_cargo(
+        cargo: Cargo,
+        custom: CustomDefinitions,
+        toolchain: ToolChain,
+        executor: Executor,
+        main_result: MainResult,
+        edition: Edition,
+        prelude_parent_path: Option<PathBuf>,
+    ) -> Result<Self> {
+        let repl = Self::with_cargo_unbuilt(
+            cargo,
+            custom,
+            toolchain,
+            executor,
+            main_result,
+            edition,
+            prelude_parent_path,
+        )?;
+        repl.build()?;
+        Ok(repl)
+    }
+
+    /// Like [Repl::with_cargo], without starting to build the dependencies in the background
+    fn with_cargo_unbuilt(
+        cargo: Cargo,
+        custom: CustomDefinitions,
+        toolchain: ToolChain,
+        executor: Executor,
+        main_result: MainResult,
+        edition: Edition,
+        prelude_parent_path: Option<PathBuf>,
+    ) -> Result<Self> {
+        let executor_definition = executor.definition(&custom.executors)?;
+        let main_result_definition = main_result.definition(&custom.main_results)?;
         // NOTE: All the code in new should always not block
         cargo.cargo_new(edition)?;
         if let Some(ref path) = prelude_parent_path {
-            cargo.cargo_new_lib_simple(path, PRELUDE_NAME)?;
+            cargo.cargo_new_lib_simple(path, PRELUDE_NAME, edition)?;
             cargo.cargo_add_prelude(path.join(PRELUDE_NAME), PRELUDE_NAME)?;
         }
         // check for required dependencies (in case of async)
-        if let Some(dependecy) = executor.dependecy() {
+        if !executor_definition.dependency.is_empty() {
             // needs to be sync
             // repl::new(Tokio)
             // repl.eval(5); // cargo-edit may not have written to Cargo.toml yet
             //
             // NOTE: This code blocks
-            cargo.cargo_add_sync(&dependecy)?;
+            cargo.cargo_add_sync(&executor_definition.dependency)?;
+        }
+        if !main_result_definition.dependency.is_empty() {
+            cargo.cargo_add_sync(&main_result_definition.dependency)?;
         }
-        cargo.cargo_build(toolchain)?;
 
-        let (header, footer) = Self::generate_body_delimiters(executor, main_result);
+        let (header, footer) =
+            Self::generate_body_delimiters(&executor_definition, &main_result_definition);
+        // The items of the repl code go before main, its statements go in main
         let (body, cursor) = if prelude_parent_path.is_some() {
             (
                 vec![
-                    header,
                     format!("#[allow(unused_imports)]use {PRELUDE_NAME}::*;"),
+                    header,
                     footer,
                     "}".to_string(),
                 ],
@@ -119,44 +365,92 @@ impl Repl {
         } else {
             (vec![header, footer, "}".to_string()], 1)
         };
-        Ok(Self {
+        let mut repl = Self {
+            origins: vec![None; body.len()],
+            next_input: 1,
             body,
             cursor,
+            snapshots: vec![],
+            snapshots_idx: 0,
+            uncommitted_dependencies: false,
             toolchain,
             executor,
+            executor_definition,
             main_result,
+            main_result_definition,
+            custom,
             edition,
             prelude: prelude_parent_path,
+            eval_mode: EvalMode::default(),
+            run_mode: RunMode::default(),
+            limits: Limits::default(),
+            #[cfg(unix)]
+            persistent: Default::default(),
             cargo,
-        })
+        };
+        repl.start_history()?;
+        Ok(repl)
     }
 
-    fn generate_body_delimiters(executor: Executor, main_result: MainResult) -> (String, String) {
+    fn generate_body_delimiters(
+        executor: &CustomExecutor,
+        main_result: &CustomMainResult,
+    ) -> (String, String) {
+        let result_open = main_result.main_wrapper(executor.attribute.is_some()).0;
         (
-            executor.main() + " -> " + main_result.ttype() + "{",
-            "#[allow(unreachable_code)]".to_string()
-                + main_result.instance()
+            executor.main()
+                + " -> "
+                + main_result.main_type()
+                + "{"
+                + &result_open
+                + &executor.main_wrapper().0,
+            Self::main_tail(executor, main_result)
                 + " // Do not write past this line (it will corrupt the repl)",
         )
     }
 
+    /// The end of main, without the repl warning comment
+    fn main_tail(executor: &CustomExecutor, main_result: &CustomMainResult) -> String {
+        "#[allow(unreachable_code)]".to_string()
+            + &main_result.success
+            + &executor.main_wrapper().1
+            + &main_result.main_wrapper(executor.attribute.is_some()).1
+    }
+
+    /// Index of the main function in the body, the lines before it are the module level items
+    fn main_idx(&self) -> usize {
+        let header =
+            Self::generate_body_delimiters(&self.executor_definition, &self.main_result_definition)
+                .0;
+        // The body can be edited by the user, items go at the top if main is not found
+        self.body
+            .iter()
+            .position(|line| line == &header)
+            .unwrap_or_default()
+    }
+
     pub fn set_executor(&mut self, executor: Executor) -> Result<()> {
+        let definition = executor.definition(&self.custom.executors)?;
+        let main_idx = self.main_idx();
         // remove old dependecy if it exists
-        if let Some(dependecy) = self.executor.dependecy() {
+        if let Some(crate_name) = self.executor_definition.dependency.first() {
             // cargo rm needs only the crate name
-            self.cargo.cargo_rm_sync(&dependecy[0])?;
+            self.cargo.cargo_rm_sync(crate_name)?;
         }
 
         // use the new executor
         self.executor = executor;
+        self.executor_definition = definition;
         // check for required dependencies (in case of async)
-        if let Some(dependecy) = executor.dependecy() {
-            self.cargo.cargo_add_sync(&dependecy)?;
+        if !self.executor_definition.dependency.is_empty() {
+            self.cargo
+                .cargo_add_sync(&self.executor_definition.dependency)?;
         }
         // finally set the correct main function
-        let (header, footer) = Self::generate_body_delimiters(self.executor, self.main_result);
+        let (header, footer) =
+            Self::generate_body_delimiters(&self.executor_definition, &self.main_result_definition);
         let footer_pos = self.body.len() - 2;
-        self.body[0] = header;
+        self.body[main_idx] = header;
         self.body[footer_pos] = footer;
         Ok(())
     }
@@ -165,52 +459,374 @@ impl Repl {
         let main_file = std::fs::read_to_string(&self.cargo.paths.main_file_extern)?;
         let lines_num = main_file.lines().count();
         if lines_num < 2 {
-            return Err("main.rs file corrupted, resetting irust..".into());
+            return Err(ReplError::CorruptedMainFile);
         }
         let cursor_pos = lines_num - 2;
 
-        self.body = main_file.lines().map(ToOwned::to_owned).collect();
+        self.set_body(main_file.lines().map(ToOwned::to_owned).collect());
         self.cursor = cursor_pos;
         Ok(())
     }
 
     pub fn hard_load(&mut self, code: impl ToString, cursor: usize) {
-        self.body = code.to_string().lines().map(ToOwned::to_owned).collect();
+        self.set_body(code.to_string().lines().map(ToOwned::to_owned).collect());
         self.cursor = cursor;
     }
 
+    /// Replace the body, unchanged lines keep their origin
+    fn set_body(&mut self, body: Vec<String>) {
+        let mut origins = Vec::with_capacity(body.len());
+        let mut old_idx = 0;
+        for line in &body {
+            let found = self.body[old_idx..].iter().position(|old| old == line);
+            origins.push(found.and_then(|idx| {
+                old_idx += idx + 1;
+                self.origins[old_idx - 1]
+            }));
+        }
+        self.body = body;
+        self.origins = origins;
+    }
+
     // Note: Insert must be followed by write_to_extern if persistance is needed
     // Or else it will be overwritten by the main_extern thread
     // Fix this
+    /// The insert is committed, see [Repl::undo]
     pub fn insert(&mut self, input: impl ToString) {
         let input = input.to_string();
-        // CRATE_ATTRIBUTE are special in the sense that they should be inserted outside of the main function
+        let input_number = self.next_input;
+        let origin = input_origin(self.next_input);
+        self.next_input += 1;
+        self.insert_with_origin(&input, origin);
+        // Inserts don't change the dependencies, unless a reset came before (exp: `:load`)
+        let dependencies = if self.uncommitted_dependencies {
+            self.dependency_files().ok()
+        } else {
+            None
+        };
+        self.push_snapshot(
+            dependencies,
+            Commit {
+                input_number: Some(input_number),
+                description: input,
+            },
+        );
+    }
+
+    /// Forget the commits, the current state becomes the initial one
+    fn start_history(&mut self) -> io::Result<()> {
+        self.snapshots = vec![Snapshot {
+            body: self.body.clone(),
+            origins: self.origins.clone(),
+            cursor: self.cursor,
+            settings: self.settings(),
+            dependencies: Some(self.dependency_files()?),
+            commit: None,
+        }];
+        self.snapshots_idx = 0;
+        self.uncommitted_dependencies = false;
+        Ok(())
+    }
+
+    /// The dependencies of the state at `idx`, from the last snapshot that kept them
+    fn snapshot_dependencies(&self, idx: usize) -> &DependencyFiles {
+        self.snapshots[..=idx]
+            .iter()
+            .rev()
+            .find_map(|snapshot| snapshot.dependencies.as_ref())
+            .expect("the initial snapshot keeps the dependencies")
+    }
+
+    /// Commits that change nothing are dropped, `dependencies` are only kept if they changed
+    fn push_snapshot(&mut self, dependencies: Option<DependencyFiles>, commit: Commit) {
+        let mut dependencies = dependencies
+            .filter(|dependencies| dependencies != self.snapshot_dependencies(self.snapshots_idx));
+        // The first build writes the lockfile of the current state, it's not a change
+        let recorded = self.snapshot_dependencies(self.snapshots_idx);
+        if recorded.lockfile.is_none()
+            && dependencies
+                .as_ref()
+                .is_some_and(|dependencies| dependencies.manifest == recorded.manifest)
+        {
+            self.snapshots[self.snapshots_idx].dependencies = dependencies.take();
+        }
+        let settings = self.settings();
+        let current = &self.snapshots[self.snapshots_idx];
+        if current.body == self.body
+            && current.cursor == self.cursor
+            && current.settings == settings
+            && dependencies.is_none()
+        {
+            return;
+        }
+        self.snapshots.truncate(self.snapshots_idx + 1);
+        self.snapshots.push(Snapshot {
+            body: self.body.clone(),
+            origins: self.origins.clone(),
+            cursor: self.cursor,
+            settings,
+            dependencies,
+            commit: Some(commit),
+        });
+        self.snapshots_idx += 1;
+        self.uncommitted_dependencies = false;
+
+        // The state after the oldest commit becomes the initial one
+        if self.snapshots.len() > MAX_COMMITS + 1 {
+            let initial = self.snapshots.remove(0);
+            self.snapshots_idx -= 1;
+            let initial_dependencies = initial.dependencies.expect("kept by the initial snapshot");
+            let initial = &mut self.snapshots[0];
+            initial.commit = None;
+            initial.dependencies.get_or_insert(initial_dependencies);
+        }
+    }
+
+    fn settings(&self) -> Settings {
+        Settings {
+            executor: self.executor.clone(),
+            main_result: self.main_result.clone(),
+            edition: self.edition,
+        }
+    }
+
+    /// Record the current state (the repl code, the executor, main result and edition, and the
+    /// dependencies) so the change can be undone, `description` says what made it (exp: `:add
+    /// rand`). Inserts are committed on their own
+    ///
+    /// The commits that were undone can't be redone anymore, and only the most recent ones are
+    /// kept
+    pub fn commit(&mut self, description: impl ToString) -> io::Result<()> {
+        let dependencies = self.dependency_files()?;
+        self.push_snapshot(
+            Some(dependencies),
+            Commit {
+                input_number: None,
+                description: description.to_string(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Go back to the state before the last commit, the dependencies (the manifest and the
+    /// lockfile) are restored if the commit changed them
+    pub fn undo(&mut self) -> Result<()> {
+        if self.snapshots_idx == 0 {
+            return Err(ReplError::NothingToUndo);
+        }
+        let restore_dependencies = self.snapshots[self.snapshots_idx].dependencies.is_some();
+        self.snapshots_idx -= 1;
+        self.apply_snapshot(restore_dependencies)
+    }
+
+    /// Apply the last undone commit again
+    pub fn redo(&mut self) -> Result<()> {
+        if self.snapshots_idx + 1 == self.snapshots.len() {
+            return Err(ReplError::NothingToRedo);
+        }
+        self.snapshots_idx += 1;
+        let restore_dependencies = self.snapshots[self.snapshots_idx].dependencies.is_some();
+        self.apply_snapshot(restore_dependencies)
+    }
+
+    fn apply_snapshot(&mut self, restore_dependencies: bool) -> Result<()> {
+        let snapshot = &self.snapshots[self.snapshots_idx];
+        let Settings {
+            executor,
+            main_result,
+            edition,
+        } = snapshot.settings.clone();
+        // The body has the main function header of these settings
+        if executor != self.executor {
+            self.executor_definition = executor.definition(&self.custom.executors)?;
+            self.executor = executor;
+        }
+        if main_result != self.main_result {
+            self.main_result_definition = main_result.definition(&self.custom.main_results)?;
+            self.main_result = main_result;
+        }
+        let edition_changed = edition != self.edition;
+        if edition_changed {
+            if let Some(path) = &self.prelude {
+                self.cargo
+                    .cargo_new_lib_simple(path, PRELUDE_NAME, edition)?;
+            }
+            self.edition = edition;
+        }
+        let snapshot = &self.snapshots[self.snapshots_idx];
+        self.body = snapshot.body.clone();
+        self.origins = snapshot.origins.clone();
+        self.cursor = snapshot.cursor;
+        // The edition is part of the manifest
+        if restore_dependencies || edition_changed {
+            self.restore_dependency_files(
+                self.edition,
+                self.snapshot_dependencies(self.snapshots_idx),
+            )?;
+            #[cfg(unix)]
+            self.persistent.restart();
+        }
+        Ok(())
+    }
+
+    /// The commits, oldest first, with whether they are applied (the others were undone)
+    pub fn commits(&self) -> impl Iterator<Item = (&Commit, bool)> {
+        self.snapshots
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, snapshot)| {
+                Some((snapshot.commit.as_ref()?, idx <= self.snapshots_idx))
+            })
+    }
+
+    /// Where `input` goes in the body: crate attributes at the top, items (fn, struct, impl,
+    /// macro_rules!...) before main like in a normal crate, or in place of the items they define
+    /// again, and statements at the cursor in main
+    pub fn insert_position(&self, input: &str) -> usize {
+        // CRATE_ATTRIBUTE are special in the sense that they should be inserted at the very top
         // #![feature(unboxed_closures)]
         // fn main() {}
         const CRATE_ATTRIBUTE: &str = "#!";
 
-        let outside_main = input.trim_start().starts_with(CRATE_ATTRIBUTE);
-        if outside_main {
-            for line in input.lines() {
-                self.body.insert(0, line.to_owned());
-                self.cursor += 1;
-            }
+        if input.trim_start().starts_with(CRATE_ATTRIBUTE) {
+            0
+        } else if statements::is_items(input) {
+            self.redefined_items(input)
+                .first()
+                .map_or_else(|| self.main_idx(), |(lines, _)| lines.start)
         } else {
-            for line in input.lines() {
-                self.body.insert(self.cursor, line.to_owned());
-                self.cursor += 1;
+            self.cursor
+        }
+    }
+
+    /// `origin` gives the origin of each input line from its index
+    fn insert_with_origin(&mut self, input: &str, origin: impl Fn(usize) -> Option<LineOrigin>) {
+        let position = self.insert_position(input);
+        // The removed lines are after the insert position
+        for (lines, kept) in self.redefined_items(input).into_iter().rev() {
+            match kept {
+                Some(kept) => self.replace_lines(lines, kept),
+                None => self.remove_lines(lines),
             }
         }
+        // The cursor is in main, so it's always after the insert position
+        for (idx, line) in input.lines().enumerate() {
+            self.body.insert(position + idx, line.to_owned());
+            self.origins.insert(position + idx, origin(idx));
+            self.cursor += 1;
+        }
+    }
+
+    /// The lines of the module level items that `input` defines again, in order, with the lines
+    /// that are kept: an inherent impl only loses the associated items that are defined again
+    fn redefined_items(&self, input: &str) -> Vec<(Range<usize>, Option<Vec<String>>)> {
+        if !statements::is_items(input) {
+            return vec![];
+        }
+        let definitions: Vec<Definition> = statements::split_statements(input)
+            .iter()
+            .filter_map(|item| Definition::parse(item))
+            .collect();
+        self.module_items()
+            .into_iter()
+            .filter(|(_, definition)| definitions.iter().any(|new| new.redefines(definition)))
+            .map(|(lines, definition)| {
+                let redefined = definition.redefined_associated_items(&definitions);
+                let kept = match &definition {
+                    Definition::Impl { items, .. }
+                        if !redefined.is_empty() && redefined.len() < items.len() =>
+                    {
+                        let impl_ = self.body[lines.clone()].join("\n");
+                        let kept = statements::without_associated_items(&impl_, &redefined);
+                        Some(kept.lines().map(ToOwned::to_owned).collect())
+                    }
+                    _ => None,
+                };
+                (lines, kept)
+            })
+            .collect()
+    }
+
+    /// The named items of the module level section with their lines
+    fn module_items(&self) -> Vec<(Range<usize>, Definition)> {
+        statements::statement_lines(&self.body[..self.main_idx()])
+            .into_iter()
+            .filter_map(|(lines, item)| Some((lines, Definition::parse(&item)?)))
+            .collect()
+    }
+
+    /// The functions, types, impls... defined by the repl code, an input that defines one of them
+    /// again replaces it
+    pub fn definitions(&self) -> Vec<String> {
+        self.module_items()
+            .into_iter()
+            .map(|(_, definition)| definition.to_string())
+            .collect()
+    }
+
+    /// Remove the items named `name` (a function, a type...) and the impls of the type `name`
+    pub fn undef(&mut self, name: &str) -> Result<()> {
+        let undefined: Vec<Range<usize>> = self
+            .module_items()
+            .into_iter()
+            .filter(|(_, definition)| definition.is_named(name))
+            .map(|(lines, _)| lines)
+            .collect();
+        if undefined.is_empty() {
+            return Err(ReplError::NotDefined(name.to_owned()));
+        }
+        for lines in undefined.into_iter().rev() {
+            self.remove_lines(lines);
+        }
+        Ok(())
+    }
+
+    /// Make the diagnostics spans relative to the user inputs, must be called while the
+    /// evaluated input is still inserted
+    fn relocate_diagnostics(&self, diagnostics: &mut [Diagnostic]) {
+        diagnostic::relocate(
+            diagnostics,
+            "src/main.rs",
+            &|line| self.origins.get(line).copied().flatten(),
+            self.next_input,
+        );
     }
 
+    /// Same as [Repl::relocate_diagnostics] for the panic location
+    fn relocate_panic(&self, panic: &mut Option<Panic>) {
+        if let Some(panic) = panic {
+            panic.relocate(
+                "src/main.rs",
+                &|line| self.origins.get(line).copied().flatten(),
+                self.next_input,
+            );
+        }
+    }
+
+    /// Start over with an empty repl, the settings and the commits are kept
     pub fn reset(&mut self) -> Result<()> {
-        *self = Self::new(
-            self.toolchain,
-            self.executor,
-            self.main_result,
+        let eval_mode = self.eval_mode;
+        let next_input = self.next_input;
+        let snapshots = std::mem::take(&mut self.snapshots);
+        let snapshots_idx = self.snapshots_idx;
+        let run_mode = self.run_mode;
+        let limits = self.limits;
+        *self = Self::with_cargo(
+            self.cargo.fresh(),
+            std::mem::take(&mut self.custom),
+            self.toolchain.clone(),
+            self.executor.clone(),
+            self.main_result.clone(),
             self.edition,
             self.prelude.clone(),
         )?;
+        self.eval_mode = eval_mode;
+        self.next_input = next_input;
+        self.run_mode = run_mode;
+        self.limits = limits;
+        self.snapshots = snapshots;
+        self.snapshots_idx = snapshots_idx;
+        self.uncommitted_dependencies = true;
         Ok(())
     }
 
@@ -224,7 +840,13 @@ impl Repl {
     }
 
     pub fn eval(&mut self, input: impl ToString) -> Result<EvalResult> {
-        self.eval_inner(input, None, false, &*DEFAULT_EVALUATOR, CompileMode::Debug)
+        self.eval_inner(
+            input,
+            EvalCallbacks::default(),
+            false,
+            &*DEFAULT_EVALUATOR,
+            CompileMode::Debug,
+        )
     }
     //Note: These inputs should become a Config struct
     pub fn eval_with_configuration(
@@ -234,128 +856,585 @@ impl Repl {
         let EvalConfig {
             input,
             interactive_function,
+            output_function,
+            progress_function,
             color,
             evaluator,
             compile_mode,
         } = eval_config;
-        self.eval_inner(input, interactive_function, color, evaluator, compile_mode)
+        self.eval_inner(
+            input,
+            EvalCallbacks {
+                interactive_function,
+                output_function,
+                progress_function,
+            },
+            color,
+            evaluator,
+            compile_mode,
+        )
     }
 
     fn eval_inner(
         &mut self,
         input: impl ToString,
-        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+        mut callbacks: EvalCallbacks,
         color: bool,
         evaluator: &[String],
         compile_mode: CompileMode,
     ) -> Result<EvalResult> {
         let input = input.to_string();
+        #[cfg(unix)]
+        if self.eval_mode == EvalMode::Persistent {
+            let result = self.eval_persistent(input, callbacks, color, evaluator, &compile_mode);
+            self.next_input += 1;
+            return result;
+        }
         // `\n{}\n` to avoid print appearing in error messages
         let eval_statement = format!(
             "{}{}{}std::process::exit(0);", // exit(0) allows :hard_load functions to inspect variables that are used after this line
             evaluator[0], input, evaluator[1]
         );
-        let toolchain = self.toolchain;
+        let toolchain = self.toolchain.clone();
+        let run_mode = self.run_mode;
+        let limits = self.limits;
+        let main_result = self.main_result_definition.clone();
 
         let cargo = self.cargo.clone();
-        let (status, eval_result) = self.eval_in_tmp_repl(eval_statement, |_| {
-            cargo.cargo_run(
-                color,
-                compile_mode.is_release(),
-                toolchain,
-                interactive_function,
-            )
-        })?;
+        let eval_result = self.eval_in_tmp_repl_inner(
+            eval_statement,
+            evaluator_origin(&evaluator[0], &input, self.next_input),
+            true,
+            |repl| -> Result<_> {
+                let mut result = cargo.cargo_run(
+                    color,
+                    &compile_mode,
+                    &toolchain,
+                    run_mode,
+                    limits,
+                    &main_result,
+                    callbacks.reborrow(),
+                )?;
+                repl.relocate_diagnostics(&mut result.diagnostics);
+                repl.relocate_panic(&mut result.panic);
+                Ok(result)
+            },
+        );
+        self.next_input += 1;
 
-        Ok((status, eval_result).into())
+        eval_result
     }
 
     pub fn eval_build(&mut self, input: impl ToString) -> Result<EvalResult> {
         let input = input.to_string();
-        let toolchain = self.toolchain;
+        let toolchain = self.toolchain.clone();
         let cargo = self.cargo.clone();
         Ok(self
-            .eval_in_tmp_repl(input, |_| -> Result<(ExitStatus, String)> {
-                Ok(cargo.cargo_build_output(true, false, toolchain)?)
-            })?
+            .eval_in_tmp_repl(
+                input,
+                |repl| -> Result<(ExitStatus, String, Vec<Diagnostic>)> {
+                    let (status, output, mut diagnostics) = cargo.cargo_build_output(
+                        true,
+                        &CompileMode::Debug,
+                        &toolchain,
+                        None,
+                        None,
+                    )?;
+                    repl.relocate_diagnostics(&mut diagnostics);
+                    Ok((status, output, diagnostics))
+                },
+            )?
             .into())
     }
 
-    pub fn eval_check(&mut self, buffer: String) -> Result<EvalResult> {
-        let toolchain = self.toolchain;
+    /// `cargo check` with `buffer` inserted, see [EvalConfig] for the functions
+    pub fn eval_check(
+        &mut self,
+        buffer: String,
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+        mut progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<EvalResult> {
+        let toolchain = self.toolchain.clone();
         let cargo = self.cargo.clone();
         Ok(self
-            .eval_in_tmp_repl(buffer, |_| Ok(cargo.cargo_check_output(toolchain)?))?
+            .eval_in_tmp_repl(buffer, |repl| -> Result<_> {
+                let (status, output, mut diagnostics) = cargo.cargo_check_output(
+                    &toolchain,
+                    interactive_function,
+                    progress_function
+                        .as_mut()
+                        .map(|f| &mut **f as &mut dyn FnMut(&BuildProgress)),
+                )?;
+                repl.relocate_diagnostics(&mut diagnostics);
+                Ok((status, output, diagnostics))
+            })?
             .into())
     }
 
-    pub fn eval_in_tmp_repl_without_io<T>(
+    /// The closure error type only needs to be convertible from an io error, so callers can use
+    /// their own error type
+    pub fn eval_in_tmp_repl_without_io<T, E: From<io::Error>>(
         &mut self,
         input: String,
-        mut f: impl FnMut(&Self) -> Result<T>,
-    ) -> Result<T> {
+        f: impl FnMut(&Self) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        let origin = input_origin(self.next_input);
+        self.eval_in_tmp_repl_inner(input, origin, false, f)
+    }
+    pub fn eval_in_tmp_repl<T, E: From<io::Error>>(
+        &mut self,
+        input: String,
+        f: impl FnMut(&Self) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        let origin = input_origin(self.next_input);
+        self.eval_in_tmp_repl_inner(input, origin, true, f)
+    }
+
+    fn eval_in_tmp_repl_inner<T, E: From<io::Error>>(
+        &mut self,
+        input: String,
+        origin: impl Fn(usize) -> Option<LineOrigin>,
+        write: bool,
+        mut f: impl FnMut(&Self) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
         let orig_body = self.body.clone();
+        let orig_origins = self.origins.clone();
         let orig_cursor = self.cursor;
 
-        self.insert(input);
-        // self.write()?;
-        let result = f(self);
+        self.insert_with_origin(&input, origin);
+        let result = if write {
+            self.write().map_err(Into::into).and_then(|_| f(self))
+        } else {
+            f(self)
+        };
 
         self.body = orig_body;
+        self.origins = orig_origins;
         self.cursor = orig_cursor;
 
         result
     }
-    pub fn eval_in_tmp_repl<T>(
+
+    pub fn toolchain(&self) -> ToolChain {
+        self.toolchain.clone()
+    }
+
+    /// Toolchains other than the default one must be installed with rustup
+    pub fn set_toolchain(&mut self, toolchain: ToolChain) -> Result<()> {
+        toolchain.check_installed()?;
+        self.toolchain = toolchain;
+        // The persistent host is built with the toolchain when it starts
+        #[cfg(unix)]
+        self.persistent.restart();
+        Ok(())
+    }
+
+    pub fn edition(&self) -> Edition {
+        self.edition
+    }
+
+    /// Switch the repl project and the prelude crate to another edition, then check the repl code
+    /// with it
+    ///
+    /// The edition is changed even if the code doesn't compile anymore, the returned errors point
+    /// to the statements that broke. The functions follow the check, see [EvalConfig]
+    pub fn set_edition(
         &mut self,
-        input: String,
-        mut f: impl FnMut(&Self) -> Result<T>,
-    ) -> Result<T> {
-        let orig_body = self.body.clone();
-        let orig_cursor = self.cursor;
+        edition: Edition,
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+        progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<Vec<Diagnostic>> {
+        self.cargo.set_edition(edition)?;
+        if let Some(path) = &self.prelude {
+            self.cargo
+                .cargo_new_lib_simple(path, PRELUDE_NAME, edition)?;
+        }
+        self.edition = edition;
+        #[cfg(unix)]
+        self.persistent.restart();
 
-        self.insert(input);
         self.write()?;
-        let result = f(self);
+        let (_, _, mut diagnostics) = self.cargo.cargo_check_output(
+            &self.toolchain,
+            interactive_function,
+            progress_function,
+        )?;
+        self.relocate_diagnostics(&mut diagnostics);
+        diagnostics.retain(Diagnostic::is_error);
+        Ok(diagnostics)
+    }
 
-        self.body = orig_body;
-        self.cursor = orig_cursor;
+    pub fn eval_mode(&self) -> EvalMode {
+        self.eval_mode
+    }
 
-        result
+    pub fn set_eval_mode(&mut self, eval_mode: EvalMode) -> Result<()> {
+        if eval_mode == EvalMode::Persistent && !cfg!(unix) {
+            return Err(ReplError::Unsupported {
+                feature: "Persistent eval mode",
+                supported_on: "unix",
+            });
+        }
+        self.eval_mode = eval_mode;
+        // statements are executed again by the next eval
+        #[cfg(unix)]
+        self.persistent.restart();
+        Ok(())
     }
 
-    pub fn toolchain(&self) -> ToolChain {
-        self.toolchain
+    pub fn run_mode(&self) -> RunMode {
+        self.run_mode
     }
 
-    pub fn set_toolchain(&mut self, toolchain: ToolChain) {
-        self.toolchain = toolchain;
+    /// The run mode is only used by the rebuild eval mode, the persistent host is always piped
+    pub fn set_run_mode(&mut self, run_mode: RunMode) -> Result<()> {
+        if run_mode == RunMode::Pty && !cfg!(target_os = "linux") {
+            return Err(ReplError::Unsupported {
+                feature: "Pty run mode",
+                supported_on: "linux",
+            });
+        }
+        self.run_mode = run_mode;
+        Ok(())
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) -> Result<()> {
+        if limits.needs_rlimits() && !cfg!(target_os = "linux") {
+            return Err(ReplError::Unsupported {
+                feature: "Cpu time and memory limits",
+                supported_on: "linux",
+            });
+        }
+        self.limits = limits;
+        // The persistent host gets its memory limit when it starts
+        #[cfg(unix)]
+        self.persistent.restart();
+        Ok(())
+    }
+
+    pub fn sandbox(&self) -> bool {
+        self.cargo.sandbox
+    }
+
+    /// Run the evaluated programs in a sandbox (linux only): no network, a read-only filesystem
+    /// except a scratch directory (their `TMPDIR`), the home directories hidden and an allowlist of
+    /// system calls
+    ///
+    /// `IRUST_SANDBOX_SYSCALLS` allows more system calls, a comma separated list of their numbers
+    ///
+    /// Enabling it checks that the system supports it
+    pub fn set_sandbox(&mut self, sandbox: bool) -> Result<()> {
+        if sandbox {
+            #[cfg(target_os = "linux")]
+            sandbox::check(&self.cargo.paths)?;
+            #[cfg(not(target_os = "linux"))]
+            return Err(ReplError::Unsupported {
+                feature: "The sandbox",
+                supported_on: "linux",
+            });
+        }
+        self.cargo.sandbox = sandbox;
+        // The persistent host is sandboxed when it starts
+        #[cfg(unix)]
+        self.persistent.restart();
+        Ok(())
+    }
+
+    pub fn compile_profiles(&self) -> &BTreeMap<String, CompileProfile> {
+        &self.cargo.profiles
+    }
+
+    /// The profiles that can be used with [CompileMode::Profile], they replace the current ones
+    pub fn set_compile_profiles(
+        &mut self,
+        profiles: BTreeMap<String, CompileProfile>,
+    ) -> Result<()> {
+        for (name, profile) in &profiles {
+            CompileProfile::check(name, profile)?;
+        }
+        self.cargo.profiles = profiles;
+        Ok(())
+    }
+
+    pub fn crate_source(&self) -> &CrateSource {
+        &self.cargo.crate_source
+    }
+
+    /// Where the dependencies come from, anything other than [CrateSource::CratesIo] replaces
+    /// crates.io for the repl project
+    pub fn set_crate_source(&mut self, crate_source: CrateSource) -> Result<()> {
+        let crate_source = match crate_source {
+            CrateSource::Vendored(dir) => CrateSource::Vendored(existing_dir(dir)?),
+            CrateSource::LocalRegistry(dir) => CrateSource::LocalRegistry(existing_dir(dir)?),
+            source => source,
+        };
+        self.cargo.crate_source = crate_source;
+        self.cargo.write_cargo_config()?;
+        Ok(())
+    }
+
+    pub fn offline(&self) -> bool {
+        self.cargo.offline
+    }
+
+    /// Every cargo invocation of the repl runs with `--offline`, the crates must be already
+    /// downloaded or come from a local [CrateSource]
+    pub fn set_offline(&mut self, offline: bool) -> Result<()> {
+        self.cargo.offline = offline;
+        self.cargo.write_cargo_config()?;
+        Ok(())
+    }
+
+    /// The error of a failed dependency command (exp: [Repl::add_dep]), from its stderr
+    ///
+    /// It says so when the crate isn't available from the current source
+    pub fn dependency_error(&self, cargo_stderr: &str) -> String {
+        self.cargo
+            .unavailable_crate(cargo_stderr)
+            .unwrap_or_else(|| cargo_stderr.to_owned())
+    }
+
+    pub fn executor(&self) -> &Executor {
+        &self.executor
+    }
+
+    pub fn custom_executors(&self) -> &BTreeMap<String, CustomExecutor> {
+        &self.custom.executors
+    }
+
+    /// The executors that can be used with [Executor::Custom], they replace the current ones
+    ///
+    /// The current executor keeps its definition until it's set again
+    pub fn set_custom_executors(
+        &mut self,
+        executors: BTreeMap<String, CustomExecutor>,
+    ) -> Result<()> {
+        for (name, executor) in &executors {
+            CustomExecutor::check(name, executor)?;
+        }
+        if let Executor::Custom(name) = &self.executor
+            && !executors.contains_key(name)
+        {
+            return Err(ReplError::InvalidSetting {
+                setting: "executors",
+                reason: format!("`{name}` is the current executor"),
+            });
+        }
+        self.custom.executors = executors;
+        Ok(())
+    }
+
+    pub fn main_result(&self) -> &MainResult {
+        &self.main_result
+    }
+
+    /// What the current main result is made of, for the builtin ones too
+    pub fn main_result_definition(&self) -> &CustomMainResult {
+        &self.main_result_definition
+    }
+
+    pub fn custom_main_results(&self) -> &BTreeMap<String, CustomMainResult> {
+        &self.custom.main_results
+    }
+
+    /// The main results that can be used with [MainResult::Custom], they replace the current ones
+    ///
+    /// The current main result keeps its definition until it's set again
+    pub fn set_custom_main_results(
+        &mut self,
+        main_results: BTreeMap<String, CustomMainResult>,
+    ) -> Result<()> {
+        for (name, main_result) in &main_results {
+            CustomMainResult::check(name, main_result)?;
+        }
+        if let MainResult::Custom(name) = &self.main_result
+            && !main_results.contains_key(name)
+        {
+            return Err(ReplError::InvalidSetting {
+                setting: "main results",
+                reason: format!("`{name}` is the current main result"),
+            });
+        }
+        self.custom.main_results = main_results;
+        Ok(())
     }
 
-    pub fn set_main_result(&mut self, main_result: MainResult) {
+    /// What the repl code returns, the dependency of the previous main result is replaced by the
+    /// new one
+    pub fn set_main_result(&mut self, main_result: MainResult) -> Result<()> {
+        let definition = main_result.definition(&self.custom.main_results)?;
+        let main_idx = self.main_idx();
+        if let Some(crate_name) = self.main_result_definition.dependency.first() {
+            self.cargo.cargo_rm_sync(crate_name)?;
+        }
         self.main_result = main_result;
+        self.main_result_definition = definition;
+        if !self.main_result_definition.dependency.is_empty() {
+            self.cargo
+                .cargo_add_sync(&self.main_result_definition.dependency)?;
+        }
         // rebuild main fn
-        let (header, footer) = Self::generate_body_delimiters(self.executor, self.main_result);
+        let (header, footer) =
+            Self::generate_body_delimiters(&self.executor_definition, &self.main_result_definition);
         let footer_pos = self.body.len() - 2;
-        self.body[0] = header;
+        self.body[main_idx] = header;
         self.body[footer_pos] = footer;
+        Ok(())
+    }
+
+    /// Snapshot of the repl state, it can be restored later with [Repl::restore_session]
+    pub fn session(&self) -> io::Result<Session> {
+        Ok(Session {
+            body: self.body.clone(),
+            cursor: self.cursor,
+            manifest: self.manifest()?,
+            toolchain: self.toolchain.clone(),
+            executor: self.executor.clone(),
+            main_result: self.main_result.clone(),
+            edition: self.edition,
+        })
+    }
+
+    /// Replace the repl state with a saved session and start building its dependencies
+    ///
+    /// The toolchain, executor, main result and main function are checked first, the repl is left
+    /// as is if one of them is wrong
+    pub fn restore_session(&mut self, session: Session) -> Result<()> {
+        self.restore_session_unbuilt(session)?;
+        // Dependencies are shared between repls in the common target directory, so this is
+        // usually quick
+        self.build()?;
+        Ok(())
+    }
+
+    /// Like [Repl::restore_session], without starting to build the dependencies in the background
+    fn restore_session_unbuilt(&mut self, session: Session) -> Result<()> {
+        session.toolchain.check_installed()?;
+        let executor_definition = session.executor.definition(&self.custom.executors)?;
+        let main_result_definition = session.main_result.definition(&self.custom.main_results)?;
+        let header =
+            Self::generate_body_delimiters(&executor_definition, &main_result_definition).0;
+        // The cursor is in main
+        match session.body.iter().position(|line| line == &header) {
+            Some(main_idx)
+                if session.cursor > main_idx && session.cursor + 2 <= session.body.len() => {}
+            _ => return Err(ReplError::CorruptedSession),
+        }
+        self.restore_manifest(session.edition, &session.manifest)?;
+
+        self.origins = vec![None; session.body.len()];
+        self.body = session.body;
+        self.cursor = session.cursor;
+        self.toolchain = session.toolchain;
+        self.executor = session.executor;
+        self.executor_definition = executor_definition;
+        self.main_result = session.main_result;
+        self.main_result_definition = main_result_definition;
+        self.edition = session.edition;
+        #[cfg(unix)]
+        self.persistent.restart();
+        self.start_history()?;
+        self.write()?;
+        self.write_to_extern()?;
+        Ok(())
+    }
+
+    /// Cargo.toml without the parts specific to this repl instance, see [Session::manifest]
+    fn manifest(&self) -> io::Result<String> {
+        let manifest = std::fs::read_to_string(&self.cargo.paths.cargo_toml_file)?;
+        Ok(session::strip_manifest(&manifest, PRELUDE_NAME))
+    }
+
+    /// Write a manifest made by [Repl::manifest] back, with the prelude dependency of this repl
+    fn restore_manifest(&self, edition: Edition, manifest: &str) -> io::Result<()> {
+        let manifest = match &self.prelude {
+            Some(path) => session::add_dependency(
+                manifest,
+                &path_dependency(&path.join(PRELUDE_NAME), PRELUDE_NAME),
+            ),
+            None => manifest.to_owned(),
+        };
+        self.cargo.restore_manifest(edition, &manifest)
+    }
+
+    /// The manifest (see [Repl::manifest]) and the lockfile
+    fn dependency_files(&self) -> io::Result<DependencyFiles> {
+        let lockfile = match std::fs::read_to_string(self.cargo.paths.irust_dir.join("Cargo.lock"))
+        {
+            Ok(lockfile) => Some(lockfile),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(DependencyFiles {
+            manifest: self.manifest()?,
+            lockfile,
+        })
+    }
+
+    /// Write the files of [Repl::dependency_files] back
+    fn restore_dependency_files(
+        &self,
+        edition: Edition,
+        dependencies: &DependencyFiles,
+    ) -> io::Result<()> {
+        self.restore_manifest(edition, &dependencies.manifest)?;
+        match &dependencies.lockfile {
+            Some(lockfile) => {
+                std::fs::write(self.cargo.paths.irust_dir.join("Cargo.lock"), lockfile)
+            }
+            // Recorded before the first build, the lockfile is only updated by cargo
+            None => Ok(()),
+        }
     }
 
     pub fn add_dep(&self, dep: &[String]) -> std::io::Result<std::process::Child> {
         self.cargo.cargo_add(dep)
     }
 
+    pub fn remove_dep(&self, dep: &[String]) -> std::io::Result<std::process::Child> {
+        self.cargo.cargo_remove(dep)
+    }
+
+    /// Update the given dependencies to their latest compatible version, or all of them if none
+    /// is given
+    pub fn update_deps(&self, dep: &[String]) -> std::io::Result<std::process::Child> {
+        self.cargo.cargo_update(&self.toolchain, dep)
+    }
+
     pub fn build(&self) -> std::io::Result<std::process::Child> {
-        self.cargo.cargo_build(self.toolchain)
+        self.cargo.cargo_build(&self.toolchain)
     }
 
     pub fn write(&self) -> io::Result<()> {
         let mut main_file = std::fs::File::create(&self.cargo.paths.main_file)?;
-        write!(main_file, "{}", self.body.join("\n"))?;
+        write!(main_file, "{}{}", self.body.join("\n"), self.root_items())?;
 
         Ok(())
     }
 
+    /// Items of the crate root that aren't part of the body: the executor prelude, and the
+    /// prelude crate declaration for edition 2015 (its `use` paths are relative to the crate
+    /// root, so the prelude import in main doesn't resolve without it)
+    ///
+    /// They go after the body so the line numbers of the diagnostics are unchanged
+    pub(crate) fn root_items(&self) -> String {
+        let mut items = String::new();
+        if self.edition == Edition::E2015
+            && self.prelude.is_some()
+            && self.body.iter().any(|line| line.contains(PRELUDE_NAME))
+        {
+            items += &format!("\n#[allow(unused_extern_crates)]\nextern crate {PRELUDE_NAME};");
+        }
+        if let Some(prelude) = &self.executor_definition.prelude {
+            items += &format!("\n{prelude}");
+        }
+        items
+    }
+
     pub fn body(&self) -> String {
         self.body.join("\n")
     }
@@ -370,24 +1449,16 @@ impl Repl {
 
     fn write_lib(&self) -> io::Result<()> {
         let mut lib_file = std::fs::File::create(&self.cargo.paths.lib_file)?;
-        let mut body = self.body.clone();
-
-        // safe unwrap
-        let main_idx = body
-            .iter()
-            .position(|line| {
-                line == &Self::generate_body_delimiters(self.executor, self.main_result).0
-            })
-            .unwrap();
-        body.remove(main_idx); // remove fn main
-        body.pop(); // remove result type [() | Ok(())]
-        body.pop(); // remove last }
-
-        write!(lib_file, "{}", body.join("\n"))?;
+        write!(lib_file, "{}", self.lib_body())?;
 
         Ok(())
     }
 
+    /// The module level section of the body, without the main function
+    fn lib_body(&self) -> String {
+        self.body[..self.main_idx()].join("\n") + &self.root_items()
+    }
+
     fn remove_lib(&self) -> io::Result<()> {
         std::fs::remove_file(&self.cargo.paths.lib_file)
     }
@@ -399,24 +1470,136 @@ impl Repl {
         Ok(r)
     }
 
-    pub fn pop(&mut self) {
-        if self.body.len() > 2 {
-            self.body.remove(self.cursor - 1);
-            self.cursor -= 1;
+    /// Write the repl as a standalone cargo package in `dir`, as a binary or as a library with
+    /// only the top level items
+    ///
+    /// The prelude crate is copied inside the package if it's used
+    pub fn export(&self, dir: &Path, lib: bool) -> Result<()> {
+        if dir.join("Cargo.toml").exists() {
+            return Err(ReplError::InvalidDirectory {
+                dir: dir.to_owned(),
+                reason: "already contains a cargo package",
+            });
         }
+        let name: String = dir
+            .file_name()
+            .ok_or_else(|| ReplError::InvalidDirectory {
+                dir: dir.to_owned(),
+                reason: "has no name",
+            })?
+            .to_string_lossy()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        std::fs::create_dir_all(dir.join("src"))?;
+
+        let manifest = std::fs::read_to_string(&self.cargo.paths.cargo_toml_file)?;
+        let mut manifest = session::strip_manifest(&manifest, PRELUDE_NAME);
+        if let Some(prelude) = &self.prelude
+            && self.body.iter().any(|line| line.contains(PRELUDE_NAME))
+        {
+            utils::copy_dir(&prelude.join(PRELUDE_NAME), &dir.join(PRELUDE_NAME))?;
+            manifest = session::add_dependency(
+                &manifest,
+                &path_dependency(Path::new(PRELUDE_NAME), PRELUDE_NAME),
+            );
+        }
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "\
+[package]
+name = \"{name}\"
+version = \"0.1.0\"
+edition = \"{}\"
+
+{manifest}
+",
+                self.edition
+            ),
+        )?;
+
+        if lib {
+            std::fs::write(dir.join("src/lib.rs"), self.lib_body() + "\n")?;
+        } else {
+            let mut body = self.body.clone();
+            // drop the repl warning comment
+            let footer_pos = body.len() - 2;
+            body[footer_pos] =
+                Self::main_tail(&self.executor_definition, &self.main_result_definition);
+            std::fs::write(
+                dir.join("src/main.rs"),
+                body.join("\n") + &self.root_items() + "\n",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Remove the last line of the last input, it's an item or a statement of main
+    pub fn pop(&mut self) {
+        let last_input = self
+            .origins
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, origin)| {
+                Some(((origin.as_ref()?.input, origin.as_ref()?.line), idx))
+            })
+            .max()
+            .map(|(_, idx)| idx);
+        // Lines without origin were not inserted in this session (:load, :edit...)
+        let line = match last_input {
+            Some(line) => line,
+            None if self.cursor > self.main_idx() + 1 => self.cursor - 1,
+            None => return,
+        };
+        self.remove_lines(line..line + 1);
     }
 
     pub fn del(&mut self, line_num: &str) -> Result<()> {
         if let Ok(line_num) = line_num.parse::<usize>()
-            && line_num != 0
-            && line_num + 1 < self.body.len()
+            && line_num != self.main_idx()
+            && line_num + 2 < self.body.len()
         {
-            self.body.remove(line_num);
-            self.cursor -= 1;
+            self.remove_lines(line_num..line_num + 1);
             return Ok(());
         }
 
-        Err("Incorrect line number".into())
+        Err(ReplError::InvalidLineNumber(line_num.to_owned()))
+    }
+
+    /// Replace `lines` with `new_lines`, the unchanged lines keep their origin
+    fn replace_lines(&mut self, lines: Range<usize>, new_lines: Vec<String>) {
+        let mut old_idx = lines.start;
+        let origins: Vec<Option<LineOrigin>> = new_lines
+            .iter()
+            .map(|line| {
+                let found = self.body[old_idx..lines.end]
+                    .iter()
+                    .position(|old| old == line);
+                found.and_then(|idx| {
+                    old_idx += idx + 1;
+                    self.origins[old_idx - 1]
+                })
+            })
+            .collect();
+        self.cursor -= lines.clone().filter(|line| *line < self.cursor).count();
+        if lines.start < self.cursor {
+            self.cursor += new_lines.len();
+        }
+        self.body.splice(lines.clone(), new_lines);
+        self.origins.splice(lines, origins);
+    }
+
+    fn remove_lines(&mut self, lines: Range<usize>) {
+        self.cursor -= lines.clone().filter(|line| *line < self.cursor).count();
+        self.body.drain(lines.clone());
+        self.origins.drain(lines);
     }
 
     pub fn lines(&self) -> impl Iterator<Item = &String> {
@@ -426,3 +1609,50 @@ impl Repl {
         self.body.len() - 1
     }
 }
+
+/// Origin of the lines of an input, that are inserted as is
+fn input_origin(input: usize) -> impl Fn(usize) -> Option<LineOrigin> {
+    move |line| {
+        Some(LineOrigin {
+            input,
+            line,
+            column_offset: 0,
+        })
+    }
+}
+
+/// Origin of the lines of an input wrapped by the evaluator, `prefix` is the code that precedes
+/// the input
+fn evaluator_origin(
+    prefix: &str,
+    input: &str,
+    input_number: usize,
+) -> impl Fn(usize) -> Option<LineOrigin> {
+    let prefix_lines = prefix.matches('\n').count();
+    let prefix_columns = prefix
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count();
+    let input_lines = input.lines().count().max(1);
+    move |line| {
+        let line = line.checked_sub(prefix_lines)?;
+        (line < input_lines).then_some(LineOrigin {
+            input: input_number,
+            line,
+            column_offset: if line == 0 { prefix_columns } else { 0 },
+        })
+    }
+}
+
+/// Cargo resolves the paths of its config relative to the config file, so they are made absolute
+fn existing_dir(dir: PathBuf) -> Result<PathBuf> {
+    if !dir.is_dir() {
+        return Err(ReplError::InvalidDirectory {
+            dir,
+            reason: "is not a directory",
+        });
+    }
+    Ok(dir.canonicalize()?)
+}
@@ -0,0 +1,332 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant};
+use std::{fmt::Display, io, str::FromStr};
+
+/// Limits of the evaluated program, None means unlimited
+///
+/// They are written as `timeout=10s cpu_time=5s memory=512M output=1M` (or `none`)
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Wall-clock time of the run
+    pub timeout: Option<Duration>,
+    /// CPU time of the run (linux only), enforced with `RLIMIT_CPU` so it is rounded up to seconds
+    pub cpu_time: Option<Duration>,
+    /// Address space in bytes (linux only), enforced with `RLIMIT_AS`
+    pub memory: Option<u64>,
+    /// Bytes written to stdout and stderr
+    pub output: Option<u64>,
+}
+
+/// The limit that stopped a program, see [crate::EvalResult::limit_exceeded]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Timeout,
+    CpuTime,
+    Memory,
+    Output,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Timeout => write!(f, "timeout"),
+            Limit::CpuTime => write!(f, "cpu_time"),
+            Limit::Memory => write!(f, "memory"),
+            Limit::Output => write!(f, "output"),
+        }
+    }
+}
+
+impl Limits {
+    /// Cpu time and memory limits can only be enforced on linux
+    pub fn needs_rlimits(&self) -> bool {
+        self.cpu_time.is_some() || self.memory.is_some()
+    }
+
+    /// Set the rlimits of the process that `command` spawns
+    pub(crate) fn apply(&self, command: &mut Command) {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::process::CommandExt;
+            let Limits {
+                cpu_time, memory, ..
+            } = *self;
+            // SAFETY: setrlimit is async-signal-safe and is given valid rlimits
+            unsafe {
+                command.pre_exec(move || {
+                    if let Some(cpu_time) = cpu_time
+                        && libc::setrlimit(libc::RLIMIT_CPU, &cpu_rlimit(cpu_seconds(cpu_time)))
+                            == -1
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                    if let Some(memory) = memory {
+                        let limit = libc::rlimit {
+                            rlim_cur: memory as libc::rlim_t,
+                            rlim_max: memory as libc::rlim_t,
+                        };
+                        if libc::setrlimit(libc::RLIMIT_AS, &limit) == -1 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = command;
+    }
+
+    /// Limit the cpu time the process can use from now on, for processes that run several programs
+    /// (exp: the persistent eval mode host)
+    #[cfg(target_os = "linux")]
+    pub(crate) fn limit_from_now(&self, pid: u32) -> io::Result<()> {
+        let Some(cpu_time) = self.cpu_time else {
+            return Ok(());
+        };
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat"))?;
+        // utime and stime, the fields after the command name start at the third one
+        let ticks: u64 = stat
+            .rsplit_once(')')
+            .map(|(_, fields)| fields.split_whitespace().skip(11).take(2))
+            .into_iter()
+            .flatten()
+            .filter_map(|field| field.parse::<u64>().ok())
+            .sum();
+        // SAFETY: sysconf has no preconditions
+        let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+        let limit = cpu_rlimit(ticks.div_ceil(ticks_per_second) + cpu_seconds(cpu_time));
+        // SAFETY: limit is a valid rlimit and the old limit isn't requested
+        if unsafe { libc::prlimit(pid as i32, libc::RLIMIT_CPU, &limit, std::ptr::null_mut()) }
+            == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn cpu_seconds(cpu_time: Duration) -> u64 {
+    cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() != 0)
+}
+
+#[cfg(target_os = "linux")]
+fn cpu_rlimit(seconds: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: seconds as libc::rlim_t,
+        // The process gets SIGKILL if it ignores SIGXCPU
+        rlim_max: (seconds + 1) as libc::rlim_t,
+    }
+}
+
+/// Enforce the limits that rlimits can't express while the program runs
+pub(crate) struct LimitWatch {
+    limits: Limits,
+    start: Instant,
+    output: u64,
+    /// The limit the program was killed for
+    killed_for: Option<Limit>,
+}
+
+impl LimitWatch {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            start: Instant::now(),
+            output: 0,
+            killed_for: None,
+        }
+    }
+
+    pub(crate) fn count_output(&mut self, len: usize) {
+        self.output += len as u64;
+    }
+
+    /// Kill the program if it is over the timeout or the output limit
+    pub(crate) fn enforce(&mut self, process: &mut Child) -> io::Result<()> {
+        if self.killed_for.is_some() {
+            return Ok(());
+        }
+        self.killed_for = if self
+            .limits
+            .timeout
+            .is_some_and(|timeout| self.start.elapsed() > timeout)
+        {
+            Some(Limit::Timeout)
+        } else if self
+            .limits
+            .output
+            .is_some_and(|output| self.output > output)
+        {
+            Some(Limit::Output)
+        } else {
+            return Ok(());
+        };
+        process.kill()
+    }
+
+    /// The limit that stopped the program, by being killed here or by the kernel
+    pub(crate) fn exceeded(
+        &self,
+        status: ExitStatus,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Option<Limit> {
+        if self.killed_for.is_some() {
+            return self.killed_for;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            match status.signal() {
+                Some(libc::SIGXCPU) if self.limits.cpu_time.is_some() => {
+                    return Some(Limit::CpuTime);
+                }
+                // The default allocation error handler aborts
+                Some(libc::SIGABRT) if self.limits.memory.is_some() => {
+                    let message = b"memory allocation of ";
+                    if [stdout, stderr]
+                        .iter()
+                        .any(|out| out.windows(message.len()).any(|w| w == message))
+                    {
+                        return Some(Limit::Memory);
+                    }
+                }
+                _ => (),
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (status, stdout, stderr);
+        None
+    }
+}
+
+impl FromStr for Limits {
+    type Err = Box<dyn std::error::Error>;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut limits = Limits::default();
+        if s.trim().eq_ignore_ascii_case("none") {
+            return Ok(limits);
+        }
+        for limit in s.split_whitespace() {
+            let (name, value) = limit
+                .split_once('=')
+                .ok_or("Limits are written as name=value")?;
+            let none = value.eq_ignore_ascii_case("none");
+            match name.to_lowercase().as_str() {
+                "timeout" => limits.timeout = (!none).then(|| parse_duration(value)).transpose()?,
+                "cpu_time" => {
+                    limits.cpu_time = (!none).then(|| parse_duration(value)).transpose()?
+                }
+                "memory" => limits.memory = (!none).then(|| parse_size(value)).transpose()?,
+                "output" => limits.output = (!none).then(|| parse_size(value)).transpose()?,
+                _ => return Err(format!("Unknown limit: {name}").into()),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+impl Display for Limits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limits: Vec<String> = [
+            self.timeout
+                .map(|t| format!("timeout={}", display_duration(t))),
+            self.cpu_time
+                .map(|t| format!("cpu_time={}", display_duration(t))),
+            self.memory.map(|m| format!("memory={}", display_size(m))),
+            self.output.map(|o| format!("output={}", display_size(o))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if limits.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", limits.join(" "))
+        }
+    }
+}
+
+impl TryFrom<String> for Limits {
+    type Error = Box<dyn std::error::Error>;
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Limits> for String {
+    fn from(limits: Limits) -> Self {
+        limits.to_string()
+    }
+}
+
+/// `500ms`, `10s` or `2m`
+fn parse_duration(s: &str) -> std::result::Result<Duration, Box<dyn std::error::Error>> {
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration: {s}"))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" | "" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        _ => Err(format!("Invalid duration unit: {unit}").into()),
+    }
+}
+
+fn display_duration(duration: Duration) -> String {
+    if duration.subsec_millis() != 0 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{}s", duration.as_secs())
+    }
+}
+
+/// `100`, `64K`, `512M` or `1G` (in bytes, powers of 1024)
+fn parse_size(s: &str) -> std::result::Result<u64, Box<dyn std::error::Error>> {
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let number: u64 = number.parse().map_err(|_| format!("Invalid size: {s}"))?;
+    let shift = match unit.to_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return Err(format!("Invalid size unit: {unit}").into()),
+    };
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("Size too big: {s}").into())
+}
+
+fn display_size(size: u64) -> String {
+    for (unit, shift) in [("G", 30), ("M", 20), ("K", 10)] {
+        if size != 0 && size.is_multiple_of(1 << shift) {
+            return format!("{}{unit}", size >> shift);
+        }
+    }
+    size.to_string()
+}
+
+#[test]
+fn parse_limits() {
+    let limits: Limits = "timeout=10s cpu_time=1500ms memory=512M output=1000"
+        .parse()
+        .unwrap();
+    assert_eq!(limits.timeout, Some(Duration::from_secs(10)));
+    assert_eq!(limits.memory, Some(512 * 1024 * 1024));
+    assert_eq!(
+        limits.to_string(),
+        "timeout=10s cpu_time=1500ms memory=512M output=1000"
+    );
+    assert_eq!(limits.to_string().parse::<Limits>().unwrap(), limits);
+    assert_eq!("none".parse::<Limits>().unwrap(), Limits::default());
+    assert!("timeout=10x".parse::<Limits>().is_err());
+    assert!("speed=1".parse::<Limits>().is_err());
+}
@@ -1,10 +1,12 @@
-use std::{fmt::Display, str::FromStr};
+use crate::{CustomMainResult, ReplError, Result};
+use std::{collections::BTreeMap, fmt::Display, process::ExitStatus, str::FromStr};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum MainResult {
     /// fn main() -> () {()}
     #[default]
@@ -12,21 +14,65 @@ pub enum MainResult {
     /// fn main() -> Result<(), Box<dyn std::error::Error>> {Ok(())}
     /// allows using `?` with no boilerplate
     Result,
+    /// The repl code returns `Option<()>`, so `?` works on options
+    Option,
+    /// A [CustomMainResult] of the repl, by name
+    Custom(String),
 }
 
 impl MainResult {
-    pub(crate) fn ttype(&self) -> &'static str {
-        match self {
-            Self::Unit => "()",
-            Self::Result => "Result<(), Box<dyn std::error::Error>>",
-        }
+    /// What the repl code returns, custom ones are looked up in `custom`
+    pub(crate) fn definition(
+        &self,
+        custom: &BTreeMap<String, CustomMainResult>,
+    ) -> Result<CustomMainResult> {
+        let (ttype, success) = match self {
+            Self::Unit => ("()", "()"),
+            Self::Result => ("Result<(), Box<dyn std::error::Error>>", "Ok(())"),
+            Self::Option => ("Option<()>", "Some(())"),
+            Self::Custom(name) => {
+                return custom
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| ReplError::UnknownSetting {
+                        setting: "main result",
+                        name: name.clone(),
+                    });
+            }
+        };
+        Ok(CustomMainResult {
+            ttype: ttype.to_owned(),
+            success: success.to_owned(),
+            dependency: vec![],
+        })
     }
-    pub(crate) fn instance(&self) -> &'static str {
-        match self {
-            Self::Unit => "()",
-            Self::Result => "Ok(())",
-        }
+}
+
+/// The error returned by the repl code, from the stderr of a program that failed without panicking
+///
+/// `main` prints it last as `Error: {:?}` and exits with 1, and so does the persistent eval mode.
+/// A `()` main result can't return errors, the program printed it
+pub(crate) fn returned_error(
+    main_result: &CustomMainResult,
+    status: ExitStatus,
+    panicked: bool,
+    stderr: &str,
+) -> Option<String> {
+    if !main_result.returns_errors() || panicked || status.code() != Some(1) {
+        return None;
     }
+    let start = if stderr.starts_with("Error: ") {
+        0
+    } else {
+        stderr.rfind("\nError: ")? + 1
+    };
+    Some(
+        stderr[start + "Error: ".len()..]
+            // Lines end with \r\n in the pty run mode
+            .replace("\r\n", "\n")
+            .trim_end_matches('\n')
+            .to_owned(),
+    )
 }
 
 impl FromStr for MainResult {
@@ -35,7 +81,11 @@ impl FromStr for MainResult {
         match s.to_lowercase().as_str() {
             "unit" => Ok(MainResult::Unit),
             "result" => Ok(MainResult::Result),
-            _ => Err("Unknown main result type".into()),
+            "option" => Ok(MainResult::Option),
+            _ if s.is_empty() || s.contains(char::is_whitespace) => {
+                Err("Unknown main result type".into())
+            }
+            _ => Ok(MainResult::Custom(s.to_owned())),
         }
     }
 }
@@ -44,7 +94,59 @@ impl Display for MainResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             MainResult::Unit => write!(f, "Unit"),
-            MainResult::Result => write!(f, "Result<(), Box<dyn std::error::Error>>"),
+            MainResult::Result => write!(f, "Result"),
+            MainResult::Option => write!(f, "Option"),
+            MainResult::Custom(name) => write!(f, "{name}"),
         }
     }
 }
+
+impl TryFrom<String> for MainResult {
+    type Error = Box<dyn std::error::Error>;
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<MainResult> for String {
+    fn from(main_result: MainResult) -> Self {
+        main_result.to_string()
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn parse_returned_error() {
+    use std::os::unix::process::ExitStatusExt;
+    let failure = ExitStatus::from_raw(1 << 8);
+    let result = MainResult::Result.definition(&BTreeMap::new()).unwrap();
+    let unit = MainResult::Unit.definition(&BTreeMap::new()).unwrap();
+    assert_eq!(
+        returned_error(
+            &result,
+            failure,
+            false,
+            "Error: ParseIntError { kind: InvalidDigit }\n"
+        )
+        .as_deref(),
+        Some("ParseIntError { kind: InvalidDigit }")
+    );
+    // anyhow reports span multiple lines
+    assert_eq!(
+        returned_error(
+            &result,
+            failure,
+            false,
+            "printed\nError: no config\n\nCaused by:\n    missing file\n"
+        )
+        .as_deref(),
+        Some("no config\n\nCaused by:\n    missing file")
+    );
+    assert_eq!(returned_error(&result, failure, false, "Errors: 2\n"), None);
+    assert_eq!(returned_error(&result, failure, true, "Error: x\n"), None);
+    assert_eq!(
+        returned_error(&result, ExitStatus::from_raw(0), false, "Error: x\n"),
+        None
+    );
+    assert_eq!(returned_error(&unit, failure, false, "Error: x\n"), None);
+}
@@ -0,0 +1,145 @@
+use crate::diagnostic::{LineOrigin, SpanOrigin};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A panic of the evaluated program, parsed from its stderr
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Panic {
+    pub message: String,
+    pub location: Option<PanicLocation>,
+    /// Only set if `RUST_BACKTRACE` is enabled
+    pub backtrace: Option<String>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanicLocation {
+    pub file_name: String,
+    /// 1-based
+    pub line: usize,
+    /// 1-based
+    pub column: usize,
+    /// Set if the panic comes from code typed by the user, line and column are then relative to
+    /// that input instead of the generated file
+    pub origin: Option<SpanOrigin>,
+}
+
+impl Panic {
+    /// Parse the output of the default panic hook
+    ///
+    /// ```text
+    /// thread 'main' panicked at src/main.rs:2:5:
+    /// boom
+    /// note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+    /// ```
+    pub(crate) fn from_stderr(stderr: &str) -> Option<Self> {
+        let mut lines = stderr.lines();
+        // Recent versions add the thread id: thread 'main' (1234) panicked at
+        let (_, at) = lines
+            .find(|line| line.starts_with("thread '"))?
+            .split_once(" panicked at ")?;
+        let (location, mut message) = match at.strip_suffix(':') {
+            Some(location) => (location, vec![]),
+            // Before rust 1.73: thread 'main' panicked at 'boom', src/main.rs:2:5
+            None => {
+                let (message, location) = at.rsplit_once("', ")?;
+                (location, vec![message.trim_start_matches('\'')])
+            }
+        };
+
+        let mut backtrace: Option<Vec<&str>> = None;
+        for line in lines {
+            if line.starts_with("note: ") {
+                break;
+            }
+            if line == "stack backtrace:" {
+                backtrace = Some(vec![]);
+            } else if let Some(backtrace) = &mut backtrace {
+                backtrace.push(line);
+            } else {
+                message.push(line);
+            }
+        }
+
+        Some(Self {
+            message: message.join("\n"),
+            location: PanicLocation::parse(location),
+            backtrace: backtrace.map(|backtrace| backtrace.join("\n")),
+        })
+    }
+
+    /// Make the location relative to the input it came from, see [crate::diagnostic::relocate]
+    pub(crate) fn relocate(
+        &mut self,
+        file_name: &str,
+        origin: &impl Fn(usize) -> Option<LineOrigin>,
+        current_input: usize,
+    ) {
+        let Some(location) = self
+            .location
+            .as_mut()
+            .filter(|location| location.file_name == file_name)
+        else {
+            return;
+        };
+        let Some(line_origin) = location.line.checked_sub(1).and_then(origin) else {
+            return;
+        };
+        location.line = line_origin.line + 1;
+        location.column = location
+            .column
+            .saturating_sub(line_origin.column_offset)
+            .max(1);
+        location.origin = Some(SpanOrigin {
+            input: line_origin.input,
+            current: line_origin.input == current_input,
+        });
+    }
+}
+
+impl PanicLocation {
+    /// `src/main.rs:2:5`
+    fn parse(location: &str) -> Option<Self> {
+        let mut parts = location.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        Some(Self {
+            file_name: parts.next()?.to_owned(),
+            line,
+            column,
+            origin: None,
+        })
+    }
+}
+
+#[test]
+fn parse_panic() {
+    let panic = Panic::from_stderr(
+        "warning: x\nthread 'main' panicked at src/main.rs:2:5:\nboom\nbang\nnote: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n",
+    )
+    .unwrap();
+    assert_eq!(panic.message, "boom\nbang");
+    assert_eq!(
+        panic.location,
+        Some(PanicLocation {
+            file_name: "src/main.rs".into(),
+            line: 2,
+            column: 5,
+            origin: None
+        })
+    );
+    assert_eq!(panic.backtrace, None);
+
+    let panic =
+        Panic::from_stderr("thread 'main' (42) panicked at src/main.rs:2:5:\nboom").unwrap();
+    assert_eq!(panic.message, "boom");
+
+    let panic = Panic::from_stderr(
+        "thread 'main' panicked at 'boom', src/main.rs:2:5\nstack backtrace:\n   0: main\nnote: Some details are omitted",
+    )
+    .unwrap();
+    assert_eq!(panic.message, "boom");
+    assert_eq!(panic.backtrace.as_deref(), Some("   0: main"));
+    assert!(Panic::from_stderr("error").is_none());
+}
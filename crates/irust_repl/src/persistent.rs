@@ -0,0 +1,1058 @@
+//! Persistent eval mode
+//!
+//! Instead of rebuilding and rerunning the whole main body on each eval, a long lived host
+//! process is started once per repl, and each eval is compiled to a fresh dylib (a snippet) that
+//! the host loads and runs.
+//!
+//! Statements are executed once, by the first snippet that sees them. The bindings they create are
+//! moved into a store owned by the host (`HashMap<String, Box<dyn Any>>`) and the next snippets
+//! restore them by their type name.
+//!
+//! A type name doesn't always lead back to the stored type, and a type id is only the same in code
+//! built by the same compiler with the same flags. So the host only loads the snippets built like
+//! itself, and a snippet checks the types of the values it restores before running anything. A
+//! binding that fails the check is made volatile (see below) and the snippet is built again.
+//!
+//! Limitations:
+//! - A binding is only kept if its value is `'static` and its type can be named, exp: references to
+//!   other locals, closures or types defined in the repl can't be kept. The statement that created
+//!   such a binding is marked as volatile and is rerun by each snippet (like the rebuild mode)
+//! - If a snippet fails at runtime (panic, non-zero exit, cancellation) the values it moved out of
+//!   the store are lost, so the host is restarted and the next eval reruns every statement once
+//! - Async executors get a fresh runtime per snippet, so tasks spawned by earlier evals don't survive
+use crate::{
+    BuildProgress, Cargo, CompileMode, CustomExecutor, CustomMainResult, Diagnostic, EvalCallbacks,
+    EvalResult, Limit, Limits, OutputChunk, Repl, ReplError, Result, ToolChain, diagnostic,
+    diagnostic::LineOrigin,
+    evaluator_origin,
+    limits::LimitWatch,
+    statements::{is_item, let_bindings, split_statements},
+    utils::{self, StdoutStream},
+};
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+const OUTPUT_END_MARKER: &str = "\u{0}IRUST_SNIPPET_END\u{0}";
+/// Returned by the host when a snippet wasn't built with its compiler and flags
+const INCOMPATIBLE_SNIPPET: i32 = -1;
+/// Returned by a snippet when a value of the store doesn't have the type of its binding, the
+/// bindings are in the reply
+const UNRESTORABLE_BINDINGS: i32 = -2;
+
+const HOST_SRC: &str = r#"use std::any::Any;
+use std::collections::HashMap;
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+
+#[cfg_attr(target_os = "linux", link(name = "dl"))]
+unsafe extern "C" {
+    fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+}
+const RTLD_NOW: c_int = 2;
+// Written to stdout and stderr after each snippet, must match irust OUTPUT_END_MARKER
+const OUTPUT_END_MARKER: &str = "\u{0}IRUST_SNIPPET_END\u{0}";
+// Must match irust INCOMPATIBLE_SNIPPET
+const INCOMPATIBLE_SNIPPET: i32 = -1;
+
+type Snippet = extern "C" fn(*mut c_void, *mut c_void) -> i32;
+type Build = extern "C" fn() -> u64;
+
+fn main() {
+    let socket = std::env::args().nth(1).expect("socket path");
+    let stream = UnixStream::connect(socket).expect("irust socket");
+    let mut commands = BufReader::new(stream.try_clone().expect("irust socket"));
+    let mut replies = stream;
+
+    // Snippets are never unloaded, values in the store point to their code (drop glue, vtables)
+    let mut store: HashMap<String, Box<dyn Any>> = HashMap::new();
+    let mut path = String::new();
+    while commands.read_line(&mut path).unwrap_or(0) != 0 {
+        let mut types: Vec<(String, String)> = Vec::new();
+        let status = run(path.trim_end(), &mut store, &mut types);
+
+        print!("{OUTPUT_END_MARKER}");
+        let _ = std::io::stdout().flush();
+        eprint!("{OUTPUT_END_MARKER}");
+
+        let mut reply = format!("{status}\n");
+        for (name, ty) in types {
+            reply.push_str(&format!("{name}\t{ty}\n"));
+        }
+        reply.push_str("end\n");
+        if replies.write_all(reply.as_bytes()).is_err() {
+            break;
+        }
+        path.clear();
+    }
+}
+
+fn run(path: &str, store: &mut HashMap<String, Box<dyn Any>>, types: &mut Vec<(String, String)>) -> i32 {
+    let Ok(c_path) = CString::new(path) else {
+        return 1;
+    };
+    unsafe {
+        let handle = dlopen(c_path.as_ptr(), RTLD_NOW);
+        if handle.is_null() {
+            eprintln!("irust host: failed to load {path}");
+            return 1;
+        }
+        // The store values are only usable by code built like the host
+        let build = dlsym(handle, c"irust_snippet_build".as_ptr());
+        if build.is_null() || std::mem::transmute::<*mut c_void, Build>(build)() != IRUST_BUILD {
+            return INCOMPATIBLE_SNIPPET;
+        }
+        let symbol = dlsym(handle, c"irust_snippet".as_ptr());
+        if symbol.is_null() {
+            eprintln!("irust host: {path} has no snippet entry point");
+            return 1;
+        }
+        let snippet: Snippet = std::mem::transmute(symbol);
+        snippet(store as *mut _ as *mut c_void, types as *mut _ as *mut c_void)
+    }
+}
+"#;
+
+#[derive(Debug, Default)]
+pub(crate) struct Persistent {
+    host: Option<Host>,
+    /// Repl body lines (between main header and footer) that the host already executed
+    executed_lines: Vec<String>,
+    /// The statements those lines are made of
+    executed: Vec<Statement>,
+    /// Bindings that currently live in the host store
+    bindings: Vec<Binding>,
+    snippet_count: usize,
+}
+
+// A host process can't be shared, a cloned repl starts its own host and reruns the statements once
+impl Clone for Persistent {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Statement {
+    code: String,
+    kind: StatementKind,
+    /// Rerun by each snippet because one of its bindings can't be kept in the store
+    volatile: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum StatementKind {
+    Item,
+    Let(Vec<String>),
+    Expr,
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    name: String,
+    ty: String,
+    /// Index of the executed statement that created it
+    statement: usize,
+}
+
+pub(crate) struct SnippetContext<'a> {
+    /// The module level section of the repl body, crate attributes and items
+    pub items: &'a [String],
+    pub lines: &'a [String],
+    pub executor: &'a CustomExecutor,
+    pub main_result: &'a CustomMainResult,
+    pub toolchain: &'a ToolChain,
+    pub limits: Limits,
+    pub compile_mode: &'a CompileMode,
+    pub color: bool,
+    /// In[n] of the evaluated input
+    pub input_number: usize,
+    /// See [Repl::root_items]
+    pub root_items: String,
+}
+
+struct SnippetSource {
+    code: String,
+    // (line number, binding name)
+    restores: Vec<(usize, String)>,
+    stores: Vec<(usize, String)>,
+    evaluation_start: usize,
+}
+
+impl Repl {
+    pub(crate) fn eval_persistent(
+        &mut self,
+        input: String,
+        callbacks: EvalCallbacks,
+        color: bool,
+        evaluator: &[String],
+        compile_mode: &CompileMode,
+    ) -> Result<EvalResult> {
+        let header =
+            Self::generate_body_delimiters(&self.executor_definition, &self.main_result_definition)
+                .0;
+        let main_idx = self
+            .body
+            .iter()
+            .position(|line| line == &header)
+            .ok_or(ReplError::CorruptedMainFile)?;
+        let footer_idx = self.body.len() - 2;
+
+        // Places are evaluated by reference so evaluating a binding doesn't consume it
+        let is_place = input
+            .trim()
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_'));
+        let (prefix, input) = if is_place {
+            (format!("{}&", evaluator[0]), input.trim().to_owned())
+        } else {
+            (evaluator[0].clone(), input)
+        };
+        let evaluation = format!("{}{}{}", prefix, input, evaluator[1]);
+        let evaluation_origin = evaluator_origin(&prefix, &input, self.next_input);
+
+        let context = SnippetContext {
+            items: &self.body[..main_idx],
+            lines: &self.body[main_idx + 1..footer_idx],
+            executor: &self.executor_definition,
+            main_result: &self.main_result_definition,
+            toolchain: &self.toolchain,
+            limits: self.limits,
+            compile_mode,
+            color,
+            input_number: self.next_input,
+            root_items: self.root_items(),
+        };
+        self.persistent.eval(
+            &self.cargo,
+            &context,
+            &evaluation,
+            &evaluation_origin,
+            callbacks,
+        )
+    }
+}
+
+impl Persistent {
+    /// Stop the host process and forget everything it executed
+    pub(crate) fn restart(&mut self) {
+        *self = Self::default();
+    }
+
+    fn eval(
+        &mut self,
+        cargo: &Cargo,
+        context: &SnippetContext,
+        evaluation: &str,
+        evaluation_origin: &impl Fn(usize) -> Option<LineOrigin>,
+        mut callbacks: EvalCallbacks,
+    ) -> Result<EvalResult> {
+        loop {
+            if let Some(result) = self.eval_once(
+                cargo,
+                context,
+                evaluation,
+                evaluation_origin,
+                callbacks.reborrow(),
+            )? {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// None if a binding couldn't be restored, it's volatile now and the snippet needs to be built
+    /// again
+    fn eval_once(
+        &mut self,
+        cargo: &Cargo,
+        context: &SnippetContext,
+        evaluation: &str,
+        evaluation_origin: &impl Fn(usize) -> Option<LineOrigin>,
+        callbacks: EvalCallbacks,
+    ) -> Result<Option<EvalResult>> {
+        let EvalCallbacks {
+            interactive_function,
+            output_function,
+            mut progress_function,
+        } = callbacks;
+        // The repl body was modified by something else than an insert (:pop, :del, :edit...)
+        if !context.lines.starts_with(&self.executed_lines) {
+            self.restart();
+        }
+        // The toolchain, compile mode or flags changed, the host can't load the snippets anymore
+        let build = build_id(cargo, context.toolchain, context.compile_mode)?;
+        if self.host.as_ref().is_some_and(|host| host.build != build) {
+            self.restart();
+        }
+        let pending: Vec<Statement> =
+            split_statements(&context.lines[self.executed_lines.len()..].join("\n"))
+                .into_iter()
+                .map(Statement::new)
+                .collect();
+
+        if self.host.is_none() {
+            self.host = Some(Host::start(
+                cargo,
+                context,
+                build,
+                interactive_function,
+                progress_function
+                    .as_mut()
+                    .map(|f| &mut **f as &mut dyn FnMut(&BuildProgress)),
+            )?);
+        }
+
+        // Compile the snippet, bindings that can't be restored or stored are discovered here
+        let mut consumed = HashSet::new();
+        let mut unstorable = HashSet::new();
+        // Only the evaluated input can be mapped back, the statements are rearranged by the snippet
+        let relocate = |diagnostics: &mut [Diagnostic], evaluation_start: usize| {
+            diagnostic::relocate(
+                diagnostics,
+                "src/lib.rs",
+                &|line| evaluation_origin(line.checked_sub(evaluation_start)?),
+                context.input_number,
+            )
+        };
+        let (diagnostics, evaluation_start) = loop {
+            let source =
+                self.snippet_source(context, build, &pending, evaluation, &consumed, &unstorable);
+            write_snippet_package(cargo, &source.code)?;
+            let (status, output, mut diagnostics) = cargo.cargo_build_package_output(
+                &cargo.paths.snippet_dir,
+                context.color,
+                context.compile_mode,
+                context.toolchain,
+                interactive_function,
+                progress_function
+                    .as_mut()
+                    .map(|f| &mut **f as &mut dyn FnMut(&BuildProgress)),
+            )?;
+            if status.success() {
+                relocate(&mut diagnostics, source.evaluation_start);
+                break (diagnostics, source.evaluation_start);
+            }
+
+            let mut progress = false;
+            for (line, code) in error_lines(&diagnostics) {
+                if let Some((_, name)) = source.restores.iter().find(|(l, _)| *l == line) {
+                    self.make_volatile(name);
+                    progress = true;
+                } else if let Some((_, name)) = source.stores.iter().find(|(l, _)| *l == line) {
+                    // E0382: the value was moved by the snippet, so there is nothing to keep
+                    progress |= if code.as_deref() == Some("E0382") {
+                        consumed.insert(name.clone())
+                    } else {
+                        unstorable.insert(name.clone())
+                    };
+                }
+            }
+            if !progress {
+                relocate(&mut diagnostics, source.evaluation_start);
+                return Ok(Some((status, output, diagnostics).into()));
+            }
+        };
+
+        // Each snippet gets its own path, dlopen caches libraries by path
+        self.snippet_count += 1;
+        std::fs::create_dir_all(cargo.paths.persistent_dir.join("snippets"))?;
+        let snippet_path = cargo.paths.persistent_dir.join("snippets").join(format!(
+            "snippet_{}{}",
+            self.snippet_count,
+            std::env::consts::DLL_SUFFIX
+        ));
+        std::fs::copy(
+            cargo.build_profile(context.compile_mode)?.out_dir.join(
+                cargo
+                    .paths
+                    .snippet_lib_path
+                    .file_name()
+                    .expect("the snippet library path has a file name"),
+            ),
+            &snippet_path,
+        )?;
+
+        let host = self.host.as_mut().expect("host is started");
+        let start = Instant::now();
+        let run = match host.run(
+            snippet_path,
+            &context.limits,
+            interactive_function,
+            output_function,
+        ) {
+            Ok(run) => run,
+            Err(e) => {
+                self.restart();
+                return Err(e);
+            }
+        };
+        match run.status {
+            INCOMPATIBLE_SNIPPET => {
+                self.restart();
+                return Err(ReplError::Run {
+                    status: exit_status(1),
+                    output: "The persistent host can't load a snippet built with another compiler or other flags".into(),
+                });
+            }
+            UNRESTORABLE_BINDINGS => {
+                // Nothing ran, the other values are still in the store
+                let bindings = self.bindings.len();
+                for (name, _) in &run.types {
+                    self.make_volatile(name);
+                }
+                if self.bindings.len() == bindings {
+                    self.restart();
+                }
+                return Ok(None);
+            }
+            _ => (),
+        }
+        let mut result = EvalResult::from_run(
+            Output {
+                status: exit_status(run.status),
+                stdout: run.stdout,
+                stderr: run.stderr,
+            },
+            start.elapsed(),
+            diagnostics,
+            context.main_result,
+        );
+        result.limit_exceeded = run.limit_exceeded;
+        if let Some(panic) = &mut result.panic {
+            panic.relocate(
+                "src/lib.rs",
+                &|line| evaluation_origin(line.checked_sub(evaluation_start)?),
+                context.input_number,
+            );
+        }
+
+        if run.status != 0 || !run.host_alive {
+            // Values that were moved out of the store are lost
+            self.restart();
+            return Ok(Some(result));
+        }
+
+        self.executed_lines = context.lines.to_vec();
+        for mut statement in pending {
+            if let StatementKind::Let(names) = &statement.kind {
+                statement.volatile = names.iter().any(|name| unstorable.contains(name));
+            }
+            self.executed.push(statement);
+        }
+        self.bindings
+            .retain(|binding| !consumed.contains(&binding.name));
+        for (name, ty) in run.types {
+            let Some(statement) = self.defining_statement(&name) else {
+                continue;
+            };
+            match nameable_type(&ty, &cargo.name) {
+                Some(ty) => {
+                    self.bindings.retain(|binding| binding.name != name);
+                    self.bindings.push(Binding {
+                        name,
+                        ty,
+                        statement,
+                    });
+                }
+                None => self.make_volatile(&name),
+            }
+        }
+
+        Ok(Some(result))
+    }
+
+    fn defining_statement(&self, name: &str) -> Option<usize> {
+        self.executed.iter().rposition(|statement| {
+            matches!(&statement.kind, StatementKind::Let(names) if names.iter().any(|n| n == name))
+        })
+    }
+
+    fn make_volatile(&mut self, name: &str) {
+        if let Some(idx) = self.defining_statement(name) {
+            self.executed[idx].volatile = true;
+            self.bindings.retain(|binding| binding.statement != idx);
+        }
+    }
+
+    fn snippet_source(
+        &self,
+        context: &SnippetContext,
+        build: u64,
+        pending: &[Statement],
+        evaluation: &str,
+        consumed: &HashSet<String>,
+        unstorable: &HashSet<String>,
+    ) -> SnippetSource {
+        let mut code: Vec<String> = vec![];
+        let mut restores = vec![];
+        let mut stores = vec![];
+        let push = |code: &mut Vec<String>, text: &str| {
+            code.extend(text.lines().map(ToOwned::to_owned));
+            code.len()
+        };
+
+        for item in context.items {
+            push(&mut code, item);
+        }
+        push(
+            &mut code,
+            &format!(
+                "\
+extern crate alloc;
+#[unsafe(no_mangle)]
+pub extern \"C\" fn irust_snippet_build() -> u64 {{ {build} }}
+#[unsafe(no_mangle)]
+pub extern \"C\" fn irust_snippet(irust_store: *mut std::ffi::c_void, irust_types: *mut std::ffi::c_void) -> i32 {{
+let irust_store = unsafe {{ &mut *(irust_store as *mut std::collections::HashMap<String, Box<dyn std::any::Any>>) }};
+let irust_types = unsafe {{ &mut *(irust_types as *mut Vec<(String, String)>) }};"
+            ),
+        );
+        // The values are checked before anything runs, so they can stay in the store if one fails
+        for Binding { name, ty, .. } in &self.bindings {
+            let line = push(
+                &mut code,
+                &format!(
+                    "if !irust_store.get(\"{name}\").is_some_and(|irust_value| irust_value.is::<{ty}>()) {{ irust_types.push((\"{name}\".into(), String::new())); }}"
+                ),
+            );
+            restores.push((line, name.clone()));
+        }
+        push(
+            &mut code,
+            &format!(
+                "\
+if !irust_types.is_empty() {{ return {UNRESTORABLE_BINDINGS}; }}
+let irust_result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| -> {} {{
+{}",
+                context.main_result.ttype,
+                context.executor.block_on().0
+            ),
+        );
+
+        // Items are not executed, they are needed by every snippet
+        for statement in self.executed.iter().chain(pending) {
+            if statement.kind == StatementKind::Item {
+                push(&mut code, &statement.code);
+            }
+        }
+
+        // Already executed statements, only bindings and volatile statements are needed
+        let mut live: Vec<String> = vec![];
+        for (idx, statement) in self.executed.iter().enumerate() {
+            if statement.volatile {
+                push(&mut code, &statement.code);
+                continue;
+            }
+            for binding in self.bindings.iter().filter(|b| b.statement == idx) {
+                let (name, ty) = (&binding.name, &binding.ty);
+                let line = push(
+                    &mut code,
+                    &format!(
+                        "#[allow(unused_mut)] let mut {name}: {ty} = *irust_store.remove(\"{name}\").expect(\"irust: binding `{name}` is missing\").downcast::<{ty}>().expect(\"irust: binding `{name}` changed type\");"
+                    ),
+                );
+                restores.push((line, name.clone()));
+                live.retain(|n| n != name);
+                live.push(name.clone());
+            }
+        }
+        for statement in pending {
+            if statement.kind == StatementKind::Item {
+                continue;
+            }
+            push(&mut code, &statement.code);
+            if let StatementKind::Let(names) = &statement.kind {
+                for name in names {
+                    live.retain(|n| n != name);
+                    live.push(name.clone());
+                }
+            }
+        }
+
+        let evaluation_start = code.len();
+        push(&mut code, evaluation);
+
+        for name in live
+            .iter()
+            .filter(|name| !consumed.contains(*name) && !unstorable.contains(*name))
+        {
+            let line = push(
+                &mut code,
+                &format!(
+                    "irust_types.push((\"{name}\".into(), std::any::type_name_of_val(&{name}).into())); irust_store.insert(\"{name}\".into(), Box::new({name}));"
+                ),
+            );
+            stores.push((line, name.clone()));
+        }
+
+        push(
+            &mut code,
+            &format!(
+                "\
+#[allow(unreachable_code)]
+{}
+{}
+}}));
+let _ = std::io::Write::flush(&mut std::io::stdout());
+match irust_result {{
+Ok(irust_result) => {},
+Err(_) => 101,
+}}
+}}",
+                context.main_result.success,
+                context.executor.block_on().1,
+                context.main_result.report()
+            ),
+        );
+
+        SnippetSource {
+            code: code.join("\n") + &context.root_items,
+            restores,
+            stores,
+            evaluation_start,
+        }
+    }
+}
+
+impl Statement {
+    fn new(code: String) -> Self {
+        let kind = if is_item(&code) {
+            StatementKind::Item
+        } else if let Some(names) = let_bindings(&code) {
+            StatementKind::Let(names)
+        } else {
+            StatementKind::Expr
+        };
+        Self {
+            code,
+            kind,
+            volatile: false,
+        }
+    }
+}
+
+/// Identifies the compiler and flags the host and the snippets are built with, a value of the store
+/// can only be used by code built the same way (its type id and layout may change otherwise)
+fn build_id(cargo: &Cargo, toolchain: &ToolChain, compile_mode: &CompileMode) -> Result<u64> {
+    let mut rustc = Command::new("rustc");
+    if *toolchain != ToolChain::Default {
+        rustc.arg(toolchain.as_arg());
+    }
+    let version = rustc.arg("-vV").output()?;
+    if !version.status.success() {
+        return Err(ReplError::Toolchain(format!(
+            "Failed to get the rustc version: {}",
+            String::from_utf8_lossy(&version.stderr)
+        )));
+    }
+    let profile = cargo.build_profile(compile_mode)?;
+    let rustflags = profile
+        .rustflags
+        .or_else(|| std::env::var("RUSTFLAGS").ok());
+    let mut hasher = DefaultHasher::new();
+    (version.stdout, profile.args, rustflags).hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+fn write_snippet_package(cargo: &Cargo, code: &str) -> io::Result<()> {
+    let src_dir = cargo.paths.snippet_dir.join("src");
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::write(src_dir.join("lib.rs"), code)?;
+
+    // Same dependencies as the repl, so `:add` works as usual and the shared target dir is reused
+    let manifest = std::fs::read_to_string(&cargo.paths.cargo_toml_file)?;
+    let mut snippet_manifest = String::new();
+    for line in manifest.lines() {
+        if line.trim_start().starts_with("name =") && line.contains(&format!("\"{}\"", cargo.name))
+        {
+            snippet_manifest.push_str(&format!("name = \"{}_snippet\"", cargo.name));
+        } else {
+            // The snippet package is 2 directories deeper than the repl
+            snippet_manifest.push_str(&relocate_path_dependency(line));
+        }
+        snippet_manifest.push('\n');
+    }
+    snippet_manifest.push_str("\n[lib]\ncrate-type = [\"cdylib\"]\n\n[workspace]\n");
+    write_if_changed(
+        cargo.paths.snippet_dir.join("Cargo.toml"),
+        &snippet_manifest,
+    )
+}
+
+fn relocate_path_dependency(line: &str) -> String {
+    const PATH: &str = "path = \"";
+    let Some(start) = line.find(PATH).map(|idx| idx + PATH.len()) else {
+        return line.to_owned();
+    };
+    if std::path::Path::new(&line[start..].split('"').next().unwrap_or_default()).is_absolute() {
+        return line.to_owned();
+    }
+    format!("{}../../{}", &line[..start], &line[start..])
+}
+
+// Rewriting an unchanged manifest would make cargo recheck the dependency graph on each eval
+fn write_if_changed(path: PathBuf, contents: &str) -> io::Result<()> {
+    if std::fs::read_to_string(&path).ok().as_deref() != Some(contents) {
+        std::fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Host {
+    process: Child,
+    /// See [build_id]
+    build: u64,
+    /// Started in the sandbox
+    #[cfg(target_os = "linux")]
+    sandboxed: bool,
+    commands: std::os::unix::net::UnixStream,
+    events: mpsc::Receiver<HostEvent>,
+}
+
+enum HostEvent {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    /// None means the host closed the connection
+    Reply(Option<(i32, Vec<(String, String)>)>),
+}
+
+struct Run {
+    status: i32,
+    types: Vec<(String, String)>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    host_alive: bool,
+    limit_exceeded: Option<Limit>,
+}
+
+impl Host {
+    fn start(
+        cargo: &Cargo,
+        context: &SnippetContext,
+        build: u64,
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+        progress_function: Option<&mut dyn FnMut(&BuildProgress)>,
+    ) -> Result<Self> {
+        let src_dir = cargo.paths.host_dir.join("src");
+        std::fs::create_dir_all(&src_dir)?;
+        write_if_changed(
+            src_dir.join("main.rs"),
+            &format!("{HOST_SRC}\nconst IRUST_BUILD: u64 = {build};\n"),
+        )?;
+        write_if_changed(
+            cargo.paths.host_dir.join("Cargo.toml"),
+            &format!(
+                "\
+[package]
+name = \"{}_host\"
+version = \"0.1.0\"
+edition = \"2021\"
+
+[workspace]
+",
+                cargo.name
+            ),
+        )?;
+        // Built like the snippets, see build_id
+        let (status, output, diagnostics) = cargo.cargo_build_package_output(
+            &cargo.paths.host_dir,
+            false,
+            context.compile_mode,
+            context.toolchain,
+            interactive_function,
+            progress_function,
+        )?;
+        if !status.success() {
+            return Err(ReplError::Compile {
+                diagnostics,
+                output: format!("Failed to build the persistent host:\n{output}"),
+            });
+        }
+
+        let socket_path = cargo.paths.persistent_dir.join("host.sock");
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = std::os::unix::net::UnixListener::bind(&socket_path)?;
+        listener.set_nonblocking(true)?;
+
+        let host_exe_path = cargo
+            .build_profile(context.compile_mode)?
+            .out_dir
+            .join(format!("{}_host", cargo.name));
+        let mut command = std::process::Command::new(host_exe_path);
+        // The cpu time limit is set before each snippet runs
+        Limits {
+            cpu_time: None,
+            ..context.limits
+        }
+        .apply(&mut command);
+        #[cfg(target_os = "linux")]
+        if cargo.sandbox {
+            crate::sandbox::apply(&mut command, &cargo.paths)?;
+        }
+        let mut process = command
+            .arg(&socket_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let commands = loop {
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if let Some(status) = process.try_wait()? {
+                        return Err(ReplError::Run {
+                            status,
+                            output: "The persistent host exited at startup".into(),
+                        });
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+        commands.set_nonblocking(false)?;
+
+        let (tx, events) = mpsc::channel();
+        let mut stdout = process.stdout.take().expect("stdout is piped");
+        let mut stderr = process.stderr.take().expect("stderr is piped");
+        let tx_out = tx.clone();
+        std::thread::spawn(move || forward(&mut stdout, tx_out, HostEvent::Stdout));
+        let tx_err = tx.clone();
+        std::thread::spawn(move || forward(&mut stderr, tx_err, HostEvent::Stderr));
+        let mut replies = BufReader::new(commands.try_clone()?);
+        std::thread::spawn(move || {
+            loop {
+                let reply = read_reply(&mut replies);
+                let closed = reply.is_none();
+                if tx.send(HostEvent::Reply(reply)).is_err() || closed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            process,
+            build,
+            #[cfg(target_os = "linux")]
+            sandboxed: cargo.sandbox,
+            commands,
+            events,
+        })
+    }
+
+    fn run(
+        &mut self,
+        snippet: PathBuf,
+        limits: &Limits,
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+        mut output_function: Option<&mut dyn FnMut(OutputChunk)>,
+    ) -> Result<Run> {
+        #[cfg(target_os = "linux")]
+        limits.limit_from_now(self.process.id())?;
+        let mut limit_watch = LimitWatch::new(*limits);
+        writeln!(self.commands, "{}", snippet.display())?;
+
+        let mut stdout = vec![];
+        let mut stderr = vec![];
+        // How much of the output was given to the output function
+        let (mut stdout_sent, mut stderr_sent) = (0, 0);
+        let mut stdout_stream = StdoutStream::new(b"\n");
+        let mut reply = None;
+        let (mut stdout_done, mut stderr_done) = (false, false);
+        let mut host_alive = true;
+        while reply.is_none() || !stdout_done || !stderr_done {
+            match self.events.recv_timeout(Duration::from_millis(1)) {
+                Ok(HostEvent::Stdout(chunk)) => {
+                    limit_watch.count_output(chunk.len());
+                    stdout.extend(chunk);
+                    stdout_done = strip_marker(&mut stdout);
+                    let end = unmarked_len(&stdout, stdout_done);
+                    stdout_stream.push(&stdout[stdout_sent..end], &mut output_function);
+                    stdout_sent = end;
+                }
+                Ok(HostEvent::Stderr(chunk)) => {
+                    limit_watch.count_output(chunk.len());
+                    stderr.extend(chunk);
+                    stderr_done = strip_marker(&mut stderr);
+                    let end = unmarked_len(&stderr, stderr_done);
+                    if let Some(f) = output_function.as_mut() {
+                        f(OutputChunk::Stderr(&stderr[stderr_sent..end]));
+                    }
+                    stderr_sent = end;
+                }
+                Ok(HostEvent::Reply(Some(r))) => reply = Some(r),
+                Ok(HostEvent::Reply(None)) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    host_alive = false;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+            }
+            if !host_alive || self.process.try_wait()?.is_some() {
+                // The snippet exited the host (exp: std::process::exit), collect what's left
+                let status = self.process.wait()?;
+                while let Ok(event) = self.events.recv_timeout(Duration::from_millis(100)) {
+                    match event {
+                        HostEvent::Stdout(chunk) => stdout.extend(chunk),
+                        HostEvent::Stderr(chunk) => stderr.extend(chunk),
+                        HostEvent::Reply(_) => (),
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                if self.sandboxed && crate::sandbox::violation(status) {
+                    return Err(crate::sandbox::violation_error(&stdout, &stderr));
+                }
+                let limit_exceeded = limit_watch.exceeded(status, &stdout, &stderr);
+                let status = status.code().unwrap_or(1);
+                stdout_stream.push(&stdout[stdout_sent..], &mut output_function);
+                stdout_stream.finish(status == 0, &mut output_function);
+                if let Some(f) = output_function.as_mut() {
+                    f(OutputChunk::Stderr(&stderr[stderr_sent..]));
+                }
+                return Ok(Run {
+                    status,
+                    types: vec![],
+                    stdout,
+                    stderr,
+                    host_alive: false,
+                    limit_exceeded,
+                });
+            }
+            limit_watch.enforce(&mut self.process)?;
+            if let Some(function) = interactive_function {
+                function(&mut self.process)?;
+            }
+        }
+
+        let (status, types) = reply.expect("checked");
+        stdout_stream.finish(status == 0, &mut output_function);
+        Ok(Run {
+            status,
+            types,
+            stdout,
+            stderr,
+            host_alive,
+            limit_exceeded: None,
+        })
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn forward(
+    pipe: &mut impl Read,
+    tx: mpsc::Sender<HostEvent>,
+    event: fn(Vec<u8>) -> HostEvent,
+) -> Option<()> {
+    let mut buf = [0; 4096];
+    loop {
+        let n = pipe.read(&mut buf).ok()?;
+        if n == 0 {
+            return None;
+        }
+        tx.send(event(buf[..n].to_vec())).ok()?;
+    }
+}
+
+fn read_reply(replies: &mut impl BufRead) -> Option<(i32, Vec<(String, String)>)> {
+    let mut line = String::new();
+    replies.read_line(&mut line).ok()?;
+    let status = line.trim().parse().ok()?;
+    let mut types = vec![];
+    loop {
+        line.clear();
+        if replies.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        match line.trim_end_matches('\n').split_once('\t') {
+            Some((name, ty)) => types.push((name.to_owned(), ty.to_owned())),
+            None => break,
+        }
+    }
+    Some((status, types))
+}
+
+/// The output that can't be the start of the end marker
+fn unmarked_len(output: &[u8], done: bool) -> usize {
+    if done {
+        output.len()
+    } else {
+        output.len() - utils::started_len(output, OUTPUT_END_MARKER.as_bytes())
+    }
+}
+
+fn strip_marker(output: &mut Vec<u8>) -> bool {
+    if output.ends_with(OUTPUT_END_MARKER.as_bytes()) {
+        output.truncate(output.len() - OUTPUT_END_MARKER.len());
+        true
+    } else {
+        false
+    }
+}
+
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw(code << 8)
+}
+
+/// Lines of `src/lib.rs` that are part of an error, with the error code
+fn error_lines(diagnostics: &[Diagnostic]) -> Vec<(usize, Option<String>)> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.is_error())
+        .flat_map(|diagnostic| {
+            diagnostic
+                .spans
+                .iter()
+                .filter(|span| span.file_name == "src/lib.rs")
+                .flat_map(|span| span.line_start..=span.line_end)
+                .map(|line| (line, diagnostic.code.clone()))
+        })
+        .collect()
+}
+
+/// Returns the type as it can be written in the snippet, or None if it can't be named
+fn nameable_type(ty: &str, repl_name: &str) -> Option<String> {
+    // closures, async blocks, impl Trait and types defined in the repl itself
+    if ty.contains("{{") || ty.contains("impl ") || ty.contains(repl_name) {
+        return None;
+    }
+    // type_name uses the definition path, some of them are private
+    const PRIVATE_PATHS: [(&str, &str); 5] = [
+        (
+            "std::collections::hash::map::",
+            "std::collections::hash_map::",
+        ),
+        (
+            "std::collections::hash::set::",
+            "std::collections::hash_set::",
+        ),
+        ("std::hash::random::", "std::hash::"),
+        (
+            "alloc::collections::btree::map::",
+            "alloc::collections::btree_map::",
+        ),
+        (
+            "alloc::collections::btree::set::",
+            "alloc::collections::btree_set::",
+        ),
+    ];
+    Some(
+        PRIVATE_PATHS
+            .iter()
+            .fold(ty.to_owned(), |ty, (private, public)| {
+                ty.replace(private, public)
+            }),
+    )
+}
@@ -0,0 +1,181 @@
+use crate::{OutputChunk, utils};
+use serde_json::Value;
+use std::fmt;
+
+/// How far cargo is in a build
+///
+/// Cargo json messages only tell when a compilation unit is done, the total and the units being
+/// compiled come from its progress bar
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildProgress {
+    /// Compilation units that are done (including the ones that were already up to date)
+    pub done: usize,
+    /// Compilation units of the build, unknown until cargo draws its progress bar
+    pub total: Option<usize>,
+    /// The units cargo is compiling (exp: `serde, syn`), or the last one it finished until the bar
+    /// is drawn
+    pub current: Option<String>,
+}
+
+impl fmt::Display for BuildProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.total {
+            Some(total) => write!(f, "Building [{}/{total}]", self.done)?,
+            None => write!(f, "Building [{}]", self.done)?,
+        }
+        if let Some(current) = &self.current {
+            write!(f, " {current}")?;
+        }
+        Ok(())
+    }
+}
+
+impl BuildProgress {
+    /// Update from a line of cargo json output, returns true if something changed
+    fn update_from_message(&mut self, line: &str) -> bool {
+        let Ok(message) = serde_json::from_str::<Value>(line) else {
+            return false;
+        };
+        match message.get("reason").and_then(Value::as_str) {
+            Some("compiler-artifact") => {
+                self.count_done();
+                if self.total.is_none()
+                    && let Some(name) = message
+                        .get("target")
+                        .and_then(|target| target.get("name"))
+                        .and_then(Value::as_str)
+                {
+                    self.current = Some(name.to_owned());
+                }
+                true
+            }
+            Some("build-script-executed") => {
+                self.count_done();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn count_done(&mut self) {
+        self.done += 1;
+        // Messages are also emitted for some units that the bar doesn't count
+        if let Some(total) = self.total {
+            self.done = self.done.min(total);
+        }
+    }
+
+    /// Update from a line drawn by cargo progress bar, returns true if something changed
+    fn update_from_bar(&mut self, line: &str) -> bool {
+        let Some((done, total, current)) = parse_bar(line) else {
+            return false;
+        };
+        let previous = self.clone();
+        // Json messages can arrive before the bar is redrawn
+        self.done = self.done.max(done);
+        self.total = Some(total);
+        self.current = Some(current);
+        *self != previous
+    }
+}
+
+/// Follow the output of a cargo command that runs with its progress bar enabled
+pub(crate) struct ProgressParser<'a> {
+    progress: BuildProgress,
+    /// Incomplete lines
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    progress_function: &'a mut dyn FnMut(&BuildProgress),
+}
+
+impl<'a> ProgressParser<'a> {
+    pub(crate) fn new(progress_function: &'a mut dyn FnMut(&BuildProgress)) -> Self {
+        Self {
+            progress: BuildProgress::default(),
+            stdout: vec![],
+            stderr: vec![],
+            progress_function,
+        }
+    }
+
+    pub(crate) fn push(&mut self, chunk: OutputChunk) {
+        let (buffer, bar) = match chunk {
+            OutputChunk::Stdout(out) => {
+                self.stdout.extend_from_slice(out);
+                (&mut self.stdout, false)
+            }
+            OutputChunk::Stderr(err) => {
+                self.stderr.extend_from_slice(err);
+                (&mut self.stderr, true)
+            }
+        };
+        // The bar is redrawn in place, it ends with `\r`
+        while let Some(end) = buffer
+            .iter()
+            .position(|byte| *byte == b'\n' || (bar && *byte == b'\r'))
+        {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let changed = if bar {
+                self.progress.update_from_bar(&line)
+            } else {
+                self.progress.update_from_message(&line)
+            };
+            if changed {
+                (self.progress_function)(&self.progress);
+            }
+        }
+    }
+}
+
+/// Remove the progress bar drawings from cargo stderr
+pub(crate) fn strip_bars(stderr: &[u8]) -> Vec<u8> {
+    let stderr = String::from_utf8_lossy(stderr);
+    let mut stripped = String::new();
+    for line in stderr.split_inclusive('\n') {
+        for part in line.split('\r') {
+            // The bar is erased before the next line is written over it
+            let part = part.strip_prefix("\x1b[K").unwrap_or(part);
+            if parse_bar(part).is_none() {
+                stripped.push_str(part);
+            }
+        }
+    }
+    stripped.into_bytes()
+}
+
+/// `(done, total, current)` of a progress bar line (exp: `Building [==>   ] 2/5: syn, serde`)
+fn parse_bar(line: &str) -> Option<(usize, usize, String)> {
+    let line = utils::strip_ansi(line);
+    let bar = line.trim().strip_prefix("Building")?.trim_start();
+    let (_, counts) = bar.strip_prefix('[')?.split_once("] ")?;
+    let (counts, current) = counts.split_once(": ")?;
+    let (done, total) = counts.split_once('/')?;
+    Some((
+        done.parse().ok()?,
+        total.parse().ok()?,
+        current.trim().to_owned(),
+    ))
+}
+
+#[test]
+fn parse_progress() {
+    let mut updates = vec![];
+    let mut record = |progress: &BuildProgress| updates.push(progress.to_string());
+    let mut parser = ProgressParser::new(&mut record);
+    let stderr = "\x1b[1m\x1b[92m   Compiling\x1b[0m ryu v1.0.23\n\x1b[1m\x1b[96m    Building\x1b[0m [        ] 0/3: ryu       \r\x1b[K\x1b[1m\x1b[92m   Compiling\x1b[0m itoa v1.0.18\n";
+    let (first, second) = stderr.split_at(60);
+    parser.push(OutputChunk::Stderr(first.as_bytes()));
+    parser.push(OutputChunk::Stderr(second.as_bytes()));
+    parser.push(OutputChunk::Stdout(
+        br#"{"reason":"compiler-artifact","target":{"name":"ryu"}}
+{"reason":"build-finished","success":true}
+"#,
+    ));
+    assert_eq!(updates, ["Building [0/3] ryu", "Building [1/3] ryu"]);
+
+    assert_eq!(
+        String::from_utf8(strip_bars(stderr.as_bytes())).unwrap(),
+        "\x1b[1m\x1b[92m   Compiling\x1b[0m ryu v1.0.23\n\x1b[1m\x1b[92m   Compiling\x1b[0m itoa v1.0.18\n"
+    );
+}
@@ -0,0 +1,136 @@
+//! Pty run mode
+//!
+//! The program is started in a new session with a pseudo-terminal as its controlling terminal and
+//! stdio, so `isatty` is true and it can use colors, raw mode or draw a TUI.
+//!
+//! Its output is given to the output function as it arrives and recorded as the transcript of the
+//! run.
+//! The interactive function keeps working unchanged: `Child::stdin` is the pty master, so what it
+//! writes there is what the program reads from its terminal.
+use crate::{OutputChunk, Result, limits::LimitWatch, utils::StdoutStream};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ChildStdin, Command, Output, Stdio};
+
+/// Run the program that `command` starts
+pub(crate) fn run(
+    mut command: Command,
+    function: Option<fn(&mut Child) -> Result<()>>,
+    mut output_function: Option<&mut dyn FnMut(OutputChunk)>,
+    limit_watch: &mut LimitWatch,
+) -> Result<Output> {
+    let (master, slave) = open()?;
+    command
+        .stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    // SAFETY: only async-signal-safe functions are called between fork and exec
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    // Close our copies of the slave, so reading the master fails once the program is gone
+    drop(command);
+    child.stdin = Some(ChildStdin::from(master.try_clone()?));
+
+    let mut master = File::from(master);
+    // The terminal turns `\n` into `\r\n`
+    let mut stream = StdoutStream::new(b"\r\n");
+    let mut transcript = vec![];
+    let mut buffer = [0; 64 * 1024];
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            // Drain what the program wrote before exiting
+            while let Some(n) = read(&mut master, &mut buffer, 0)? {
+                stream.push(&buffer[..n], &mut output_function);
+                transcript.extend_from_slice(&buffer[..n]);
+            }
+            break status;
+        }
+        if let Some(n) = read(&mut master, &mut buffer, 1)? {
+            stream.push(&buffer[..n], &mut output_function);
+            transcript.extend_from_slice(&buffer[..n]);
+            limit_watch.count_output(n);
+        }
+        limit_watch.enforce(&mut child)?;
+        if let Some(function) = function {
+            function(&mut child)?;
+        }
+    };
+    stream.finish(status.success(), &mut output_function);
+
+    Ok(Output {
+        status,
+        // The terminal merges stdout and stderr
+        stdout: String::from_utf8_lossy(&transcript)
+            .replace("\r\n", "\n")
+            .into_bytes(),
+        stderr: vec![],
+    })
+}
+
+/// Open a pty with the size of irust terminal
+fn open() -> io::Result<(OwnedFd, OwnedFd)> {
+    // SAFETY: the file descriptors are checked before being owned, the slave name buffer is
+    // nul terminated by ptsname_r
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+        if master == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let master = OwnedFd::from_raw_fd(master);
+        if libc::grantpt(master.as_raw_fd()) != 0 || libc::unlockpt(master.as_raw_fd()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut name = [0; 128];
+        let err = libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len());
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+        let slave = libc::open(
+            name.as_ptr(),
+            libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC,
+        );
+        if slave == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let slave = OwnedFd::from_raw_fd(slave);
+
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == -1 || size.ws_col == 0 {
+            size.ws_col = 80;
+            size.ws_row = 24;
+        }
+        libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size);
+
+        Ok((master, slave))
+    }
+}
+
+/// Read what's available from the master, None if nothing arrived after `timeout` milliseconds
+/// or if the program and its children closed the terminal
+fn read(master: &mut File, buffer: &mut [u8], timeout: i32) -> io::Result<Option<usize>> {
+    let mut fd = libc::pollfd {
+        fd: master.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: fd is a valid pollfd
+    if unsafe { libc::poll(&mut fd, 1, timeout) } <= 0 || fd.revents & libc::POLLIN == 0 {
+        return Ok(None);
+    }
+    match master.read(buffer) {
+        Ok(0) => Ok(None),
+        Ok(n) => Ok(Some(n)),
+        // The slave side was closed
+        Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,34 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RunMode {
+    /// Connect the program stdio to pipes, its output is collected and returned after it exits
+    #[default]
+    Piped,
+    /// Connect the program to a pseudo-terminal (linux only), so it can use colors, raw mode, etc..
+    /// Its output is relayed to irust stdout while it runs
+    Pty,
+}
+
+impl FromStr for RunMode {
+    type Err = Box<dyn std::error::Error>;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "piped" => Ok(RunMode::Piped),
+            "pty" => Ok(RunMode::Pty),
+            _ => Err("Unknown run mode".into()),
+        }
+    }
+}
+
+impl Display for RunMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunMode::Piped => write!(f, "piped"),
+            RunMode::Pty => write!(f, "pty"),
+        }
+    }
+}
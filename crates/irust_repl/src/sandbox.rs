@@ -0,0 +1,666 @@
+//! Sandbox of the evaluated programs (linux only)
+//!
+//! The program is started in new user, mount and network namespaces:
+//! - it has no network, only a loopback interface that is down
+//! - the filesystem is read-only, except the scratch directory that is its `TMPDIR`
+//! - `$HOME`, `/home`, `/root` and `/run/user` are hidden behind empty tmpfs mounts, only the repl
+//!   directories are put back if they live there
+//! - a seccomp filter allows a list of system calls, the ones a program needs for files, memory,
+//!   threads, processes, time, signals and local sockets. The other system calls fail with `ENOSYS`,
+//!   like on a kernel that doesn't have them, and the ones that could tamper with the sandbox or the
+//!   system (exp: `mount`, `ptrace`, `bpf`, `clone` with namespace flags) kill the program
+//!
+//! A program that needs a system call outside the list can get it allowed with the
+//! `IRUST_SANDBOX_SYSCALLS` environment variable: a comma separated list of system call numbers
+//!
+//! The kernel must allow unprivileged user namespaces and support `mount_setattr` (5.12+)
+use crate::cargo_cmds::CargoPaths;
+use crate::{ReplError, Result};
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// `AT_RECURSIVE` from `linux/fcntl.h`
+const AT_RECURSIVE: libc::c_uint = 0x8000;
+/// `MOVE_MOUNT_F_EMPTY_PATH` from `linux/mount.h`
+const MOVE_MOUNT_F_EMPTY_PATH: libc::c_uint = 0x4;
+/// The arch field of `seccomp_data`, `AUDIT_ARCH_*` from `linux/audit.h`
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+/// x32 system calls have their own numbers, they are all denied
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+/// The flags of `clone` that create namespaces
+const CLONE_NAMESPACES: libc::c_int = libc::CLONE_NEWNS
+    | libc::CLONE_NEWCGROUP
+    | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWIPC
+    | libc::CLONE_NEWUSER
+    | libc::CLONE_NEWPID
+    | libc::CLONE_NEWNET;
+/// The environment variable with the system calls to allow on top of [ALLOWED_SYSCALLS]
+const EXTRA_SYSCALLS_VAR: &str = "IRUST_SANDBOX_SYSCALLS";
+/// The directories hidden from the program, on top of `$HOME`
+const PRIVATE_DIRS: &[&str] = &["/home", "/root", "/run/user"];
+
+/// The system calls that kill the program
+const FORBIDDEN_SYSCALLS: &[libc::c_long] = &[
+    // Leaving or rearranging the sandbox
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_mount_setattr,
+    libc::SYS_move_mount,
+    libc::SYS_open_tree,
+    libc::SYS_fsopen,
+    libc::SYS_fsmount,
+    libc::SYS_fsconfig,
+    libc::SYS_fspick,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_open_by_handle_at,
+    // Inspecting or modifying other processes
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    // The kernel and the system
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_kexec_load,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_acct,
+    libc::SYS_quotactl,
+    libc::SYS_syslog,
+    libc::SYS_sethostname,
+    libc::SYS_setdomainname,
+    libc::SYS_settimeofday,
+    libc::SYS_clock_settime,
+    libc::SYS_clock_adjtime,
+    libc::SYS_adjtimex,
+];
+
+/// The system calls the program can use, `clone` is allowed without the namespace flags
+///
+/// `clone3` isn't in the list: its flags can't be checked, the C library falls back to `clone`
+const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+    // Files
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_preadv,
+    libc::SYS_pwritev,
+    libc::SYS_lseek,
+    libc::SYS_openat,
+    libc::SYS_close,
+    libc::SYS_close_range,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_flock,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_getdents64,
+    libc::SYS_getcwd,
+    libc::SYS_chdir,
+    libc::SYS_fchdir,
+    libc::SYS_readlinkat,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat2,
+    libc::SYS_symlinkat,
+    libc::SYS_linkat,
+    libc::SYS_ftruncate,
+    libc::SYS_truncate,
+    libc::SYS_fallocate,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_fchmod,
+    libc::SYS_fchmodat,
+    libc::SYS_fchown,
+    libc::SYS_fchownat,
+    libc::SYS_utimensat,
+    libc::SYS_copy_file_range,
+    libc::SYS_splice,
+    libc::SYS_tee,
+    libc::SYS_memfd_create,
+    libc::SYS_umask,
+    libc::SYS_inotify_init1,
+    libc::SYS_inotify_add_watch,
+    libc::SYS_inotify_rm_watch,
+    // Waiting on file descriptors
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_pwait2,
+    libc::SYS_eventfd2,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_settime,
+    libc::SYS_timerfd_gettime,
+    libc::SYS_signalfd4,
+    // Memory
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_mincore,
+    libc::SYS_msync,
+    libc::SYS_mlock,
+    libc::SYS_mlock2,
+    libc::SYS_munlock,
+    libc::SYS_membarrier,
+    // Threads and processes
+    libc::SYS_clone,
+    libc::SYS_execve,
+    libc::SYS_execveat,
+    libc::SYS_wait4,
+    libc::SYS_waitid,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_futex,
+    libc::SYS_futex_waitv,
+    libc::SYS_set_tid_address,
+    libc::SYS_set_robust_list,
+    libc::SYS_get_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_prctl,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_sched_setaffinity,
+    libc::SYS_sched_getparam,
+    libc::SYS_sched_getscheduler,
+    libc::SYS_getpriority,
+    libc::SYS_setpriority,
+    libc::SYS_getpid,
+    libc::SYS_getppid,
+    libc::SYS_gettid,
+    libc::SYS_getpgid,
+    libc::SYS_setpgid,
+    libc::SYS_getsid,
+    libc::SYS_setsid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_getresuid,
+    libc::SYS_getresgid,
+    libc::SYS_getgroups,
+    libc::SYS_capget,
+    libc::SYS_prlimit64,
+    libc::SYS_pidfd_open,
+    libc::SYS_pidfd_send_signal,
+    // Signals
+    libc::SYS_kill,
+    libc::SYS_tkill,
+    libc::SYS_tgkill,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_rt_sigtimedwait,
+    libc::SYS_rt_sigsuspend,
+    libc::SYS_rt_sigpending,
+    libc::SYS_sigaltstack,
+    libc::SYS_restart_syscall,
+    // Time
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_getres,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_gettimeofday,
+    libc::SYS_getitimer,
+    libc::SYS_setitimer,
+    libc::SYS_timer_create,
+    libc::SYS_timer_settime,
+    libc::SYS_timer_gettime,
+    libc::SYS_timer_delete,
+    // Sockets, the network namespace has no network but local sockets work
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept,
+    libc::SYS_accept4,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvmmsg,
+    libc::SYS_shutdown,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockopt,
+    // System information
+    libc::SYS_getrandom,
+    libc::SYS_uname,
+    libc::SYS_sysinfo,
+];
+
+/// The older system calls that x86_64 still has, aarch64 only has their `*at` variants
+#[cfg(target_arch = "x86_64")]
+const ALLOWED_LEGACY_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_open,
+    libc::SYS_creat,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_access,
+    libc::SYS_pipe,
+    libc::SYS_poll,
+    libc::SYS_select,
+    libc::SYS_dup2,
+    libc::SYS_mkdir,
+    libc::SYS_rmdir,
+    libc::SYS_unlink,
+    libc::SYS_rename,
+    libc::SYS_renameat,
+    libc::SYS_readlink,
+    libc::SYS_symlink,
+    libc::SYS_link,
+    libc::SYS_chmod,
+    libc::SYS_chown,
+    libc::SYS_lchown,
+    libc::SYS_utimes,
+    libc::SYS_getdents,
+    libc::SYS_sendfile,
+    libc::SYS_fadvise64,
+    libc::SYS_epoll_create,
+    libc::SYS_epoll_wait,
+    libc::SYS_eventfd,
+    libc::SYS_inotify_init,
+    libc::SYS_fork,
+    libc::SYS_vfork,
+    libc::SYS_arch_prctl,
+    libc::SYS_getpgrp,
+    libc::SYS_getrlimit,
+    libc::SYS_time,
+    libc::SYS_pause,
+    libc::SYS_alarm,
+];
+#[cfg(not(target_arch = "x86_64"))]
+const ALLOWED_LEGACY_SYSCALLS: &[libc::c_long] = &[];
+
+/// A directory of the repl that is put back over the tmpfs hiding its parent
+struct KeptDir {
+    path: CString,
+    /// The directories to create in the tmpfs, from the top one to `path`
+    parents: Vec<CString>,
+}
+
+/// The mounts of the sandbox, prepared before the fork
+struct Mounts {
+    scratch_dir: CString,
+    hidden: Vec<CString>,
+    kept: Vec<KeptDir>,
+}
+
+/// Start the process that `command` spawns in the sandbox, with the scratch directory of `paths` as
+/// its only writable directory
+pub(crate) fn apply(command: &mut Command, paths: &CargoPaths) -> Result<()> {
+    let arch = AUDIT_ARCH.ok_or(ReplError::Unsupported {
+        feature: "The sandbox",
+        supported_on: "x86_64 and aarch64",
+    })?;
+    std::fs::create_dir_all(&paths.sandbox_dir)?;
+    std::fs::create_dir_all(&paths.irust_target_dir)?;
+    let mounts = mounts(paths)?;
+    // SAFETY: getuid and getgid always succeed
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    // The process keeps its ids, the other ones show up as the overflow id
+    let uid_map = format!("{uid} {uid} 1");
+    let gid_map = format!("{gid} {gid} 1");
+    let filter = seccomp_filter(arch, &extra_syscalls()?);
+    let mut kept_fds = vec![-1; mounts.kept.len()];
+    command.env("TMPDIR", &paths.sandbox_dir);
+    // SAFETY: only async-signal-safe functions are called between fork and exec, everything that
+    // allocates is prepared beforehand
+    unsafe {
+        command.pre_exec(move || {
+            enter(
+                &mounts,
+                &mut kept_fds,
+                uid_map.as_bytes(),
+                gid_map.as_bytes(),
+                &filter,
+            )
+        });
+    }
+    Ok(())
+}
+
+/// Check that the sandbox can be set up on this system, by running `true` in it
+pub(crate) fn check(paths: &CargoPaths) -> Result<()> {
+    let mut command = Command::new("true");
+    apply(&mut command, paths)?;
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| ReplError::Sandbox(format!("Failed to set up the sandbox: {e}")))?;
+    if !status.success() {
+        return Err(ReplError::Sandbox(format!(
+            "Failed to set up the sandbox: test process {status}"
+        )));
+    }
+    Ok(())
+}
+
+/// The program was killed by the seccomp filter
+pub(crate) fn violation(status: ExitStatus) -> bool {
+    status.signal() == Some(libc::SIGSYS)
+}
+
+/// The error of a program killed by the seccomp filter, with what it wrote before
+pub(crate) fn violation_error(stdout: &[u8], stderr: &[u8]) -> ReplError {
+    ReplError::Sandbox(format!(
+        "Sandbox violation: the program used a forbidden system call\n{}{}",
+        String::from_utf8_lossy(stdout),
+        String::from_utf8_lossy(stderr)
+    ))
+}
+
+/// The directories to hide and the repl directories to keep (the programs, the persistent host
+/// socket and the scratch directory live there)
+fn mounts(paths: &CargoPaths) -> Result<Mounts> {
+    let mut hidden: Vec<PathBuf> = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(PRIVATE_DIRS.iter().map(PathBuf::from))
+        .filter_map(|dir| dir.canonicalize().ok())
+        .filter(|dir| dir.is_dir() && dir.parent().is_some())
+        .collect();
+    hidden.sort();
+    hidden.dedup_by(|dir, parent| dir.starts_with(parent));
+
+    let mut kept: Vec<PathBuf> = [&paths.irust_dir, &paths.irust_target_dir]
+        .into_iter()
+        .map(|dir| dir.canonicalize())
+        .collect::<io::Result<_>>()?;
+    kept.sort();
+    kept.dedup_by(|dir, parent| dir.starts_with(parent));
+
+    let kept = kept
+        .into_iter()
+        .filter_map(|dir| {
+            let hidden_dir = hidden.iter().find(|hidden| dir.starts_with(hidden))?;
+            let mut parents = dir
+                .ancestors()
+                .take_while(|parent| parent != hidden_dir)
+                .map(c_path)
+                .collect::<Result<Vec<_>>>();
+            if let Ok(parents) = &mut parents {
+                parents.reverse();
+            }
+            Some(parents.and_then(|parents| {
+                Ok(KeptDir {
+                    path: c_path(&dir)?,
+                    parents,
+                })
+            }))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Mounts {
+        scratch_dir: c_path(&paths.sandbox_dir)?,
+        hidden: hidden
+            .iter()
+            .map(|dir| c_path(dir))
+            .collect::<Result<_>>()?,
+        kept,
+    })
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        ReplError::Sandbox(format!(
+            "The sandbox path `{}` contains a nul byte",
+            path.display()
+        ))
+    })
+}
+
+/// The system call numbers of [EXTRA_SYSCALLS_VAR]
+fn extra_syscalls() -> Result<Vec<u32>> {
+    let Ok(var) = std::env::var(EXTRA_SYSCALLS_VAR) else {
+        return Ok(vec![]);
+    };
+    var.split(',')
+        .map(str::trim)
+        .filter(|number| !number.is_empty())
+        .map(|number| {
+            number.parse().map_err(|_| {
+                ReplError::Sandbox(format!(
+                    "{EXTRA_SYSCALLS_VAR}: `{number}` is not a system call number"
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Runs in the forked child before exec
+fn enter(
+    mounts: &Mounts,
+    kept_fds: &mut [libc::c_int],
+    uid_map: &[u8],
+    gid_map: &[u8],
+    filter: &[libc::sock_filter],
+) -> io::Result<()> {
+    // SAFETY: the pointers are valid nul terminated strings or null where the kernel allows it
+    unsafe {
+        check_os(libc::unshare(
+            libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET,
+        ))?;
+        write_file(c"/proc/self/setgroups", b"deny")?;
+        write_file(c"/proc/self/uid_map", uid_map)?;
+        write_file(c"/proc/self/gid_map", gid_map)?;
+
+        // Keep the changes below in this namespace
+        check_os(libc::mount(
+            std::ptr::null(),
+            c"/".as_ptr(),
+            std::ptr::null(),
+            libc::MS_REC | libc::MS_PRIVATE,
+            std::ptr::null(),
+        ))?;
+        // The scratch directory gets its own mount so it can stay writable
+        check_os(libc::mount(
+            mounts.scratch_dir.as_ptr(),
+            mounts.scratch_dir.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND | libc::MS_REC,
+            std::ptr::null(),
+        ))?;
+
+        // Copies of the repl directories, taken before their parent gets hidden
+        for (kept, fd) in mounts.kept.iter().zip(kept_fds.iter_mut()) {
+            *fd = libc::syscall(
+                libc::SYS_open_tree,
+                libc::AT_FDCWD,
+                kept.path.as_ptr(),
+                libc::OPEN_TREE_CLONE | libc::OPEN_TREE_CLOEXEC | AT_RECURSIVE,
+            ) as libc::c_int;
+            check_os(*fd)?;
+        }
+        for dir in &mounts.hidden {
+            check_os(libc::mount(
+                c"tmpfs".as_ptr(),
+                dir.as_ptr(),
+                c"tmpfs".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                c"mode=0755".as_ptr().cast(),
+            ))?;
+        }
+        for (kept, fd) in mounts.kept.iter().zip(kept_fds.iter()) {
+            for parent in &kept.parents {
+                if libc::mkdir(parent.as_ptr(), 0o755) == -1
+                    && io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST)
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            check_os(libc::syscall(
+                libc::SYS_move_mount,
+                *fd,
+                c"".as_ptr(),
+                libc::AT_FDCWD,
+                kept.path.as_ptr(),
+                MOVE_MOUNT_F_EMPTY_PATH,
+            ) as libc::c_int)?;
+            libc::close(*fd);
+        }
+
+        mount_setattr(c"/", libc::MOUNT_ATTR_RDONLY, 0)?;
+        mount_setattr(&mounts.scratch_dir, 0, libc::MOUNT_ATTR_RDONLY)?;
+
+        check_os(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+        let program = libc::sock_fprog {
+            len: filter.len() as libc::c_ushort,
+            filter: filter.as_ptr() as *mut libc::sock_filter,
+        };
+        check_os(libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &program as *const libc::sock_fprog,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Set and clear the attributes of the mounts under `path`
+fn mount_setattr(path: &CStr, set: u64, clear: u64) -> io::Result<()> {
+    let attr = libc::mount_attr {
+        attr_set: set,
+        attr_clr: clear,
+        propagation: 0,
+        userns_fd: 0,
+    };
+    // SAFETY: path is a valid nul terminated string and attr a valid mount_attr of the given size
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            AT_RECURSIVE,
+            &attr as *const libc::mount_attr,
+            std::mem::size_of::<libc::mount_attr>(),
+        )
+    };
+    check_os(result as libc::c_int)
+}
+
+fn write_file(path: &CStr, content: &[u8]) -> io::Result<()> {
+    // SAFETY: path is a valid nul terminated string and content a valid buffer
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        check_os(fd)?;
+        let written = libc::write(fd, content.as_ptr().cast(), content.len());
+        libc::close(fd);
+        if written != content.len() as isize {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn check_os(result: libc::c_int) -> io::Result<()> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Kill the process on a forbidden system call or one from another architecture, allow the listed
+/// ones and `extra`, fail the rest with `ENOSYS`
+fn seccomp_filter(arch: u32, extra: &[u32]) -> Vec<libc::sock_filter> {
+    const LOAD: u32 = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    const JEQ: u32 = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    const JSET: u32 = libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K;
+    const RET: u32 = libc::BPF_RET | libc::BPF_K;
+    // Offsets in seccomp_data, the low half of the first argument on little endian
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    const ARG0: u32 = 16;
+    let instruction = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    };
+
+    let kill = instruction(RET, libc::SECCOMP_RET_KILL_PROCESS, 0, 0);
+    let allow = instruction(RET, libc::SECCOMP_RET_ALLOW, 0, 0);
+    let mut filter = vec![
+        instruction(LOAD, ARCH, 0, 0),
+        instruction(JEQ, arch, 1, 0),
+        kill,
+        instruction(LOAD, NR, 0, 0),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        instruction(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            X32_SYSCALL_BIT,
+            0,
+            1,
+        ),
+        kill,
+    ]);
+    for syscall in FORBIDDEN_SYSCALLS {
+        filter.extend([instruction(JEQ, *syscall as u32, 0, 1), kill]);
+    }
+    filter.extend([
+        instruction(JEQ, libc::SYS_clone as u32, 0, 4),
+        instruction(LOAD, ARG0, 0, 0),
+        instruction(JSET, CLONE_NAMESPACES as u32, 0, 1),
+        kill,
+        allow,
+    ]);
+    let allowed = ALLOWED_SYSCALLS
+        .iter()
+        .chain(ALLOWED_LEGACY_SYSCALLS)
+        .map(|syscall| *syscall as u32)
+        .chain(extra.iter().copied());
+    for syscall in allowed {
+        filter.extend([instruction(JEQ, syscall, 0, 1), allow]);
+    }
+    filter.push(instruction(
+        RET,
+        libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32,
+        0,
+        0,
+    ));
+    filter
+}
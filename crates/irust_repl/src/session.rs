@@ -0,0 +1,85 @@
+use crate::{Edition, Executor, MainResult, ToolChain};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A snapshot of the repl state, see [crate::Repl::session] and [crate::Repl::restore_session]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub body: Vec<String>,
+    pub cursor: usize,
+    /// Cargo.toml without its `[package]` table and the prelude dependency (exp: `[dependencies]`)
+    pub manifest: String,
+    pub toolchain: ToolChain,
+    pub executor: Executor,
+    pub main_result: MainResult,
+    pub edition: Edition,
+}
+
+/// Remove the package table and the prelude dependency from a manifest, they are specific to
+/// this repl instance
+pub(crate) fn strip_manifest(manifest: &str, prelude_name: &str) -> String {
+    let mut in_package = false;
+    manifest
+        .lines()
+        .filter(|line| {
+            let line = line.trim_start();
+            if line.starts_with('[') {
+                in_package = line.starts_with("[package]") || line.starts_with("[package.");
+            }
+            // `irust_prelude = ...` or `irust_prelude.path = ...`, not `irust_prelude_extra`
+            let is_prelude = line
+                .strip_prefix(prelude_name)
+                .is_some_and(|rest| rest.trim_start().starts_with(['=', '.']));
+            !in_package && !is_prelude
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_owned()
+}
+
+/// Add a dependency line to the `[dependencies]` table of a stripped manifest
+pub(crate) fn add_dependency(manifest: &str, dependency: &str) -> String {
+    let mut lines: Vec<&str> = manifest.lines().collect();
+    match lines
+        .iter()
+        .position(|line| line.trim() == "[dependencies]")
+    {
+        Some(idx) => lines.insert(idx + 1, dependency),
+        None => {
+            lines.insert(0, "[dependencies]");
+            lines.insert(1, dependency);
+        }
+    }
+    lines.join("\n")
+}
+
+#[test]
+fn strip_and_restore_manifest() {
+    let manifest = "\
+[package]
+name = \"irust_host_repl_1\"
+edition = \"2024\"
+
+[package.metadata.x]
+a = 1
+
+[dependencies]
+irust_prelude = { path = \"/data/irust/irust_prelude\" }
+irust_prelude_extra = \"1\"
+regex = \"1\"
+
+[features]
+f = []";
+    let stripped = strip_manifest(manifest, "irust_prelude");
+    assert_eq!(
+        stripped,
+        "[dependencies]\nirust_prelude_extra = \"1\"\nregex = \"1\"\n\n[features]\nf = []"
+    );
+    assert_eq!(
+        add_dependency(&stripped, "p = 1"),
+        "[dependencies]\np = 1\nirust_prelude_extra = \"1\"\nregex = \"1\"\n\n[features]\nf = []"
+    );
+    assert_eq!(add_dependency("", "p = 1"), "[dependencies]\np = 1");
+}
@@ -0,0 +1,44 @@
+use crate::{Edition, Executor, MainResult, diagnostic::LineOrigin};
+
+/// The oldest commits are forgotten past this, each one keeps a copy of the repl code
+pub(crate) const MAX_COMMITS: usize = 100;
+
+/// A committed change of the repl, see [crate::Repl::undo] and [crate::Repl::commits]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    /// In[n] of the inserted input, None for the other changes
+    pub input_number: Option<usize>,
+    /// The inserted input, or what made the change for the others (exp: `:add rand`)
+    pub description: String,
+}
+
+/// The state of the repl after a commit
+#[derive(Debug, Clone)]
+pub(crate) struct Snapshot {
+    pub body: Vec<String>,
+    pub origins: Vec<Option<LineOrigin>>,
+    pub cursor: usize,
+    pub settings: Settings,
+    /// Only kept for the initial state and the commits that changed them
+    pub dependencies: Option<DependencyFiles>,
+    /// None for the initial state
+    pub commit: Option<Commit>,
+}
+
+/// The settings that the main function header and the manifest depend on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Settings {
+    pub executor: Executor,
+    pub main_result: MainResult,
+    pub edition: Edition,
+}
+
+/// The files that define the dependencies of the repl
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DependencyFiles {
+    /// The stripped manifest, see [crate::Session::manifest]
+    pub manifest: String,
+    /// The versions picked by cargo (exp: by `:update`), None before the first build, then the
+    /// current lockfile is kept when it's restored
+    pub lockfile: Option<String>,
+}
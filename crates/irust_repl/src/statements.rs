@@ -0,0 +1,761 @@
+//! Top level statements of the repl code, used to tell items from the statements of main, to
+//! find the items that are defined again and the variables in scope
+
+use std::{fmt::Display, ops::Range};
+
+/// Split code into top level statements, comments and string literals are taken into account
+pub(crate) fn split_statements(code: &str) -> Vec<String> {
+    let chars: Vec<char> = code.chars().collect();
+    statement_spans(&chars)
+        .into_iter()
+        .map(|span| chars[span].iter().collect())
+        .collect()
+}
+
+/// The ranges of the top level statements in `chars`, without their surrounding whitespace
+fn statement_spans(chars: &[char]) -> Vec<Range<usize>> {
+    let mut spans = vec![];
+    let mut start = 0;
+    let mut depth = 0;
+    let mut idx = 0;
+
+    let end_statement = |spans: &mut Vec<Range<usize>>, start: &mut usize, end: usize| {
+        if let Some(first) = (*start..end).find(|idx| !chars[*idx].is_whitespace()) {
+            let last = (first..end)
+                .rfind(|idx| !chars[*idx].is_whitespace())
+                .expect("there is at least one non whitespace char");
+            spans.push(first..last + 1);
+        }
+        *start = end;
+    };
+
+    while idx < chars.len() {
+        match chars[idx] {
+            // Inner attributes are statements of their own (exp: `#![allow(dead_code)]`)
+            '#' if depth == 0
+                && chars.get(idx + 1) == Some(&'!')
+                && chars.get(idx + 2) == Some(&'[') =>
+            {
+                end_statement(&mut spans, &mut start, idx);
+                let mut bracket_depth = 0;
+                while idx < chars.len() {
+                    match chars[idx] {
+                        '[' => bracket_depth += 1,
+                        ']' if bracket_depth == 1 => break,
+                        ']' => bracket_depth -= 1,
+                        _ => (),
+                    }
+                    idx += 1;
+                }
+                end_statement(&mut spans, &mut start, (idx + 1).min(chars.len()));
+            }
+            '/' if chars.get(idx + 1) == Some(&'/') => {
+                while idx < chars.len() && chars[idx] != '\n' {
+                    idx += 1;
+                }
+            }
+            '/' if chars.get(idx + 1) == Some(&'*') => {
+                let mut comment_depth = 0;
+                while idx < chars.len() {
+                    if chars[idx] == '/' && chars.get(idx + 1) == Some(&'*') {
+                        comment_depth += 1;
+                        idx += 1;
+                    } else if chars[idx] == '*' && chars.get(idx + 1) == Some(&'/') {
+                        comment_depth -= 1;
+                        idx += 1;
+                        if comment_depth == 0 {
+                            break;
+                        }
+                    }
+                    idx += 1;
+                }
+            }
+            'r' if matches!(chars.get(idx + 1), Some('"' | '#'))
+                && !chars[..idx]
+                    .last()
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_') =>
+            {
+                let hashes = chars[idx + 1..].iter().take_while(|c| **c == '#').count();
+                idx += 1 + hashes;
+                if chars.get(idx) == Some(&'"') {
+                    let closing: String = std::iter::once('"')
+                        .chain(std::iter::repeat_n('#', hashes))
+                        .collect();
+                    idx += 1;
+                    while idx < chars.len()
+                        && !chars[idx..]
+                            .iter()
+                            .collect::<String>()
+                            .starts_with(&closing)
+                    {
+                        idx += 1;
+                    }
+                    idx += hashes;
+                }
+            }
+            '"' => {
+                idx += 1;
+                while idx < chars.len() && chars[idx] != '"' {
+                    if chars[idx] == '\\' {
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+            }
+            // char literal, as opposed to a lifetime
+            '\'' if chars.get(idx + 1) == Some(&'\\') => {
+                idx += 2;
+                while idx < chars.len() && chars[idx] != '\'' {
+                    idx += 1;
+                }
+            }
+            '\'' if chars.get(idx + 2) == Some(&'\'') => idx += 2,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    let statement: String = chars[start..idx].iter().collect();
+                    let rest: String = chars[idx + 1..].iter().collect();
+                    let rest = rest.trim_start();
+                    let continues = rest.starts_with("else")
+                        || rest.starts_with('.')
+                        || rest.starts_with('?')
+                        || rest.starts_with(';');
+                    if ends_with_block(&statement) && !continues {
+                        end_statement(&mut spans, &mut start, idx + 1);
+                    }
+                }
+            }
+            ';' if depth == 0 => end_statement(&mut spans, &mut start, idx + 1),
+            _ => (),
+        }
+        idx += 1;
+    }
+    end_statement(&mut spans, &mut start, chars.len());
+    spans
+}
+
+/// The statements of `lines` with the range of lines they span
+///
+/// A comment that follows a statement on its last line belongs to it. Statements that share a
+/// line with another one are left out, they can't be removed alone
+pub(crate) fn statement_lines(lines: &[String]) -> Vec<(Range<usize>, String)> {
+    let chars: Vec<char> = lines.join("\n").chars().collect();
+    let mut line_of = Vec::with_capacity(chars.len());
+    let mut line = 0;
+    for c in &chars {
+        line_of.push(line);
+        if *c == '\n' {
+            line += 1;
+        }
+    }
+
+    let spans = statement_spans(&chars);
+    let mut statements: Vec<(Range<usize>, String)> = vec![];
+    for (idx, span) in spans.iter().enumerate() {
+        let mut lines = line_of[span.start]..line_of[span.end - 1] + 1;
+        let first_line: String = chars[span.clone()]
+            .iter()
+            .take_while(|c| **c != '\n')
+            .collect();
+        if idx > 0
+            && line_of[spans[idx - 1].end - 1] == lines.start
+            && first_line.trim_start().starts_with("//")
+        {
+            lines.start += 1;
+        }
+        statements.push((lines, chars[span.clone()].iter().collect()));
+    }
+
+    let shares_a_line = |idx: usize, other: Option<usize>| {
+        other
+            .and_then(|other| statements.get(other))
+            .is_some_and(|(other, _)| {
+                let lines = &statements[idx].0;
+                !other.is_empty() && other.start < lines.end && lines.start < other.end
+            })
+    };
+    (0..statements.len())
+        .filter(|idx| {
+            !statements[*idx].0.is_empty()
+                && !shares_a_line(*idx, idx.checked_sub(1))
+                && !shares_a_line(*idx, Some(idx + 1))
+        })
+        .map(|idx| statements[idx].clone())
+        .collect()
+}
+
+// Statements that end with their block, instead of a `;`
+fn ends_with_block(statement: &str) -> bool {
+    let statement = strip_attributes(statement);
+    let mut words = statement
+        .split(|c: char| c.is_whitespace() || c == '(' || c == '{')
+        .filter(|word| !word.is_empty())
+        .skip_while(|word| {
+            matches!(
+                *word,
+                "pub" | "crate)" | "super)" | "const" | "async" | "unsafe" | "extern" | "\"C\""
+            )
+        });
+    statement.starts_with('{')
+        || matches!(
+            words.next(),
+            Some(
+                "fn" | "struct"
+                    | "enum"
+                    | "union"
+                    | "trait"
+                    | "impl"
+                    | "mod"
+                    | "macro_rules!"
+                    | "if"
+                    | "match"
+                    | "for"
+                    | "while"
+                    | "loop"
+            ) | None
+        )
+}
+
+pub(crate) fn is_item(statement: &str) -> bool {
+    let statement = strip_attributes(statement);
+    // a block, even if it starts with an item
+    if statement.starts_with('{') {
+        return false;
+    }
+    let mut words = statement
+        .split(|c: char| c.is_whitespace() || c == '(' || c == '{')
+        .filter(|word| !word.is_empty());
+    match words.next() {
+        Some(
+            "fn" | "struct" | "enum" | "union" | "trait" | "impl" | "use" | "mod" | "type"
+            | "static" | "macro_rules!" | "macro" | "pub" | "extern",
+        ) => true,
+        // const fn|unsafe fn|async fn|const X: .., as opposed to a const block
+        Some("const") => !statement["const".len()..].trim_start().starts_with('{'),
+        Some("unsafe" | "async") => matches!(words.next(), Some("fn" | "impl" | "trait")),
+        _ => false,
+    }
+}
+
+/// The statement without its leading attributes and comments
+pub(crate) fn strip_attributes(mut statement: &str) -> &str {
+    loop {
+        statement = statement.trim_start();
+        if let Some(comment) = statement.strip_prefix("//") {
+            statement = comment.split_once('\n').map_or("", |(_, rest)| rest);
+            continue;
+        }
+        if let Some(comment) = statement.strip_prefix("/*") {
+            statement = comment.split_once("*/").map_or("", |(_, rest)| rest);
+            continue;
+        }
+        if !statement.starts_with("#[") {
+            return statement;
+        }
+        let mut depth = 0;
+        let end = statement.char_indices().find_map(|(idx, c)| {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ => (),
+            }
+            (depth == 0 && c == ']').then_some(idx)
+        });
+        match end {
+            Some(end) => statement = &statement[end + 1..],
+            None => return statement,
+        }
+    }
+}
+
+/// Code made only of items goes to the module level section of the repl body, anything else
+/// goes to main
+///
+/// A `main` function would clash with the repl one, so code that defines it (exp: a loaded file)
+/// stays in main
+pub(crate) fn is_items(code: &str) -> bool {
+    let statements: Vec<String> = split_statements(code)
+        .into_iter()
+        // only comments
+        .filter(|statement| !strip_attributes(statement).is_empty())
+        .collect();
+    !statements.is_empty()
+        && statements
+            .iter()
+            .all(|statement| is_item(statement) && !defines_main(statement))
+}
+
+fn defines_main(item: &str) -> bool {
+    Definition::parse(item).is_some_and(|definition| {
+        definition
+            == Definition::Named {
+                keyword: "fn".to_owned(),
+                name: "main".to_owned(),
+            }
+    })
+}
+
+/// `impl_` without its associated items named `names`, the lines they leave empty are removed
+pub(crate) fn without_associated_items(impl_: &str, names: &[String]) -> String {
+    let chars: Vec<char> = impl_.chars().collect();
+    let header_len = impl_.len() - strip_attributes(impl_).len();
+    let body = impl_[header_len..]
+        .find('{')
+        .map(|body_start| impl_[..header_len + body_start].chars().count() + 1)
+        .zip(chars.iter().rposition(|c| *c == '}'));
+    let Some((body_start, body_end)) = body else {
+        return impl_.to_owned();
+    };
+
+    let mut removed = vec![false; chars.len()];
+    for span in statement_spans(&chars[body_start..body_end]) {
+        let item: String = chars[body_start + span.start..body_start + span.end]
+            .iter()
+            .collect();
+        if let Some(Definition::Named { name, .. }) = Definition::parse(&item)
+            && names.contains(&name)
+        {
+            removed[body_start + span.start..body_start + span.end].fill(true);
+        }
+    }
+    let mut lines = vec![];
+    let mut offset = 0;
+    for line in impl_.split('\n') {
+        let len = line.chars().count();
+        let line_removed = &removed[offset..offset + len];
+        let kept: String = line
+            .chars()
+            .zip(line_removed)
+            .filter(|(_, removed)| !**removed)
+            .map(|(c, _)| c)
+            .collect();
+        if !(line_removed.contains(&true) && kept.trim().is_empty()) {
+            lines.push(kept);
+        }
+        // the newline
+        offset += len + 1;
+    }
+    lines.join("\n")
+}
+
+/// What an item of the module level section defines, an item that defines the same thing again
+/// replaces it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Definition {
+    /// exp: `fn foo`, `struct Bar`, `macro_rules! baz`
+    Named { keyword: String, name: String },
+    /// `impl Bar` or `impl Trait for Bar`, with the names of its associated items
+    Impl {
+        self_ty: String,
+        trait_: Option<String>,
+        items: Vec<String>,
+    },
+}
+
+impl Definition {
+    /// None for items without a name (exp: `use`, `extern crate`)
+    pub(crate) fn parse(item: &str) -> Option<Self> {
+        let mut item = strip_attributes(item);
+        if let Some(restricted) = item.strip_prefix("pub(") {
+            item = &restricted[restricted.find(')')? + 1..];
+        }
+        let mut words = item
+            .split(|c: char| c.is_whitespace() || "(<{:=;,".contains(c))
+            .filter(|word| !word.is_empty())
+            .peekable();
+        let keyword = loop {
+            let word = words.next()?;
+            match word {
+                "pub" | "unsafe" | "async" | "extern" => (),
+                // const fn
+                "const"
+                    if matches!(words.peek(), Some(&("fn" | "unsafe" | "async" | "extern"))) => {}
+                // the abi of an extern fn (exp: `extern "C" fn`)
+                _ if word.starts_with('"') => (),
+                "impl" => return Self::parse_impl(&item[item.find("impl")? + "impl".len()..]),
+                "fn" | "struct" | "enum" | "union" | "trait" | "type" | "static" | "const"
+                | "mod" | "macro_rules!" | "macro" => break word,
+                _ => return None,
+            }
+        };
+        // static mut
+        let name = words.find(|word| *word != "mut")?;
+        let name: String = name
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        (!name.is_empty()).then(|| Self::Named {
+            keyword: keyword.to_owned(),
+            name,
+        })
+    }
+
+    /// `impl` is the code after the impl keyword
+    fn parse_impl(impl_: &str) -> Option<Self> {
+        let body_start = impl_.find('{')?;
+        let mut header = impl_[..body_start].trim();
+        // impl<T: Clone>
+        if header.starts_with('<') {
+            let mut depth = 0;
+            let end = header.char_indices().find_map(|(idx, c)| {
+                match c {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    _ => (),
+                }
+                (depth == 0).then_some(idx)
+            })?;
+            header = &header[end + 1..];
+        }
+        let header = header.split_whitespace().collect::<Vec<_>>().join(" ");
+        let header = match header.find(" where ") {
+            Some(where_clause) => &header[..where_clause],
+            None => header.strip_suffix(" where").unwrap_or(&header),
+        };
+        let (trait_, self_ty) = match header.split_once(" for ") {
+            Some((trait_, self_ty)) => (Some(trait_.trim().to_owned()), self_ty.trim()),
+            None => (None, header.trim()),
+        };
+        let body = impl_[body_start + 1..].trim_end().strip_suffix('}')?;
+        let items = split_statements(body)
+            .iter()
+            .filter_map(|item| match Self::parse(item)? {
+                Self::Named { name, .. } => Some(name),
+                Self::Impl { .. } => None,
+            })
+            .collect();
+        Some(Self::Impl {
+            self_ty: self_ty.to_owned(),
+            trait_,
+            items,
+        })
+    }
+
+    /// Items of the same namespace can't have the same name, and a trait can only be implemented
+    /// once for a type. Inherent impls can be split in several blocks, one that defines an
+    /// associated item again replaces it in the previous one, see
+    /// [Definition::redefined_associated_items]
+    pub(crate) fn redefines(&self, other: &Self) -> bool {
+        fn namespace(keyword: &str) -> u8 {
+            match keyword {
+                "fn" | "const" | "static" => 0,
+                "macro_rules!" | "macro" => 1,
+                _ => 2,
+            }
+        }
+        match (self, other) {
+            (
+                Self::Named { keyword, name },
+                Self::Named {
+                    keyword: other_keyword,
+                    name: other_name,
+                },
+            ) => name == other_name && namespace(keyword) == namespace(other_keyword),
+            (
+                Self::Impl {
+                    self_ty,
+                    trait_: Some(trait_),
+                    ..
+                },
+                Self::Impl {
+                    self_ty: other_ty,
+                    trait_: Some(other_trait),
+                    ..
+                },
+            ) => self_ty == other_ty && trait_ == other_trait,
+            (
+                Self::Impl {
+                    self_ty,
+                    trait_: None,
+                    items,
+                },
+                Self::Impl {
+                    self_ty: other_ty,
+                    trait_: None,
+                    items: other_items,
+                },
+            ) => self_ty == other_ty && items.iter().any(|item| other_items.contains(item)),
+            _ => false,
+        }
+    }
+
+    /// The associated items of this inherent impl that the `new` impls define again, they're
+    /// removed from it instead of the whole block
+    pub(crate) fn redefined_associated_items(&self, new: &[Self]) -> Vec<String> {
+        let Self::Impl {
+            self_ty,
+            trait_: None,
+            items,
+        } = self
+        else {
+            return vec![];
+        };
+        items
+            .iter()
+            .filter(|item| {
+                new.iter().any(|new| match new {
+                    Self::Impl {
+                        self_ty: new_ty,
+                        trait_: None,
+                        items: new_items,
+                    } => new_ty == self_ty && new_items.contains(item),
+                    _ => false,
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Whether `:undef name` removes it, the impls of a type go with it
+    ///
+    /// A single impl can be removed with its full name (exp: `impl Display for Bar`)
+    pub(crate) fn is_named(&self, name: &str) -> bool {
+        match self {
+            Self::Named { name: own_name, .. } => own_name == name,
+            Self::Impl { self_ty, .. } => {
+                let ty = self_ty.split('<').next().unwrap_or_default();
+                let ty = ty.rsplit("::").next().unwrap_or_default();
+                ty.trim_start_matches(['&', ' ']) == name
+                    || self.to_string() == name.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+        }
+    }
+}
+
+impl Display for Definition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Named { keyword, name } => write!(f, "{keyword} {name}"),
+            Self::Impl {
+                self_ty,
+                trait_: Some(trait_),
+                ..
+            } => write!(f, "impl {trait_} for {self_ty}"),
+            Self::Impl { self_ty, .. } => write!(f, "impl {self_ty}"),
+        }
+    }
+}
+
+/// Names bound by a `let` statement, None if the statement is not a `let`
+pub(crate) fn let_bindings(statement: &str) -> Option<Vec<String>> {
+    let statement = strip_attributes(statement).strip_prefix("let")?;
+    if !statement.starts_with(|c: char| c.is_whitespace() || c == '(') {
+        return None;
+    }
+    // The pattern ends at the type annotation or the initializer
+    let tokens = tokens(statement);
+    let mut depth = 0;
+    let mut pattern = vec![];
+    for token in &tokens {
+        match token.as_str() {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth -= 1,
+            ":" | "=" | ";" if depth == 0 => break,
+            _ => (),
+        }
+        pattern.push(token.as_str());
+    }
+
+    let mut names = vec![];
+    for (idx, token) in pattern.iter().enumerate() {
+        let is_ident = token.starts_with(|c: char| c.is_lowercase() || c == '_')
+            && token.chars().all(|c| c.is_alphanumeric() || c == '_');
+        let next = pattern.get(idx + 1).copied();
+        let previous = idx.checked_sub(1).map(|idx| pattern[idx]);
+        if !is_ident
+            || matches!(*token, "_" | "mut" | "ref" | "box")
+            // paths, tuple structs, struct fields and macros
+            || matches!(next, Some("(" | "{" | "::" | ":" | "!"))
+            || previous == Some("::")
+        {
+            continue;
+        }
+        names.push(token.to_string());
+    }
+    Some(names)
+}
+
+// Just enough tokenization to find patterns, `::` `..` and `=>` are kept as one token
+fn tokens(code: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_alphanumeric() || c == '_' {
+            let mut token = c.to_string();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+            continue;
+        }
+        let mut token = c.to_string();
+        if let Some(&next) = chars.peek()
+            && matches!((c, next), (':', ':') | ('.', '.') | ('=', '>') | ('=', '='))
+        {
+            token.push(next);
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    tokens
+}
+
+#[test]
+fn split_and_classify_statements() {
+    let code = r#"let a = if true { 1 } else { 2 };
+#[derive(Debug)]
+struct A { b: i32 }
+fn f() -> &'static str { "}" }
+for i in 0..2 { println!("{i}"); }
+let (mut b, A { b: c }) = (1, A { b: 2 });
+let s: &str = r"{;";
+v.push('}');"#;
+    let statements = split_statements(code);
+    assert_eq!(statements.len(), 7);
+    assert!(is_item(&statements[1]));
+    assert!(is_item(&statements[2]));
+    assert_eq!(let_bindings(&statements[0]), Some(vec!["a".to_owned()]));
+    assert_eq!(
+        let_bindings(&statements[4]),
+        Some(vec!["b".to_owned(), "c".to_owned()])
+    );
+    assert_eq!(let_bindings(&statements[5]), Some(vec!["s".to_owned()]));
+    assert_eq!(let_bindings(&statements[6]), None);
+}
+
+#[test]
+fn items_go_to_module_level() {
+    assert!(is_items(
+        "#[derive(Debug)]\nstruct A;\nimpl A { fn a() {} }"
+    ));
+    assert!(is_items("pub fn f() -> u8 { 1 }"));
+    assert!(is_items("macro_rules! m { () => {}; }"));
+    assert!(is_items("use std::collections::HashMap;"));
+    assert!(!is_items("fn f() {} let a = f();"));
+    assert!(!is_items("let a = 1;"));
+    assert!(!is_items("unsafe { f() }"));
+    assert!(!is_items("const { 1 };"));
+    assert!(!is_items("{ struct A; }"));
+    assert!(!is_items("// fn f() {}"));
+    assert!(is_items("/// Doc\nfn f() {} // trailing"));
+    assert!(!is_items(
+        "fn f() {}\n#[allow(dead_code)]\nfn main() { f() }"
+    ));
+}
+
+#[test]
+fn parse_definitions() {
+    let named = |keyword: &str, name: &str| {
+        Some(Definition::Named {
+            keyword: keyword.to_owned(),
+            name: name.to_owned(),
+        })
+    };
+    assert_eq!(
+        Definition::parse("/// Doc\n#[inline]\npub(crate) const fn foo<T>() {}"),
+        named("fn", "foo")
+    );
+    assert_eq!(
+        Definition::parse("pub extern \"C\" fn bar() {}"),
+        named("fn", "bar")
+    );
+    assert_eq!(
+        Definition::parse("const N: usize = 2;"),
+        named("const", "N")
+    );
+    assert_eq!(
+        Definition::parse("static mut S: u8 = 2;"),
+        named("static", "S")
+    );
+    assert_eq!(Definition::parse("struct A(u8);"), named("struct", "A"));
+    assert_eq!(
+        Definition::parse("macro_rules! m { () => {} }"),
+        named("macro_rules!", "m")
+    );
+    assert_eq!(Definition::parse("use std::fmt;"), None);
+
+    let display = Definition::parse(
+        "impl<T: Debug> std::fmt::Display for Bar<T>\nwhere T: Clone {\n    fn fmt(&self) {}\n}",
+    )
+    .unwrap();
+    assert_eq!(display.to_string(), "impl std::fmt::Display for Bar<T>");
+    assert!(display.is_named("Bar"));
+    assert!(display.is_named("impl std::fmt::Display  for Bar<T>"));
+    let baz = Definition::parse("impl Bar { fn baz() {} const C: u8 = 1; }").unwrap();
+    let qux = Definition::parse("impl Bar { fn qux() {} }").unwrap();
+    assert!(baz.redefines(&Definition::parse("impl Bar { fn baz(&self) {} }").unwrap()));
+    assert!(!baz.redefines(&qux));
+    assert!(!baz.redefines(&display));
+    assert!(
+        named("struct", "Bar")
+            .unwrap()
+            .redefines(&named("enum", "Bar").unwrap())
+    );
+    assert!(
+        !named("fn", "Bar")
+            .unwrap()
+            .redefines(&named("struct", "Bar").unwrap())
+    );
+}
+
+#[test]
+fn remove_associated_items() {
+    let names = ["baz".to_owned(), "C".to_owned()];
+    assert_eq!(
+        without_associated_items(
+            "/// {Doc}\nimpl Bar {\n    /// Doc\n    fn baz() {}\n\n    fn qux() {}\n    const C: u8 = 1;\n}",
+            &names
+        ),
+        "/// {Doc}\nimpl Bar {\n\n    fn qux() {}\n}"
+    );
+    assert_eq!(
+        without_associated_items("impl Bar { fn baz() {} fn qux() {} }", &names),
+        "impl Bar {  fn qux() {} }"
+    );
+}
+
+#[test]
+fn lines_of_statements() {
+    let lines: Vec<String> = "#![allow(dead_code)]
+/// Doc
+fn a() {} // trailing
+fn b() {} fn c() {}
+struct D {
+    e: u8,
+}"
+    .lines()
+    .map(ToOwned::to_owned)
+    .collect();
+    let statements = statement_lines(&lines);
+    let ranges: Vec<_> = statements.iter().map(|(lines, _)| lines.clone()).collect();
+    assert_eq!(ranges, [0..1, 1..3, 4..7]);
+    assert!(statements[1].1.starts_with("/// Doc"));
+}
+
+#[test]
+fn bindings_of_let_statements() {
+    let bindings = |statement| let_bindings(statement).unwrap();
+    assert_eq!(bindings("let a = 1;"), ["a"]);
+    assert_eq!(bindings("let mut a: Vec<u8> = vec![];"), ["a"]);
+    assert_eq!(
+        bindings("let (a, ref mut b, _): (u8, u8, u8) = (1, 2, 3);"),
+        ["a", "b"]
+    );
+    assert_eq!(bindings("let Point { x: a, y, .. } = p;"), ["a", "y"]);
+    assert_eq!(
+        bindings("let Some(v @ 1..=9) = std::option::Option::Some(2) else { return };"),
+        ["v"]
+    );
+    assert_eq!(bindings("#[allow(unused)]\nlet x;"), ["x"]);
+    assert!(let_bindings("letter(1);").is_none());
+    assert!(let_bindings("if let Some(a) = b {}").is_none());
+}
@@ -1,13 +1,17 @@
+use crate::ReplError;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, process::Command, str::FromStr};
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum ToolChain {
     Stable,
     Beta,
     Nightly,
+    /// Any other rustup toolchain (exp: `1.79.0`, `nightly-2025-06-01` or a linked toolchain)
+    Named(String),
     // cargo with no +argument, it can be different from the above
     #[default]
     Default,
@@ -21,21 +25,73 @@ impl FromStr for ToolChain {
             "beta" => Ok(ToolChain::Beta),
             "nightly" => Ok(ToolChain::Nightly),
             "default" => Ok(ToolChain::Default),
-            _ => Err("Unknown toolchain".into()),
+            _ if s.is_empty() || s.starts_with('+') || s.contains(char::is_whitespace) => {
+                Err(format!("Invalid toolchain name: {s}").into())
+            }
+            _ => Ok(ToolChain::Named(s.to_owned())),
         }
     }
 }
 
 impl ToolChain {
-    pub(crate) fn as_arg(&self) -> &str {
+    pub(crate) fn as_arg(&self) -> String {
         match self {
-            ToolChain::Stable => "+stable",
-            ToolChain::Beta => "+beta",
-            ToolChain::Nightly => "+nightly",
             // The caller should not call as_arg for the default toolchain
             ToolChain::Default => unreachable!(),
+            toolchain => format!("+{toolchain}"),
         }
     }
+
+    /// Toolchains installed with rustup, as shown by `rustup toolchain list` (exp:
+    /// `stable-x86_64-unknown-linux-gnu`)
+    pub fn installed() -> crate::Result<Vec<String>> {
+        let output = Command::new("rustup")
+            .args(["toolchain", "list"])
+            .output()
+            .map_err(|e| {
+                ReplError::Toolchain(format!("Failed to run `rustup toolchain list`: {e}"))
+            })?;
+        if !output.status.success() {
+            return Err(ReplError::Toolchain(format!(
+                "`rustup toolchain list` failed:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            // Markers like ` (active, default)` follow the name
+            .filter_map(|line| line.split_whitespace().next())
+            .map(ToOwned::to_owned)
+            .collect())
+    }
+
+    /// Toolchains other than the default one must be installed with rustup
+    pub(crate) fn check_installed(&self) -> crate::Result<()> {
+        if *self != ToolChain::Default && self.find_in(&Self::installed()?).is_none() {
+            return Err(ReplError::Toolchain(format!(
+                "Toolchain `{self}` is not installed, see `rustup toolchain list`"
+            )));
+        }
+        Ok(())
+    }
+
+    /// The installed toolchain this one refers to, rustup names can omit the host triple
+    /// (exp: `1.79.0` is `1.79.0-x86_64-unknown-linux-gnu`)
+    pub fn find_in<'a>(&self, installed: &'a [String]) -> Option<&'a str> {
+        let name = self.to_string();
+        installed
+            .iter()
+            .find(|toolchain| {
+                toolchain.strip_prefix(&name).is_some_and(|host| {
+                    // Dates start with a digit (exp: `nightly` is not `nightly-2025-06-01-..`)
+                    host.is_empty()
+                        || host
+                            .strip_prefix('-')
+                            .is_some_and(|host| host.starts_with(|c: char| c.is_ascii_alphabetic()))
+                })
+            })
+            .map(String::as_str)
+    }
 }
 
 impl Display for ToolChain {
@@ -44,7 +100,46 @@ impl Display for ToolChain {
             ToolChain::Stable => write!(f, "stable"),
             ToolChain::Beta => write!(f, "beta"),
             ToolChain::Nightly => write!(f, "nightly"),
+            ToolChain::Named(name) => write!(f, "{name}"),
             ToolChain::Default => write!(f, "default"),
         }
     }
 }
+
+impl TryFrom<String> for ToolChain {
+    type Error = Box<dyn std::error::Error>;
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ToolChain> for String {
+    fn from(toolchain: ToolChain) -> Self {
+        toolchain.to_string()
+    }
+}
+
+#[test]
+fn find_installed_toolchain() {
+    let installed = [
+        "stable-x86_64-unknown-linux-gnu".to_owned(),
+        "nightly-2025-06-01-x86_64-unknown-linux-gnu".to_owned(),
+        "1.79.0-x86_64-unknown-linux-gnu".to_owned(),
+        "my-toolchain".to_owned(),
+    ];
+    let find = |name: &str| name.parse::<ToolChain>().unwrap().find_in(&installed);
+    assert_eq!(find("Stable"), Some("stable-x86_64-unknown-linux-gnu"));
+    assert_eq!(find("nightly"), None);
+    assert_eq!(
+        find("nightly-2025-06-01"),
+        Some("nightly-2025-06-01-x86_64-unknown-linux-gnu")
+    );
+    assert_eq!(find("1.79.0"), Some("1.79.0-x86_64-unknown-linux-gnu"));
+    assert_eq!(find("1.79"), None);
+    assert_eq!(find("my-toolchain"), Some("my-toolchain"));
+    assert_eq!(
+        "1.79.0".parse::<ToolChain>().unwrap(),
+        ToolChain::Named("1.79.0".to_owned())
+    );
+    assert!("+nightly".parse::<ToolChain>().is_err());
+}
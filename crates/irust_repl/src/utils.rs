@@ -1,36 +1,55 @@
-use crate::Result;
+use crate::{OutputChunk, Result, limits::LimitWatch};
 use std::{
     io::Read,
     process::{Child, Output},
     sync::mpsc,
+    time::Duration,
 };
 
 pub fn stdout_and_stderr(out: Output) -> String {
-    let mut combined = Vec::new();
-    // dont include the trailing new line, it comes from the repl println! wrapper
-    let stdout = &out.stdout[..out.stdout.len().saturating_sub(1)];
-    // the () sufix comes from the repl println! wrapper in the case where the output is void
-    if let Some(stdout) = stdout.strip_suffix(b"()") {
-        combined.extend_from_slice(stdout);
-    } else {
-        combined.extend_from_slice(stdout);
-    }
-    if !out.stderr.is_empty() {
-        combined.extend_from_slice(b"IRUST_INTERNAL_STDERR_START");
-        combined.extend_from_slice(&out.stderr);
+    let (stdout, stderr) = split_stdout_and_stderr(&out);
+    join_stdout_and_stderr(stdout, &stderr)
+}
+
+/// Stdout without the artifacts of the repl println! wrapper, and stderr
+pub fn split_stdout_and_stderr(out: &Output) -> (String, String) {
+    let mut stdout = &out.stdout[..];
+    // the wrapper doesn't run if the program failed (exp: it panicked)
+    if out.status.success() {
+        // dont include the trailing new line, it comes from the repl println! wrapper
+        stdout = &stdout[..stdout.len().saturating_sub(1)];
+        // the () sufix comes from the repl println! wrapper in the case where the output is void
+        stdout = stdout.strip_suffix(b"()").unwrap_or(stdout);
     }
+    (
+        String::from_utf8_lossy(stdout).to_string(),
+        String::from_utf8_lossy(&out.stderr).to_string(),
+    )
+}
 
-    String::from_utf8_lossy(&combined).to_string()
+pub fn join_stdout_and_stderr(mut stdout: String, stderr: &str) -> String {
+    if !stderr.is_empty() {
+        stdout.push_str("IRUST_INTERNAL_STDERR_START");
+        stdout.push_str(stderr);
+    }
+    stdout
 }
 
 pub trait ProcessUtils {
-    fn interactive_output(self, function: Option<fn(&mut Child) -> Result<()>>) -> Result<Output>;
+    fn interactive_output(
+        self,
+        function: Option<fn(&mut Child) -> Result<()>>,
+        output_function: Option<&mut dyn FnMut(OutputChunk)>,
+        limit_watch: Option<&mut LimitWatch>,
+    ) -> Result<Output>;
 }
 
 impl ProcessUtils for Child {
     fn interactive_output(
         mut self,
         function: Option<fn(&mut Child) -> Result<()>>,
+        mut output_function: Option<&mut dyn FnMut(OutputChunk)>,
+        mut limit_watch: Option<&mut LimitWatch>,
     ) -> Result<Output> {
         let mut stdout = self.stdout.take().expect("stdout is piped");
         let mut stderr = self.stderr.take().expect("stderr is piped");
@@ -42,38 +61,167 @@ impl ProcessUtils for Child {
             Stderr(Vec<u8>),
         }
 
-        std::thread::spawn(move || {
-            let mut out = Vec::new();
-            let _ = stdout.read_to_end(&mut out);
-            let _ = tx_out.send(OutType::Stdout(out));
-        });
+        // The channel is closed once both pipes are closed
+        std::thread::spawn(move || read_chunks(&mut stdout, tx_out, OutType::Stdout));
+        std::thread::spawn(move || read_chunks(&mut stderr, tx_err, OutType::Stderr));
 
-        std::thread::spawn(move || {
-            let mut err = Vec::new();
-            let _ = stderr.read_to_end(&mut err);
-            let _ = tx_err.send(OutType::Stderr(err));
-        });
+        let mut output = Output {
+            status: Default::default(),
+            stdout: vec![],
+            stderr: vec![],
+        };
+        let mut stdout_stream = StdoutStream::new(b"\n");
+        let mut receive = |chunk, output: &mut Output| match chunk {
+            OutType::Stdout(out) => {
+                stdout_stream.push(&out, &mut output_function);
+                output.stdout.extend(out);
+            }
+            OutType::Stderr(err) => {
+                if let Some(f) = output_function.as_mut() {
+                    f(OutputChunk::Stderr(&err));
+                }
+                output.stderr.extend(err);
+            }
+        };
 
         while self.try_wait()?.is_none() {
+            if let Ok(chunk) = rx.recv_timeout(Duration::from_millis(1)) {
+                if let Some(watch) = limit_watch.as_mut() {
+                    watch.count_output(match &chunk {
+                        OutType::Stdout(out) | OutType::Stderr(out) => out.len(),
+                    });
+                }
+                receive(chunk, &mut output);
+            }
+            if let Some(watch) = limit_watch.as_mut() {
+                watch.enforce(&mut self)?;
+            }
             if let Some(ref function) = function {
                 function(&mut self)?;
             }
         }
-        let mut stdout = None;
-        let mut stderr = None;
-        for _ in 0..2 {
-            match rx.recv()? {
-                OutType::Stdout(out) => stdout = Some(out),
-                OutType::Stderr(err) => stderr = Some(err),
+        for chunk in rx {
+            receive(chunk, &mut output);
+        }
+        output.status = self.wait()?;
+        stdout_stream.finish(output.status.success(), &mut output_function);
+
+        Ok(output)
+    }
+}
+
+fn read_chunks<T>(pipe: &mut impl Read, tx: mpsc::Sender<T>, chunk: fn(Vec<u8>) -> T) {
+    let mut buf = [0; 4096];
+    while let Ok(n @ 1..) = pipe.read(&mut buf) {
+        if tx.send(chunk(buf[..n].to_vec())).is_err() {
+            break;
+        }
+    }
+}
+
+/// Remove color escape sequences (exp: `\x1b[1m`)
+pub(crate) fn strip_ansi(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip until the final byte of the sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
             }
+        } else {
+            stripped.push(c);
         }
+    }
+    stripped
+}
 
-        Ok(Output {
-            status: self.wait()?,
-            stdout: stdout.unwrap(),
-            stderr: stderr.unwrap(),
-        })
+/// Length of the longest end of `output` that is the start of `tail`
+pub(crate) fn started_len(output: &[u8], tail: &[u8]) -> usize {
+    (1..=tail.len().min(output.len()))
+        .rev()
+        .find(|&n| output.ends_with(&tail[..n]))
+        .unwrap_or(0)
+}
+
+/// Give the program stdout to the output function, without the artifacts of the repl println!
+/// wrapper (like [split_stdout_and_stderr])
+pub(crate) struct StdoutStream {
+    /// How the program new lines look like
+    new_line: &'static [u8],
+    /// Output that might be the start of the wrapper tail, sent once more output arrives
+    pending: Vec<u8>,
+}
+
+impl StdoutStream {
+    pub(crate) fn new(new_line: &'static [u8]) -> Self {
+        Self {
+            new_line,
+            pending: vec![],
+        }
     }
+
+    pub(crate) fn push(
+        &mut self,
+        bytes: &[u8],
+        output_function: &mut Option<&mut dyn FnMut(OutputChunk)>,
+    ) {
+        self.pending.extend_from_slice(bytes);
+        // The void output `()\n` or just the new line after a value
+        let void_tail = [b"()", self.new_line].concat();
+        let keep =
+            started_len(&self.pending, &void_tail).max(started_len(&self.pending, self.new_line));
+        let send = self.pending.len() - keep;
+        if send != 0 {
+            if let Some(f) = output_function.as_mut() {
+                f(OutputChunk::Stdout(&self.pending[..send]));
+            }
+            self.pending.drain(..send);
+        }
+    }
+
+    /// The program exited
+    pub(crate) fn finish(
+        &mut self,
+        success: bool,
+        output_function: &mut Option<&mut dyn FnMut(OutputChunk)>,
+    ) {
+        let mut pending = &self.pending[..];
+        // the wrapper only runs if the program succeeded
+        if success && let Some(value) = pending.strip_suffix(self.new_line) {
+            pending = value.strip_suffix(b"()").unwrap_or(value);
+        }
+        if !pending.is_empty()
+            && let Some(f) = output_function.as_mut()
+        {
+            f(OutputChunk::Stdout(pending));
+        }
+        self.pending.clear();
+    }
+}
+
+#[test]
+fn stdout_stream() {
+    let mut sent = vec![];
+    let mut send = |chunk: OutputChunk| {
+        if let OutputChunk::Stdout(out) = chunk {
+            sent.extend_from_slice(out);
+        }
+    };
+    let mut f: Option<&mut dyn FnMut(OutputChunk)> = Some(&mut send);
+    let mut stream = StdoutStream::new(b"\r\n");
+    stream.push(b"hello\r\n(", &mut f);
+    assert_eq!(stream.pending, b"(");
+    stream.push(b")\r", &mut f);
+    stream.push(b"\n", &mut f);
+    assert_eq!(stream.pending, b"()\r\n");
+    stream.push(b"more\r\n", &mut f);
+    assert_eq!(stream.pending, b"\r\n");
+    stream.push(b"()\r\n", &mut f);
+    stream.finish(true, &mut f);
+    assert_eq!(sent, b"hello\r\n()\r\nmore\r\n");
 }
 
 pub fn _is_allowed_in_lib(s: &str) -> bool {
@@ -111,3 +259,20 @@ pub fn _is_use_stmt(l: &str) -> bool {
     let l = l.trim_start();
     l.starts_with("use") || l.starts_with("#[allow(unused_imports)]use")
 }
+
+/// Recursively copy a directory, `target` directories are skipped
+pub fn copy_dir(src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            if entry.file_name() != "target" {
+                copy_dir(&path, &dst.join(entry.file_name()))?;
+            }
+        } else {
+            std::fs::copy(&path, dst.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
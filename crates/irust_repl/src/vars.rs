@@ -0,0 +1,245 @@
+//! The variables in scope at the repl cursor, and the types and values of expressions
+
+use crate::{CompileMode, Diagnostic, EvalCallbacks, Repl, Result, statements};
+#[cfg(unix)]
+use crate::{DEFAULT_EVALUATOR, EvalMode};
+use std::process::Child;
+
+/// Starts the lines of the program output that carry a value, the repl code can print too
+const VALUE_MARKER: &str = "irust_value\u{1}";
+
+/// `(&IRustValue(&x)).irust_debug()` is the `{:?}` of x, or None if its type doesn't implement
+/// Debug: the method that takes `IRustValue` is picked first, it's only implemented for Debug types
+const VALUE_HELPERS: &str = "struct IRustValue<'a, T>(&'a T); \
+trait IRustDebug { fn irust_debug(&self) -> Option<String>; } \
+impl<T: std::fmt::Debug> IRustDebug for IRustValue<'_, T> { \
+fn irust_debug(&self) -> Option<String> { Some(format!(\"{:?}\", self.0)) } } \
+trait IRustNoDebug { fn irust_debug(&self) -> Option<String> { None } } \
+impl<T> IRustNoDebug for &IRustValue<'_, T> {}";
+
+impl Repl {
+    /// The variables in scope at the cursor, oldest first. A shadowed variable is listed once,
+    /// where it was bound last
+    pub fn bindings(&self) -> Vec<String> {
+        let statements = self
+            .body
+            .get(self.main_idx() + 1..self.cursor)
+            .unwrap_or_default()
+            .join("\n");
+        let mut bindings: Vec<String> = vec![];
+        for binding in statements::split_statements(&statements)
+            .iter()
+            .flat_map(|statement| statements::let_bindings(statement).unwrap_or_default())
+        {
+            bindings.retain(|other| *other != binding);
+            bindings.push(binding);
+        }
+        bindings
+    }
+
+    /// The types of `expressions` (exp: variables) as reported by the compiler, None if it
+    /// couldn't be found
+    ///
+    /// Each expression is assigned to `()`, the type mismatch errors give their types
+    ///
+    /// The types aren't fully qualified, the compiler trims their paths (exp: `HashMap<u8, String>`)
+    pub fn expression_types(
+        &mut self,
+        expressions: &[String],
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+    ) -> Result<Vec<Option<String>>> {
+        // The expression of each line of the input
+        let mut line_expressions = vec![];
+        let input = expressions
+            .iter()
+            .enumerate()
+            .map(|(idx, expression)| {
+                let statement = format!("let _: () = {expression};");
+                line_expressions.extend(std::iter::repeat_n(idx, statement.lines().count()));
+                statement
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let diagnostics = self.check_input(input, interactive_function)?;
+
+        let mut errors: Vec<Vec<&Diagnostic>> = vec![vec![]; expressions.len()];
+        let mut other_errors = false;
+        for diagnostic in diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.is_error())
+        {
+            match input_line(diagnostic).and_then(|line| line_expressions.get(line)) {
+                Some(idx) => errors[*idx].push(diagnostic),
+                // exp: `aborting due to 2 previous errors`
+                None if diagnostic.spans.is_empty() => (),
+                None => other_errors = true,
+            }
+        }
+        // An expression without errors is a `()`, unless the errors come from elsewhere
+        Ok(errors
+            .into_iter()
+            .map(|errors| match errors[..] {
+                [] if other_errors => None,
+                [] => Some("()".to_owned()),
+                _ => errors.into_iter().find_map(found_type),
+            })
+            .collect())
+    }
+
+    /// The `{:?}` of `bindings`, None for the variables that were moved and the types that don't
+    /// implement Debug
+    ///
+    /// The persistent eval mode reads them from its host, otherwise the repl code is run again to
+    /// get them
+    pub fn binding_values(
+        &mut self,
+        bindings: &[String],
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+    ) -> Result<Vec<Option<String>>> {
+        // The first line has the helpers, then each variable has its own line so the errors
+        // (exp: borrow of a moved value) tell which ones can't be shown
+        let input = |shown: &[bool]| {
+            let lines: Vec<String> = bindings
+                .iter()
+                .zip(shown)
+                .enumerate()
+                .map(|(idx, (binding, shown))| {
+                    if *shown {
+                        format!(
+                            "if let Some(value) = (&IRustValue(&{binding})).irust_debug() {{ \
+                             println!(\"{{}}{idx} {{}}\", {VALUE_MARKER:?}, value.replace('\\n', \" \")); }}"
+                        )
+                    } else {
+                        String::new()
+                    }
+                })
+                .collect();
+            format!("{{ {VALUE_HELPERS}\n{}\n}}", lines.join("\n"))
+        };
+
+        let mut shown = vec![true; bindings.len()];
+        loop {
+            let diagnostics = self.check_input(input(&shown), interactive_function)?;
+            let mut hidden = false;
+            for line in diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.is_error())
+                .filter_map(input_line)
+            {
+                if let Some(shown) = line.checked_sub(1).and_then(|idx| shown.get_mut(idx))
+                    && *shown
+                {
+                    *shown = false;
+                    hidden = true;
+                }
+            }
+            // The other errors are reported by the run
+            if !hidden {
+                break;
+            }
+        }
+
+        let mut callbacks = EvalCallbacks {
+            interactive_function,
+            ..Default::default()
+        };
+        #[cfg(unix)]
+        if self.eval_mode == EvalMode::Persistent {
+            let result = self
+                .eval_persistent(
+                    input(&shown),
+                    callbacks,
+                    false,
+                    &DEFAULT_EVALUATOR[..],
+                    &CompileMode::Debug,
+                )?
+                .into_result()?;
+            return Ok(values(&result.stdout, bindings.len()));
+        }
+
+        let toolchain = self.toolchain.clone();
+        let run_mode = self.run_mode;
+        let limits = self.limits;
+        let main_result = self.main_result_definition.clone();
+        let cargo = self.cargo.clone();
+        let result = self
+            .eval_in_tmp_repl(input(&shown), |repl| -> Result<_> {
+                let mut result = cargo.cargo_run(
+                    false,
+                    &CompileMode::Debug,
+                    &toolchain,
+                    run_mode,
+                    limits,
+                    &main_result,
+                    callbacks.reborrow(),
+                )?;
+                repl.relocate_diagnostics(&mut result.diagnostics);
+                Ok(result)
+            })?
+            .into_result()?;
+        Ok(values(&result.stdout, bindings.len()))
+    }
+
+    /// The diagnostics of `cargo check` with `input` inserted
+    fn check_input(
+        &mut self,
+        input: String,
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+    ) -> Result<Vec<Diagnostic>> {
+        let toolchain = self.toolchain.clone();
+        let cargo = self.cargo.clone();
+        self.eval_in_tmp_repl(input, |repl| -> Result<_> {
+            let (_, _, mut diagnostics) =
+                cargo.cargo_check_output(&toolchain, interactive_function, None)?;
+            repl.relocate_diagnostics(&mut diagnostics);
+            Ok(diagnostics)
+        })
+    }
+}
+
+/// The values printed by the [Repl::binding_values] program, by binding index
+fn values(stdout: &str, count: usize) -> Vec<Option<String>> {
+    let mut values = vec![None; count];
+    for line in stdout.lines() {
+        if let Some((_, value)) = line.split_once(VALUE_MARKER)
+            && let Some((idx, value)) = value.split_once(' ')
+            && let Some(slot) = idx.parse().ok().and_then(|idx: usize| values.get_mut(idx))
+        {
+            *slot = Some(value.to_owned());
+        }
+    }
+    values
+}
+
+/// The 0-based line of the inserted input the diagnostic points to
+///
+/// The primary span can be elsewhere if the error comes from a macro (exp: `vec![1]`)
+fn input_line(diagnostic: &Diagnostic) -> Option<usize> {
+    let mut spans = diagnostic
+        .primary_span()
+        .into_iter()
+        .chain(&diagnostic.spans);
+    let span = spans.find(|span| span.origin.is_some_and(|origin| origin.current))?;
+    Some(span.line_start - 1)
+}
+
+/// The type in a `()` mismatch error, from its note if it has one as it's more detailed
+///
+/// exp: ``expected `()`, found `Vec<u8>` `` with the note ``expected unit type `()` found struct
+/// `Vec<u8>` ``
+fn found_type(diagnostic: &Diagnostic) -> Option<String> {
+    let label = diagnostic.primary_span()?.label.as_deref()?;
+    let found = label.strip_prefix("expected `()`, found ")?;
+    let note = diagnostic.children.iter().find_map(|child| {
+        let (_, found) = child.message.split_once("\n")?;
+        let found = found.trim_start().strip_prefix("found ")?;
+        Some(&found[found.find('`')? + 1..found.rfind('`')?])
+    });
+    Some(match (note, found) {
+        (Some(note), _) => note.to_owned(),
+        // Untyped literals, they fall back to these types
+        (None, "integer") => "i32".to_owned(),
+        (None, "floating-point number") => "f64".to_owned(),
+        (None, _) => found.strip_prefix('`')?.strip_suffix('`')?.to_owned(),
+    })
+}
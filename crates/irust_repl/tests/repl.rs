@@ -29,3 +29,1073 @@ fn two_repls_at_the_same_time() {
 
     assert_eq!(a1_thread.join().unwrap() + a2_thread.join().unwrap(), 9)
 }
+
+#[cfg(unix)]
+#[test]
+fn persistent() {
+    let mut repl = Repl::default();
+    repl.set_eval_mode(EvalMode::Persistent).unwrap();
+    repl.insert(r#"let mut v = { println!("side effect"); vec![1] };"#);
+    assert_eq!(repl.eval("v.len()").unwrap().output, "side effect\n1");
+
+    repl.insert("v.push(2);");
+    repl.insert("fn sum(v: &[i32]) -> i32 { v.iter().sum() }");
+    assert_eq!(repl.eval("sum(&v)").unwrap().output, "3");
+    assert_eq!(repl.eval("v").unwrap().output, "[1, 2]");
+
+    // closures can't be stored, they are rerun instead
+    repl.insert("let double = |x: i32| x * 2;");
+    assert_eq!(repl.eval("double(v[1])").unwrap().output, "4");
+    assert_eq!(repl.eval("double(5)").unwrap().output, "10");
+
+    // the type name is private, the statement is rerun instead
+    repl.insert("let it = vec![1, 2, 3].into_iter();");
+    assert_eq!(repl.eval("it.len()").unwrap().output, "3");
+    assert_eq!(repl.eval("it.len()").unwrap().output, "3");
+    // the type name is another type (`for<'a, 'b> fn(&'a u8, &'b u8)`), the value isn't restored
+    repl.insert("let same: for<'a> fn(&'a u8, &'a u8) -> bool = |a, b| a == b;");
+    assert_eq!(repl.eval("same(&1, &1)").unwrap().output, "true");
+    assert_eq!(repl.eval("same(&1, &2)").unwrap().output, "false");
+
+    let result = repl.eval("v.foo()").unwrap();
+    let span = result.diagnostics[0].primary_span().unwrap();
+    assert!(span.origin.unwrap().current);
+    assert_eq!((span.line_start, span.column_start), (1, 3));
+
+    // a panic restarts the host, every statement runs again once
+    assert!(!repl.eval("panic!()").unwrap().status.success());
+    assert_eq!(repl.eval("v").unwrap().output, "side effect\n[1, 2]");
+}
+
+#[test]
+fn diagnostics() {
+    let mut repl = Repl::default();
+    repl.insert("let a = 4;");
+    let result = repl.eval("b + 1").unwrap();
+    assert!(!result.status.success());
+
+    let error = result.diagnostics.iter().find(|d| d.is_error()).unwrap();
+    assert_eq!(error.level, DiagnosticLevel::Error);
+    assert_eq!(error.code.as_deref(), Some("E0425"));
+    assert!(error.primary_span().unwrap().text[0].contains("b + 1"));
+    assert!(
+        error
+            .suggestions
+            .iter()
+            .any(|suggestion| suggestion.replacement == "a")
+    );
+}
+
+#[test]
+fn diagnostics_spans_point_to_inputs() {
+    let mut repl = Repl::default();
+    repl.insert("let a: i32 = 4;"); // In[1]
+    let result = repl.eval("a +\n  a.len()").unwrap(); // In[2]
+    let span = result.diagnostics[0].primary_span().unwrap();
+    assert_eq!(
+        span.origin,
+        Some(SpanOrigin {
+            input: 2,
+            current: true
+        })
+    );
+    assert_eq!((span.line_start, span.column_start), (2, 5));
+    assert_eq!(span.text[0], "  a.len()");
+
+    repl.insert("let b: String = a;"); // In[3]
+    let result = repl.eval("b").unwrap(); // In[4]
+    let span = result.diagnostics[0].primary_span().unwrap();
+    assert_eq!(
+        span.origin,
+        Some(SpanOrigin {
+            input: 3,
+            current: false
+        })
+    );
+    assert_eq!((span.line_start, span.column_start), (1, 17));
+}
+
+#[test]
+fn session() {
+    let mut repl = Repl::default();
+    repl.set_main_result(MainResult::Result).unwrap();
+    repl.insert("let a: u8 = \"4\".parse()?;");
+    let session = repl.session().unwrap();
+    assert_eq!(session.manifest, "");
+    drop(repl);
+
+    let mut repl = Repl::default();
+    repl.restore_session(session.clone()).unwrap();
+    assert_eq!(repl.eval("a + 1").unwrap().output, "5");
+    repl.insert("let b = a;");
+    assert_eq!(repl.session().unwrap().cursor, session.cursor + 1);
+
+    let corrupted = Session {
+        cursor: 0,
+        ..session
+    };
+    assert!(repl.restore_session(corrupted).is_err());
+}
+
+#[test]
+fn export() {
+    let tmp = std::env::temp_dir().join(format!("irust_export_test_{}", std::process::id()));
+    let mut repl = Repl::new(
+        ToolChain::default(),
+        Executor::Sync,
+        MainResult::Result,
+        Edition::E2021,
+        Some(tmp.join("data")),
+    )
+    .unwrap();
+    std::fs::write(
+        tmp.join("data/irust_prelude/src/lib.rs"),
+        "pub fn prelude_fn() -> u8 { 2 }",
+    )
+    .unwrap();
+    repl.insert("pub fn double(x: u8) -> u8 { x * prelude_fn() }");
+    repl.insert("let a: u8 = \"21\".parse()?;");
+    repl.insert("println!(\"{}\", double(a));");
+
+    let bin = tmp.join("bin");
+    repl.export(&bin, false).unwrap();
+    assert!(repl.export(&bin, false).is_err());
+    let output = std::process::Command::new("cargo")
+        .arg("run")
+        .arg("--quiet")
+        .current_dir(&bin)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+
+    let lib = tmp.join("lib");
+    repl.export(&lib, true).unwrap();
+    let lib_rs = std::fs::read_to_string(lib.join("src/lib.rs")).unwrap();
+    assert!(lib_rs.contains("pub fn double") && !lib_rs.contains("fn main"));
+    // The statements of main are not part of the library
+    assert!(!lib_rs.contains("let a"));
+    assert!(
+        std::process::Command::new("cargo")
+            .args(["check", "--quiet"])
+            .current_dir(&lib)
+            .status()
+            .unwrap()
+            .success()
+    );
+
+    std::fs::remove_dir_all(tmp).unwrap();
+}
+
+#[test]
+fn items_at_module_level() {
+    let mut repl = Repl::default();
+    repl.insert("let a = 2;");
+    repl.insert("pub struct Wrapper(u8);");
+    repl.insert(
+        "impl std::fmt::Display for Wrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, \"<{}>\", self.0) }
+}",
+    );
+    repl.insert("let b = twice!(a);");
+    // Like in a crate, a macro can be used by the code of main inserted before it
+    repl.insert("macro_rules! twice { ($e:expr) => { $e * 2 }; }");
+    assert_eq!(
+        repl.eval("Wrapper(a + b).to_string()").unwrap().output,
+        "\"<6>\""
+    );
+    let body = repl.body();
+    assert!(body.find("pub struct Wrapper").unwrap() < body.find("fn main").unwrap());
+    assert!(body.find("fn main").unwrap() < body.find("let a").unwrap());
+
+    // pop removes the last input, item or statement
+    repl.pop();
+    assert!(!repl.body().contains("macro_rules!"));
+    repl.pop();
+    assert!(!repl.body().contains("let b"));
+    assert_eq!(
+        repl.eval("Wrapper(a).to_string()").unwrap().output,
+        "\"<2>\""
+    );
+    assert!(
+        repl.del(
+            &repl
+                .body()
+                .lines()
+                .position(|l| l.starts_with("fn main"))
+                .unwrap()
+                .to_string()
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn redefinitions() {
+    let mut repl = Repl::default();
+    repl.insert("fn foo() -> u8 { 1 }");
+    repl.insert("struct Bar(u8);");
+    repl.insert("impl Bar { fn baz(&self) -> u8 { self.0 } }");
+    repl.insert("impl Bar { fn qux(&self) -> u8 { 10 } }");
+    repl.insert("let a = Bar(foo());");
+    assert_eq!(repl.eval("a.baz() + a.qux()").unwrap().output, "11");
+
+    repl.insert("fn foo() -> u8 {\n    2\n}");
+    repl.insert("impl Bar { fn baz(&self) -> u8 { self.0 * 100 } }");
+    assert_eq!(repl.eval("a.baz() + a.qux()").unwrap().output, "210");
+    assert_eq!(
+        repl.definitions(),
+        ["fn foo", "struct Bar", "impl Bar", "impl Bar"]
+    );
+    // Redefined in place
+    assert!(
+        repl.body()
+            .starts_with("fn foo() -> u8 {\n    2\n}\nstruct Bar(u8);")
+    );
+    // Only the associated items that are defined again are replaced
+    repl.insert("impl Bar {\n    fn double(&self) -> u8 { self.0 * 2 }\n    fn triple(&self) -> u8 { self.0 * 3 }\n}");
+    repl.insert("impl Bar { fn double(&self) -> u8 { 0 } }");
+    assert_eq!(repl.eval("a.double() + a.triple()").unwrap().output, "6");
+
+    assert!(repl.undef("missing").is_err());
+    repl.insert("fn unused() {}");
+    repl.undef("unused").unwrap();
+    assert!(!repl.body().contains("fn unused"));
+    // The impls go with their type
+    repl.undef("Bar").unwrap();
+    assert_eq!(repl.definitions(), ["fn foo"]);
+}
+
+#[test]
+fn undo_redo() {
+    let mut repl = Repl::default();
+    repl.insert("let a = 1;");
+    repl.insert("fn f() -> u8 {\n    2\n}");
+    repl.insert("let b = a + f();");
+    assert_eq!(repl.eval("b").unwrap().output, "3");
+    repl.undo().unwrap();
+    assert!(!repl.body().contains("let b"));
+    repl.undo().unwrap();
+    assert!(!repl.body().contains("fn f"));
+    repl.redo().unwrap();
+    assert_eq!(repl.eval("f() + a").unwrap().output, "3");
+    let commits: Vec<_> = repl
+        .commits()
+        .map(|(commit, applied)| (commit.input_number, applied))
+        .collect();
+    assert_eq!(
+        commits,
+        [(Some(1), true), (Some(2), true), (Some(3), false)]
+    );
+
+    // A new commit replaces the undone ones, and commits that change nothing are dropped
+    repl.insert("let c = 3;");
+    repl.commit(":sync").unwrap();
+    assert!(repl.redo().is_err());
+    assert_eq!(repl.commits().count(), 3);
+
+    // The dependencies are restored
+    let krate = path_crate("irust_undo_test", "pub fn answer() -> u8 { 42 }");
+    let add = ["--path".to_owned(), krate.path()];
+    assert!(repl.add_dep(&add).unwrap().wait().unwrap().success());
+    repl.commit(":add irust_undo_test").unwrap();
+    repl.undo().unwrap();
+    assert!(repl.dependencies().unwrap().is_empty());
+    assert!(repl.body().contains("let c"));
+    repl.redo().unwrap();
+    assert_eq!(repl.eval("irust_undo_test::answer()").unwrap().output, "42");
+
+    repl.undo().unwrap();
+    repl.undo().unwrap();
+    repl.undo().unwrap();
+    repl.undo().unwrap();
+    assert!(repl.undo().is_err());
+    assert!(!repl.body().contains("let a"));
+}
+
+#[test]
+fn history_is_capped() {
+    let mut repl = Repl::default();
+    for n in 0..150 {
+        repl.insert(format!("let a{n} = {n};"));
+    }
+    assert_eq!(repl.commits().count(), 100);
+    for _ in 0..100 {
+        repl.undo().unwrap();
+    }
+    assert!(repl.undo().is_err());
+    assert!(repl.body().contains("let a49 ") && !repl.body().contains("let a50 "));
+    assert_eq!(repl.eval("a49").unwrap().output, "49");
+}
+
+#[test]
+fn undo_executor() {
+    let mut repl = Repl::default();
+    // Polls the future in a loop, like the custom_executors test runtime
+    let executor = CustomExecutor {
+        block_on: Some("run($$)".to_owned()),
+        prelude: Some(
+            "fn run<F: std::future::Future>(f: F) -> F::Output {
+    let mut f = std::pin::pin!(f);
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    loop {
+        if let std::task::Poll::Ready(output) = f.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}"
+            .to_owned(),
+        ),
+        ..Default::default()
+    };
+    repl.set_custom_executors([("rt".to_owned(), executor)].into())
+        .unwrap();
+    repl.insert("let a = 1;");
+    repl.set_executor("rt".parse().unwrap()).unwrap();
+    repl.commit(":executor rt").unwrap();
+    repl.set_main_result(MainResult::Result).unwrap();
+    repl.commit(":main_result Result").unwrap();
+    assert_eq!(repl.eval("async { a }.await").unwrap().output, "1");
+
+    // The main function header matches the restored settings
+    repl.undo().unwrap();
+    repl.undo().unwrap();
+    assert_eq!(repl.executor(), &Executor::Sync);
+    assert_eq!(repl.main_result(), &MainResult::Unit);
+    assert!(!repl.eval("async { a }.await").unwrap().status.success());
+    repl.redo().unwrap();
+    assert_eq!(repl.executor(), &Executor::Custom("rt".to_owned()));
+    assert_eq!(repl.eval("async { a }.await").unwrap().output, "1");
+    repl.undo().unwrap();
+    repl.insert("fn two() -> u8 { 2 }");
+    let body = repl.body();
+    assert!(body.find("fn two").unwrap() < body.find("fn main").unwrap());
+    assert_eq!(repl.eval("a + two()").unwrap().output, "3");
+}
+
+#[test]
+fn bindings() {
+    let mut repl = Repl::default();
+    repl.insert("let a = 1;");
+    repl.insert("let (b, mut c) = (\"b\", vec![1u8]);");
+    repl.insert("if let Some(d) = Some(2) { let e = d; }");
+    repl.insert("struct NoDebug;");
+    repl.insert("let n = NoDebug;");
+    repl.insert("let moved = String::from(\"moved\");");
+    repl.insert("drop(moved);");
+    repl.insert("let a = 2.5;");
+    let bindings = repl.bindings();
+    assert_eq!(bindings, ["b", "c", "n", "moved", "a"]);
+
+    let types = repl.expression_types(&bindings, None).unwrap();
+    assert_eq!(
+        types,
+        [
+            Some("&str".to_owned()),
+            Some("Vec<u8>".to_owned()),
+            Some("NoDebug".to_owned()),
+            Some("String".to_owned()),
+            Some("f64".to_owned()),
+        ]
+    );
+
+    let values = repl.binding_values(&bindings, None).unwrap();
+    assert_eq!(
+        values,
+        [
+            Some("\"b\"".to_owned()),
+            Some("[1]".to_owned()),
+            None,
+            None,
+            Some("2.5".to_owned()),
+        ]
+    );
+
+    // The persistent eval mode shows the values from when their statements ran
+    #[cfg(unix)]
+    {
+        repl.set_eval_mode(EvalMode::Persistent).unwrap();
+        repl.insert("let t = std::time::Instant::now();");
+        let t = repl.eval("t").unwrap().output;
+        let values = repl
+            .binding_values(&["t".to_owned(), "b".to_owned()], None)
+            .unwrap();
+        assert_eq!(values, [Some(t), Some("\"b\"".to_owned())]);
+        repl.set_eval_mode(EvalMode::Rebuild).unwrap();
+    }
+}
+
+#[test]
+fn expression_types() {
+    let mut repl = Repl::default();
+    repl.insert("fn f() -> u8 { 1 }");
+    let expressions = [
+        "vec![1u8]",
+        "std::collections::HashMap::<u8, String>::new()",
+        "{\n    let a = 1;\n    a + 1\n}",
+        "f",
+        "println!()",
+        "1.5",
+        "undefined",
+    ]
+    .map(ToOwned::to_owned);
+    let types = repl.expression_types(&expressions, None).unwrap();
+    assert_eq!(
+        types,
+        [
+            Some("Vec<u8>".to_owned()),
+            Some("HashMap<u8, String>".to_owned()),
+            Some("i32".to_owned()),
+            Some("fn() -> u8 {f}".to_owned()),
+            Some("()".to_owned()),
+            Some("f64".to_owned()),
+            None,
+        ]
+    );
+}
+
+#[test]
+fn errors() {
+    let mut repl = Repl::default();
+    let err = repl.eval("1 + \"a\"").unwrap().into_result().unwrap_err();
+    assert!(matches!(err, ReplError::Compile { ref diagnostics, .. } if diagnostics[0].is_error()));
+
+    let err = repl
+        .eval("panic!(\"boom\")")
+        .unwrap()
+        .into_result()
+        .unwrap_err();
+    let ReplError::Run { status, output } = err else {
+        panic!("expected a run error")
+    };
+    assert_eq!(status.code(), Some(101));
+    assert!(output.contains("boom"));
+
+    assert!(repl.eval("1").unwrap().into_result().is_ok());
+    assert!(matches!(
+        repl.del("0"),
+        Err(ReplError::InvalidLineNumber(_))
+    ));
+}
+
+#[test]
+fn panics() {
+    let mut repl = Repl::default();
+    let result = repl
+        .eval("{ print!(\"hi\"); eprintln!(\"oops\"); panic!(\"boom\") }")
+        .unwrap();
+    assert_eq!(result.stdout, "hi");
+    assert!(result.stderr.starts_with("oops"));
+    assert!(result.run_time.is_some());
+    let panic = result.panic.unwrap();
+    assert_eq!(panic.message, "boom");
+    let location = panic.location.unwrap();
+    assert_eq!((location.line, location.column), (1, 36));
+    assert!(location.origin.unwrap().current);
+
+    let result = repl.eval("1").unwrap();
+    assert_eq!(result.stdout, "1");
+    assert!(result.panic.is_none());
+
+    // A panic in another thread doesn't make the program fail
+    let result = repl
+        .eval("std::thread::spawn(|| panic!()).join().ok();")
+        .unwrap();
+    assert!(result.status.success());
+    assert!(result.stderr.contains("panicked"));
+    assert!(result.panic.is_none());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pty() {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let mut repl = Repl::default();
+    repl.set_run_mode(RunMode::Pty).unwrap();
+    let result = repl
+        .eval("{ use std::io::IsTerminal; std::io::stdout().is_terminal() }")
+        .unwrap();
+    assert_eq!(result.stdout, "true");
+
+    static TYPED: AtomicBool = AtomicBool::new(false);
+    fn type_line(process: &mut std::process::Child) -> Result<(), ReplError> {
+        // Cargo, which runs first, has no stdin
+        if let Some(stdin) = process.stdin.as_mut()
+            && !TYPED.swap(true, Ordering::SeqCst)
+        {
+            stdin.write_all("héllo\r".as_bytes())?;
+        }
+        Ok(())
+    }
+    let result = repl
+        .eval_with_configuration(EvalConfig {
+            input: "{ let mut s = String::new(); std::io::stdin().read_line(&mut s).unwrap(); s }",
+            interactive_function: Some(type_line),
+            output_function: None,
+            progress_function: None,
+            color: false,
+            evaluator: &*DEFAULT_EVALUATOR,
+            compile_mode: CompileMode::Debug,
+        })
+        .unwrap();
+    // The terminal echoes the input
+    assert_eq!(result.stdout, "héllo\n\"héllo\\n\"");
+
+    let result = repl.eval("panic!(\"boom\")").unwrap();
+    assert_eq!(result.panic.unwrap().message, "boom");
+}
+
+#[test]
+fn output_function() {
+    fn eval_streaming(repl: &mut Repl, input: &str) -> (EvalResult, Vec<u8>, Vec<u8>) {
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let result = repl
+            .eval_with_configuration(EvalConfig {
+                input,
+                interactive_function: None,
+                output_function: Some(&mut |chunk| match chunk {
+                    OutputChunk::Stdout(out) => stdout.extend_from_slice(out),
+                    OutputChunk::Stderr(err) => stderr.extend_from_slice(err),
+                }),
+                progress_function: None,
+                color: false,
+                evaluator: &*DEFAULT_EVALUATOR,
+                compile_mode: CompileMode::Debug,
+            })
+            .unwrap();
+        (result, stdout, stderr)
+    }
+
+    let mut repl = Repl::default();
+    let input = "for i in 0..3 { println!(\"{i}\"); eprintln!(\"e{i}\"); }";
+    let (result, stdout, stderr) = eval_streaming(&mut repl, input);
+    assert_eq!(result.stdout, "0\n1\n2\n");
+    assert_eq!(stdout, result.stdout.as_bytes());
+    assert_eq!(stderr, result.stderr.as_bytes());
+
+    let (result, stdout, _) = eval_streaming(&mut repl, "{ print!(\"a\"); 5 }");
+    assert_eq!(result.stdout, "a5");
+    assert_eq!(stdout, b"a5");
+
+    #[cfg(unix)]
+    {
+        repl.set_eval_mode(EvalMode::Persistent).unwrap();
+        let (result, stdout, stderr) = eval_streaming(&mut repl, input);
+        assert_eq!(result.stdout, "0\n1\n2\n");
+        assert_eq!(stdout, result.stdout.as_bytes());
+        assert_eq!(stderr, b"e0\ne1\ne2\n");
+    }
+}
+
+#[test]
+fn build_progress_and_cancellation() {
+    let mut repl = Repl::default();
+    let mut updates = vec![];
+    let result = repl
+        .eval_with_configuration(EvalConfig {
+            input: "1",
+            interactive_function: None,
+            output_function: None,
+            progress_function: Some(&mut |progress| updates.push(progress.clone())),
+            color: false,
+            evaluator: &*DEFAULT_EVALUATOR,
+            compile_mode: CompileMode::Debug,
+        })
+        .unwrap();
+    assert_eq!(result.output, "1");
+    assert!(updates.iter().any(|progress| progress.done == 1));
+
+    fn cancel(process: &mut std::process::Child) -> Result<(), ReplError> {
+        process.kill()?;
+        Err(ReplError::Cancelled)
+    }
+    let err = repl
+        .eval_with_configuration(EvalConfig {
+            input: "2",
+            interactive_function: Some(cancel),
+            output_function: None,
+            progress_function: None,
+            color: false,
+            evaluator: &*DEFAULT_EVALUATOR,
+            compile_mode: CompileMode::Debug,
+        })
+        .unwrap_err();
+    assert!(matches!(err, ReplError::Cancelled));
+    assert_eq!(repl.eval("3").unwrap().output, "3");
+}
+
+#[test]
+fn limits() {
+    let mut repl = Repl::default();
+    let exceeded = |repl: &mut Repl, limits: &str, input: &str| {
+        repl.set_limits(limits.parse().unwrap()).unwrap();
+        repl.eval(input).unwrap().limit_exceeded
+    };
+    assert_eq!(
+        exceeded(&mut repl, "timeout=1s", "loop {}"),
+        Some(Limit::Timeout)
+    );
+    assert_eq!(
+        exceeded(&mut repl, "output=1K", "loop { println!(\"spam\") }"),
+        Some(Limit::Output)
+    );
+    #[cfg(target_os = "linux")]
+    {
+        assert_eq!(
+            exceeded(&mut repl, "cpu_time=1s", "loop {}"),
+            Some(Limit::CpuTime)
+        );
+        assert_eq!(
+            exceeded(&mut repl, "memory=256M", "vec![1u8; 1 << 30].len()"),
+            Some(Limit::Memory)
+        );
+    }
+    assert_eq!(exceeded(&mut repl, "timeout=10s", "1"), None);
+
+    repl.set_limits("timeout=1s".parse().unwrap()).unwrap();
+    let err = repl.eval("loop {}").unwrap().into_result().unwrap_err();
+    assert!(matches!(
+        err,
+        ReplError::LimitExceeded {
+            limit: Limit::Timeout,
+            ..
+        }
+    ));
+
+    #[cfg(unix)]
+    {
+        repl.set_eval_mode(EvalMode::Persistent).unwrap();
+        repl.insert("let a = 1;");
+        assert_eq!(
+            exceeded(&mut repl, "timeout=1s", "loop {}"),
+            Some(Limit::Timeout)
+        );
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            exceeded(&mut repl, "cpu_time=1s", "loop {}"),
+            Some(Limit::CpuTime)
+        );
+        // The host was restarted
+        assert_eq!(exceeded(&mut repl, "none", "a"), None);
+    }
+}
+
+#[test]
+#[ignore = "needs rustup"]
+fn toolchains() {
+    let installed = ToolChain::installed().unwrap();
+    let mut repl = Repl::default();
+    assert!(
+        repl.set_toolchain("0.0.1-not-installed".parse().unwrap())
+            .is_err()
+    );
+    assert_eq!(repl.toolchain(), ToolChain::Default);
+
+    let toolchain: ToolChain = installed[0].parse().unwrap();
+    repl.set_toolchain(toolchain.clone()).unwrap();
+    assert_eq!(repl.toolchain(), toolchain);
+    assert_eq!(repl.eval("1 + 1").unwrap().stdout, "2");
+}
+
+#[test]
+fn compare() {
+    let mut repl = Repl::default();
+    repl.insert("let a = [1u8];");
+    let targets: Vec<CompareTarget> = ["@2018", "@2021", "0.0.1-not-installed"]
+        .iter()
+        .map(|target| target.parse().unwrap())
+        .collect();
+    let comparisons = repl.compare(
+        &targets,
+        EvalConfig {
+            input: "std::any::type_name_of_val(&a.into_iter().next().unwrap())",
+            interactive_function: None,
+            output_function: None,
+            progress_function: None,
+            color: false,
+            evaluator: &*DEFAULT_EVALUATOR,
+            compile_mode: CompileMode::Debug,
+        },
+    );
+    assert_eq!(comparisons[0].target.to_string(), "default@2018");
+    assert_eq!(comparisons[0].result.as_ref().unwrap().stdout, "\"&u8\"");
+    assert_eq!(comparisons[1].result.as_ref().unwrap().stdout, "\"u8\"");
+    assert!(comparisons[2].result.is_err());
+    // The repl itself is unchanged
+    assert_eq!(repl.eval("a.len()").unwrap().stdout, "1");
+}
+
+#[test]
+fn edition() {
+    let mut repl = Repl::default();
+    assert!(
+        repl.set_edition(Edition::E2015, None, None)
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(repl.edition(), Edition::E2015);
+    // `async` is a keyword since 2018
+    repl.insert("let async = 1;");
+    assert_eq!(repl.eval("async").unwrap().stdout, "1");
+
+    let errors = repl.set_edition(Edition::E2018, None, None).unwrap();
+    assert!(!errors.is_empty());
+    // The edition is changed anyway
+    assert_eq!(repl.edition(), Edition::E2018);
+    assert!(!repl.eval("1").unwrap().status.success());
+
+    assert!(
+        repl.set_edition(Edition::E2015, None, None)
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(repl.eval("async + 1").unwrap().stdout, "2");
+}
+
+#[test]
+fn compile_profiles() {
+    let mut repl = Repl::default();
+    let profiles = std::collections::BTreeMap::from([(
+        "small".to_owned(),
+        CompileProfile {
+            opt_level: Some("s".to_owned()),
+            debug: Some(false),
+            ..Default::default()
+        },
+    )]);
+    repl.set_compile_profiles(profiles).unwrap();
+    repl.insert("let a = 2;");
+    let mut eval = |compile_mode: &str| {
+        repl.eval_with_configuration(EvalConfig {
+            input: "a * 21",
+            interactive_function: None,
+            output_function: None,
+            progress_function: None,
+            color: false,
+            evaluator: &*DEFAULT_EVALUATOR,
+            compile_mode: compile_mode.parse().unwrap(),
+        })
+    };
+    assert_eq!(eval("small").unwrap().stdout, "42");
+    assert_eq!(eval("debug").unwrap().stdout, "42");
+    assert!(eval("missing").is_err());
+
+    let reserved = std::collections::BTreeMap::from([("release".to_owned(), Default::default())]);
+    assert!(repl.set_compile_profiles(reserved).is_err());
+}
+
+#[test]
+fn dependencies() {
+    let krate = path_crate("irust_deps_test", "pub fn answer() -> u8 { 42 }")
+        .with_features("default = [\"a\"]\na = []\nb = [\"a\"]");
+
+    let mut repl = Repl::default();
+    assert!(
+        repl.add_dep(&["--path".to_owned(), krate.path()])
+            .unwrap()
+            .wait()
+            .unwrap()
+            .success()
+    );
+    let deps = repl.dependencies().unwrap();
+    assert_eq!(deps.len(), 1);
+    assert_eq!(deps[0].name, "irust_deps_test");
+    assert_eq!(deps[0].version.as_deref(), Some("0.1.0"));
+    assert_eq!(deps[0].features, ["a", "default"]);
+    assert!(deps[0].path.is_some());
+    assert_eq!(repl.eval("irust_deps_test::answer()").unwrap().stdout, "42");
+
+    let features = repl.features("irust_deps_test").unwrap();
+    let b = features.iter().find(|feature| feature.name == "b").unwrap();
+    assert!(!b.enabled);
+    assert_eq!(b.enables, ["a"]);
+    assert!(repl.features("missing").is_err());
+
+    assert!(
+        repl.remove_dep(&["irust_deps_test".to_owned()])
+            .unwrap()
+            .wait()
+            .unwrap()
+            .success()
+    );
+    assert!(repl.dependencies().unwrap().is_empty());
+}
+
+#[test]
+fn vendored_dependencies() {
+    let krate = path_crate("irust_vendored", "pub fn nine() -> u8 { 9 }");
+
+    let mut repl = Repl::default();
+    assert!(
+        repl.set_crate_source(CrateSource::Vendored(krate.vendor_dir().join("missing")))
+            .is_err()
+    );
+    repl.set_offline(true).unwrap();
+    repl.set_crate_source(CrateSource::Vendored(krate.vendor_dir()))
+        .unwrap();
+    // The settings survive a reset
+    repl.reset().unwrap();
+    assert!(repl.offline());
+
+    let output = repl
+        .add_dep(&["irust_vendored".to_owned()])
+        .unwrap()
+        .wait_with_output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(repl.eval("irust_vendored::nine()").unwrap().stdout, "9");
+
+    let output = repl
+        .add_dep(&["irust_not_vendored".to_owned()])
+        .unwrap()
+        .wait_with_output()
+        .unwrap();
+    assert!(!output.status.success());
+    let error = repl.dependency_error(&String::from_utf8_lossy(&output.stderr));
+    assert!(error.starts_with("`irust_not_vendored` is not available in the vendored crates"));
+}
+
+#[test]
+fn custom_executors() {
+    // A minimal runtime, futures that are never woken up again are polled in a loop
+    let krate = path_crate(
+        "irust_rt",
+        "pub fn run<F: std::future::Future>(f: F) -> F::Output {
+            let mut f = std::pin::pin!(f);
+            let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+            loop {
+                if let std::task::Poll::Ready(output) = f.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+        }",
+    );
+
+    let mut repl = Repl::default();
+    let executor = CustomExecutor {
+        block_on: Some("run($$)".to_owned()),
+        dependency: vec!["irust_rt".to_owned(), "--path".to_owned(), krate.path()],
+        prelude: Some("use irust_rt::run;".to_owned()),
+        ..Default::default()
+    };
+    assert!(
+        repl.set_custom_executors([("tokio".to_owned(), executor.clone())].into())
+            .is_err()
+    );
+    repl.set_custom_executors([("rt".to_owned(), executor)].into())
+        .unwrap();
+    assert!(
+        repl.set_executor(Executor::Custom("missing".to_owned()))
+            .is_err()
+    );
+
+    repl.set_executor("rt".parse().unwrap()).unwrap();
+    repl.insert("async fn four() -> u8 { 4 }");
+    assert_eq!(repl.eval("four().await").unwrap().output, "4");
+    // The executor stays the current one until it's replaced
+    assert!(repl.set_custom_executors(Default::default()).is_err());
+    #[cfg(unix)]
+    {
+        repl.set_eval_mode(EvalMode::Persistent).unwrap();
+        assert_eq!(repl.eval("four().await + 1").unwrap().output, "5");
+        repl.set_eval_mode(EvalMode::Rebuild).unwrap();
+    }
+
+    // Switching back removes the dependency
+    repl.set_executor(Executor::Sync).unwrap();
+    repl.pop();
+    assert!(repl.dependencies().unwrap().is_empty());
+    assert_eq!(repl.eval("1 + 1").unwrap().output, "2");
+}
+
+#[test]
+fn main_results() {
+    let mut repl = Repl::default();
+    repl.set_main_result(MainResult::Option).unwrap();
+    repl.insert("let v = vec![1, 2];");
+    assert_eq!(repl.eval("v.first()? + 1").unwrap().output, "2");
+    let result = repl.eval("v.get(5)?").unwrap();
+    assert!(!result.status.success());
+    assert!(result.panic.is_none());
+    assert_eq!(result.returned_error.as_deref(), Some("None"));
+    #[cfg(unix)]
+    {
+        repl.set_eval_mode(EvalMode::Persistent).unwrap();
+        assert_eq!(
+            repl.eval("v.get(5)?").unwrap().returned_error.as_deref(),
+            Some("None")
+        );
+        repl.set_eval_mode(EvalMode::Rebuild).unwrap();
+    }
+
+    // An error type with its own report, like anyhow's
+    let krate = path_crate(
+        "irust_report",
+        "pub struct Report(String);
+        impl<E: std::fmt::Display> From<E> for Report {
+            fn from(e: E) -> Self { Report(e.to_string()) }
+        }
+        impl std::fmt::Debug for Report {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, \"{}\\n\\nCaused by: the input\", self.0)
+            }
+        }",
+    );
+    let report = CustomMainResult {
+        ttype: "Result<(), irust_report::Report>".to_owned(),
+        success: "Ok(())".to_owned(),
+        dependency: vec!["irust_report".to_owned(), "--path".to_owned(), krate.path()],
+    };
+    assert!(
+        repl.set_custom_main_results([("option".to_owned(), report.clone())].into())
+            .is_err()
+    );
+    repl.set_custom_main_results([("report".to_owned(), report)].into())
+        .unwrap();
+    repl.set_main_result("report".parse().unwrap()).unwrap();
+    repl.insert("let n: u8 = \"300\".parse()?;");
+    let result = repl.eval("n").unwrap();
+    assert_eq!(
+        result.returned_error.as_deref(),
+        Some("number too large to fit in target type\n\nCaused by: the input")
+    );
+
+    // Switching back removes the dependency
+    repl.pop();
+    repl.set_main_result(MainResult::Unit).unwrap();
+    assert!(repl.dependencies().unwrap().is_empty());
+    assert_eq!(repl.eval("1 + 1").unwrap().output, "2");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+#[ignore = "needs unprivileged user namespaces"]
+fn sandbox() {
+    let mut repl = Repl::default();
+    repl.set_sandbox(true).unwrap();
+    let eval = |repl: &mut Repl, input: &str| repl.eval(input).unwrap().stdout;
+
+    let outside = std::env::temp_dir().join("irust_sandbox_test");
+    assert_eq!(
+        eval(
+            &mut repl,
+            &format!("std::fs::write({outside:?}, \"\").unwrap_err().kind()")
+        ),
+        "ReadOnlyFilesystem"
+    );
+    assert!(!outside.exists());
+    assert_eq!(
+        eval(
+            &mut repl,
+            "std::fs::write(std::env::temp_dir().join(\"a\"), \"1\").is_ok()"
+        ),
+        "true"
+    );
+    assert_eq!(
+        eval(
+            &mut repl,
+            "std::net::TcpStream::connect(\"1.1.1.1:80\").is_err()"
+        ),
+        "true"
+    );
+
+    // $HOME is hidden
+    let private = std::path::Path::new(&std::env::var("HOME").unwrap()).join("irust_sandbox_test");
+    std::fs::write(&private, "").unwrap();
+    assert_eq!(
+        eval(
+            &mut repl,
+            &format!("std::path::Path::new({private:?}).exists()")
+        ),
+        "false"
+    );
+    std::fs::remove_file(private).unwrap();
+    // A system call outside the allowlist fails with ENOSYS (io_uring_setup)
+    assert_eq!(
+        eval(
+            &mut repl,
+            "unsafe extern \"C\" { fn syscall(n: i64, ...) -> i64; } unsafe { syscall(425, 1, 0) }; std::io::Error::last_os_error().raw_os_error()"
+        ),
+        "Some(38)"
+    );
+
+    let forbidden = "unsafe extern \"C\" { fn unshare(flags: i32) -> i32; } eprint!(\"before\"); unsafe { unshare(0x10000000) }";
+    let err = repl.eval(forbidden).unwrap_err();
+    assert!(matches!(err, ReplError::Sandbox(_)));
+    assert!(err.to_string().contains("forbidden system call\nbefore"));
+
+    repl.set_eval_mode(EvalMode::Persistent).unwrap();
+    repl.insert("let a = 1;");
+    assert!(matches!(
+        repl.eval(forbidden).unwrap_err(),
+        ReplError::Sandbox(_)
+    ));
+    // The host was restarted
+    assert_eq!(eval(&mut repl, "a"), "1");
+
+    repl.set_sandbox(false).unwrap();
+    assert_eq!(
+        eval(
+            &mut repl,
+            &format!("std::fs::write({outside:?}, \"\").is_ok()")
+        ),
+        "true"
+    );
+    std::fs::remove_file(outside).unwrap();
+}
+
+/// A library crate in its own temp directory, removed when dropped
+///
+/// It's laid out like a vendored crate (`<name>-0.1.0` with a `.cargo-checksum.json`), so it can be
+/// used as a path dependency or from a vendor directory
+struct TempCrate {
+    vendor_dir: std::path::PathBuf,
+    dir: std::path::PathBuf,
+}
+
+/// The crate `name` 0.1.0 with `lib_src` as its `src/lib.rs`
+fn path_crate(name: &str, lib_src: &str) -> TempCrate {
+    let vendor_dir = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+    let dir = vendor_dir.join(format!("{name}-0.1.0"));
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n"),
+    )
+    .unwrap();
+    std::fs::write(dir.join("src/lib.rs"), lib_src).unwrap();
+    std::fs::write(
+        dir.join(".cargo-checksum.json"),
+        r#"{"files":{},"package":null}"#,
+    )
+    .unwrap();
+    TempCrate { vendor_dir, dir }
+}
+
+impl TempCrate {
+    /// Add a `[features]` table to the manifest
+    fn with_features(self, features: &str) -> Self {
+        let manifest = self.dir.join("Cargo.toml");
+        let mut toml = std::fs::read_to_string(&manifest).unwrap();
+        toml.push_str(&format!("\n[features]\n{features}\n"));
+        std::fs::write(manifest, toml).unwrap();
+        self
+    }
+
+    /// The `--path` of `cargo add`
+    fn path(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    /// A vendor directory that only has this crate, see [CrateSource::Vendored]
+    fn vendor_dir(&self) -> std::path::PathBuf {
+        self.vendor_dir.clone()
+    }
+}
+
+impl Drop for TempCrate {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.vendor_dir);
+    }
+}
@@ -33,13 +33,11 @@ fn main() -> Result<()> {
                 KeyEvent {
                     code: KeyCode::Backspace,
                     ..
-                } => {
-                    if !buffer.is_at_start() {
-                        buffer.move_backward();
-                        printer.cursor.move_left();
-                        buffer.remove_current_char();
-                        printer.print_input(&default_process_fn, &buffer)?;
-                    }
+                } if !buffer.is_at_start() => {
+                    buffer.move_backward();
+                    printer.cursor.move_left();
+                    buffer.remove_current_char();
+                    printer.print_input(&default_process_fn, &buffer)?;
                 }
                 KeyEvent {
                     code: KeyCode::Enter,